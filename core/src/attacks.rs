@@ -0,0 +1,401 @@
+use std::sync::OnceLock;
+
+use crate::{
+    grid::Grid,
+    markers::{BlackMarker, WhiteMarker},
+    movegen,
+    player::Player,
+};
+
+/// (row, column) steps. rows increase towards black, columns towards the a-file.
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (2, 1),
+    (2, -1),
+    (-2, 1),
+    (-2, -1),
+    (1, 2),
+    (1, -2),
+    (-1, 2),
+    (-1, -2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// how a slider's relevant occupancy is turned into a table index
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SliderIndexing {
+    Magic,
+    Pext,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+struct SliderTable {
+    magics: [Magic; 64],
+    attacks: Vec<Grid>,
+}
+
+impl SliderTable {
+    #[inline(always)]
+    fn index(&self, pos: u8, occupied: u64, indexing: SliderIndexing) -> usize {
+        let magic = &self.magics[pos as usize];
+        let index = match indexing {
+            SliderIndexing::Magic => {
+                ((occupied & magic.mask).wrapping_mul(magic.magic) >> magic.shift) as usize
+            }
+            SliderIndexing::Pext => pext(occupied, magic.mask) as usize,
+        };
+        magic.offset + index
+    }
+
+    #[inline(always)]
+    fn attacks(&self, pos: u8, occupied: Grid, indexing: SliderIndexing) -> Grid {
+        self.attacks[self.index(pos, occupied.into(), indexing)]
+    }
+
+    /// fancy magics: every square gets its own table, sized by the number of relevant
+    /// occupancy bits. magics are found with a fixed seed, so the tables are identical
+    /// between runs.
+    fn new(directions: &[(i8, i8); 4], indexing: SliderIndexing) -> Self {
+        let mut magics = [Magic::default(); 64];
+        let mut attacks = Vec::new();
+        let mut rng = SparseRng::new(0x2545_f491_4f6c_dd1d);
+
+        let mut occupancies = Vec::with_capacity(4096);
+        let mut references = Vec::with_capacity(4096);
+        let mut epochs = vec![0u32; 4096];
+        let mut used = vec![Grid::EMPTY; 4096];
+        let mut epoch = 0;
+
+        for pos in 0..64u8 {
+            let mask = relevant_mask(pos, directions);
+            let bits = mask.count_ones();
+            let size = 1usize << bits;
+
+            // enumerate every subset of the mask (carry-rippler)
+            occupancies.clear();
+            references.clear();
+            let mut subset = 0u64;
+            loop {
+                occupancies.push(subset);
+                references.push(sliding_attacks(pos, subset, directions));
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+
+            let offset = attacks.len();
+            attacks.resize(offset + size, Grid::EMPTY);
+            let mut entry = Magic {
+                mask,
+                magic: 0,
+                shift: 64 - bits,
+                offset,
+            };
+
+            if indexing == SliderIndexing::Pext {
+                for (&occupied, &reference) in occupancies.iter().zip(references.iter()) {
+                    attacks[offset + pext(occupied, mask) as usize] = reference;
+                }
+                magics[pos as usize] = entry;
+                continue;
+            }
+
+            'search: loop {
+                let magic = rng.sparse();
+                // cheap rejection of candidates that cannot spread the high bits
+                if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                    continue;
+                }
+                entry.magic = magic;
+                epoch += 1;
+                for (&occupied, &reference) in occupancies.iter().zip(references.iter()) {
+                    let index = ((occupied & mask).wrapping_mul(magic) >> entry.shift) as usize;
+                    if epochs[index] < epoch {
+                        epochs[index] = epoch;
+                        used[index] = reference;
+                    } else if used[index] != reference {
+                        continue 'search;
+                    }
+                }
+                break;
+            }
+
+            attacks[offset..offset + size].copy_from_slice(&used[..size]);
+            magics[pos as usize] = entry;
+        }
+
+        Self { magics, attacks }
+    }
+}
+
+struct AttackTables {
+    indexing: SliderIndexing,
+    knight: [Grid; 64],
+    king: [Grid; 64],
+    /// [player][pos], the squares a pawn of `player` on `pos` attacks
+    pawn: [[Grid; 64]; 2],
+    rook: SliderTable,
+    bishop: SliderTable,
+}
+
+impl AttackTables {
+    fn new() -> Self {
+        let indexing = if has_pext() {
+            SliderIndexing::Pext
+        } else {
+            SliderIndexing::Magic
+        };
+        Self::with_indexing(indexing)
+    }
+
+    fn with_indexing(indexing: SliderIndexing) -> Self {
+        let mut knight = [Grid::EMPTY; 64];
+        let mut king = [Grid::EMPTY; 64];
+        let mut pawn = [[Grid::EMPTY; 64]; 2];
+        for pos in 0..64u8 {
+            knight[pos as usize] = step_attacks(pos, &KNIGHT_STEPS);
+            king[pos as usize] = step_attacks(pos, &KING_STEPS);
+            pawn[Player::White as usize][pos as usize] = step_attacks(pos, &[(1, 1), (1, -1)]);
+            pawn[Player::Black as usize][pos as usize] = step_attacks(pos, &[(-1, 1), (-1, -1)]);
+        }
+
+        Self {
+            indexing,
+            knight,
+            king,
+            pawn,
+            rook: SliderTable::new(&ROOK_DIRECTIONS, indexing),
+            bishop: SliderTable::new(&BISHOP_DIRECTIONS, indexing),
+        }
+    }
+}
+
+#[inline(always)]
+fn tables() -> &'static AttackTables {
+    static TABLES: OnceLock<AttackTables> = OnceLock::new();
+    TABLES.get_or_init(AttackTables::new)
+}
+
+/// builds the tables up front, so the first search does not pay for it
+pub fn init() {
+    tables();
+}
+
+/// the indexing scheme picked at startup: PEXT if the cpu supports BMI2, magics otherwise
+pub fn slider_indexing() -> SliderIndexing {
+    tables().indexing
+}
+
+/// squares attacked by a rook on `pos`, up to and including the first blocker of either
+/// colour
+#[inline(always)]
+pub fn rook_attacks(pos: u8, occupied: Grid) -> Grid {
+    let tables = tables();
+    tables.rook.attacks(pos, occupied, tables.indexing)
+}
+
+#[inline(always)]
+pub fn bishop_attacks(pos: u8, occupied: Grid) -> Grid {
+    let tables = tables();
+    tables.bishop.attacks(pos, occupied, tables.indexing)
+}
+
+#[inline(always)]
+pub fn queen_attacks(pos: u8, occupied: Grid) -> Grid {
+    rook_attacks(pos, occupied) | bishop_attacks(pos, occupied)
+}
+
+#[inline(always)]
+pub fn knight_attacks(pos: u8) -> Grid {
+    tables().knight[pos as usize]
+}
+
+#[inline(always)]
+pub fn king_attacks(pos: u8) -> Grid {
+    tables().king[pos as usize]
+}
+
+/// squares attacked by a pawn of `player` on `pos`. looking up the opposite player gives
+/// the squares a pawn of `player` would have to stand on to attack `pos`.
+#[inline(always)]
+pub fn pawn_attacks(player: Player, pos: u8) -> Grid {
+    tables().pawn[player as usize][pos as usize]
+}
+
+/// compares the lookup tables against the ray fills in `movegen`, for every square and
+/// `samples` random occupancies per square. both indexing schemes are checked when the cpu
+/// supports PEXT. returns a description of the first mismatch.
+pub fn verify_attack_tables(samples: usize) -> Result<(), String> {
+    let mut schemes = vec![SliderIndexing::Magic];
+    if has_pext() {
+        schemes.push(SliderIndexing::Pext);
+    }
+
+    let mut rng = SparseRng::new(0x9e37_79b9_7f4a_7c15);
+    for indexing in schemes {
+        let tables = AttackTables::with_indexing(indexing);
+        for pos in 0..64u8 {
+            let grid = Grid::from_pos(pos);
+            for sample in 0..samples {
+                // sparse and dense boards, with the slider's own square always occupied
+                let occupied = Grid::from_u64(match sample % 3 {
+                    0 => rng.sparse(),
+                    1 => rng.next(),
+                    _ => rng.next() | rng.next(),
+                }) | grid;
+                let empty = !occupied;
+                // treating every blocker as capturable makes the rays include them
+                let expected_rook =
+                    movegen::Rays::ray_horizontal_vertical_occluded_with_captures_and_non_captures(
+                        grid, empty, occupied,
+                    ) & !grid;
+                let expected_bishop =
+                    movegen::Rays::ray_diagonal_occluded_with_captures_and_non_captures(
+                        grid, empty, occupied,
+                    ) & !grid;
+
+                let rook = tables.rook.attacks(pos, occupied, indexing);
+                let bishop = tables.bishop.attacks(pos, occupied, indexing);
+                if rook != expected_rook || bishop != expected_bishop {
+                    return Err(format!(
+                        "{:?} slider attacks differ from the ray fills on square {} with occupancy {:#018x}",
+                        indexing,
+                        pos,
+                        Into::<u64>::into(occupied),
+                    ));
+                }
+            }
+
+            let knight = movegen::Knight::moves(grid, Grid::EMPTY);
+            let king = movegen::King::regular_moves(grid, Grid::EMPTY);
+            let white_pawn = movegen::Pawn::squares_attacked::<WhiteMarker>(grid);
+            let black_pawn = movegen::Pawn::squares_attacked::<BlackMarker>(grid);
+            if tables.knight[pos as usize] != knight
+                || tables.king[pos as usize] != king
+                || tables.pawn[Player::White as usize][pos as usize] != white_pawn
+                || tables.pawn[Player::Black as usize][pos as usize] != black_pawn
+            {
+                return Err(format!(
+                    "leaper attacks differ from movegen on square {}",
+                    pos
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[inline(always)]
+fn offset(pos: u8, row_step: i8, col_step: i8) -> Option<u8> {
+    let row = (pos >> 3) as i8 + row_step;
+    let col = (pos & 0b111) as i8 + col_step;
+    if (0..8).contains(&row) && (0..8).contains(&col) {
+        Some((row * 8 + col) as u8)
+    } else {
+        None
+    }
+}
+
+fn step_attacks(pos: u8, steps: &[(i8, i8)]) -> Grid {
+    steps
+        .iter()
+        .filter_map(|&(row_step, col_step)| offset(pos, row_step, col_step))
+        .fold(Grid::EMPTY, |grid, pos| grid | Grid::from_pos(pos))
+}
+
+/// slow reference used to fill the tables
+fn sliding_attacks(pos: u8, occupied: u64, directions: &[(i8, i8); 4]) -> Grid {
+    let mut attacks = 0u64;
+    for &(row_step, col_step) in directions {
+        let mut cur = pos;
+        while let Some(next) = offset(cur, row_step, col_step) {
+            attacks |= 1 << next;
+            if occupied & (1 << next) != 0 {
+                break;
+            }
+            cur = next;
+        }
+    }
+    Grid::from_u64(attacks)
+}
+
+/// squares whose occupancy can change the attack set: the rays without their last square
+fn relevant_mask(pos: u8, directions: &[(i8, i8); 4]) -> u64 {
+    let mut mask = 0u64;
+    for &(row_step, col_step) in directions {
+        let mut cur = pos;
+        while let Some(next) = offset(cur, row_step, col_step) {
+            if offset(next, row_step, col_step).is_none() {
+                break;
+            }
+            mask |= 1 << next;
+            cur = next;
+        }
+    }
+    mask
+}
+
+#[cfg(target_arch = "x86_64")]
+fn has_pext() -> bool {
+    is_x86_feature_detected!("bmi2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn has_pext() -> bool {
+    false
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn pext(value: u64, mask: u64) -> u64 {
+    #[target_feature(enable = "bmi2")]
+    unsafe fn pext_bmi2(value: u64, mask: u64) -> u64 {
+        std::arch::x86_64::_pext_u64(value, mask)
+    }
+    // SAFETY: only reached when `has_pext` chose PEXT indexing
+    unsafe { pext_bmi2(value, mask) }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+#[inline(always)]
+fn pext(_value: u64, _mask: u64) -> u64 {
+    unreachable!("PEXT indexing is only selected on x86_64")
+}
+
+/// xorshift64*, with a sparse variant for magic candidates
+struct SparseRng(u64);
+
+impl SparseRng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt::Display,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Sub, SubAssign},
+};
+
+use crate::{grid::Grid, square::Square};
+
+/// a set of squares.
+///
+/// `from_u64` and `to_u64` use the same numbering as `Square::index`, so a1 is bit 0 and h8
+/// is bit 63.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Bitboard(u64);
+
+impl Bitboard {
+    pub const EMPTY: Self = Self(0);
+    pub const FULL: Self = Self(u64::MAX);
+
+    /// bit `i` is the square with index `i`
+    pub const fn from_u64(bits: u64) -> Self {
+        Self(Self::flip_files(bits))
+    }
+
+    /// bit `i` is the square with index `i`
+    pub const fn to_u64(self) -> u64 {
+        Self::flip_files(self.0)
+    }
+
+    // mirrors every rank, converting between the public and the internal numbering
+    const fn flip_files(bits: u64) -> u64 {
+        bits.reverse_bits().swap_bytes()
+    }
+
+    #[inline(always)]
+    pub(crate) fn from_grid(grid: Grid) -> Self {
+        Self(grid.into())
+    }
+
+    pub fn contains(self, square: Square) -> bool {
+        self.0 >> square.pos() & 1 == 1
+    }
+
+    pub fn insert(&mut self, square: Square) {
+        self.0 |= 1 << square.pos();
+    }
+
+    pub fn remove(&mut self, square: Square) {
+        self.0 &= !(1 << square.pos());
+    }
+
+    pub fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// the square if exactly one is set
+    pub fn single_square(self) -> Option<Square> {
+        if self.len() == 1 {
+            Some(Square::from_pos_unchecked(self.0.trailing_zeros() as u8))
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(self) -> BitboardIterator {
+        BitboardIterator { bits: self.to_u64() }
+    }
+}
+
+impl From<Square> for Bitboard {
+    fn from(square: Square) -> Self {
+        Self(1 << square.pos())
+    }
+}
+
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<T: IntoIterator<Item = Square>>(iter: T) -> Self {
+        let mut res = Self::EMPTY;
+        for square in iter {
+            res.insert(square);
+        }
+        res
+    }
+}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+
+    type IntoIter = BitboardIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// yields squares in `Square::index` order
+pub struct BitboardIterator {
+    bits: u64,
+}
+
+impl Iterator for BitboardIterator {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bits == 0 {
+            None
+        } else {
+            let index = self.bits.trailing_zeros() as u8;
+            self.bits &= self.bits - 1;
+            Square::from_index(index)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.bits.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for BitboardIterator {}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Self(self.0 ^ rhs.0)
+    }
+}
+
+/// set difference
+impl Sub for Bitboard {
+    type Output = Bitboard;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 & !rhs.0)
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = *self | rhs;
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        *self = *self & rhs;
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        *self = *self ^ rhs;
+    }
+}
+
+impl SubAssign for Bitboard {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+/// 8x8 diagram with the eighth rank first, `x` for set squares
+impl Display for Bitboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                let square = Square::new(file, rank).unwrap();
+                f.write_str(if self.contains(square) { "x" } else { "." })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::{Debug, Display};
+
+use crate::{player::Player, square::Square, variant::Variant, GameState};
+
+/// a board drawn as text, followed by the side to move, castling rights, en passant
+/// square, fen and hash. see `GameState::diagram`.
+pub struct Diagram<'a> {
+    state: &'a GameState,
+    unicode: bool,
+    flipped: bool,
+}
+
+impl GameState {
+    /// `unicode` draws the pieces as chess glyphs instead of fen letters, `flipped` shows
+    /// the board from black's side
+    pub fn diagram(&self, unicode: bool, flipped: bool) -> Diagram<'_> {
+        Diagram {
+            state: self,
+            unicode,
+            flipped,
+        }
+    }
+}
+
+impl Display for Diagram<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const LINE: &str = "  +---+---+---+---+---+---+---+---+";
+        let order = |i: u8| if self.flipped { i } else { 7 - i };
+
+        writeln!(f, "{}", LINE)?;
+        for rank in (0..8).map(order) {
+            write!(f, "{} |", rank + 1)?;
+            for file in (0..8).map(|i| 7 - order(i)) {
+                let square = Square::new(file, rank).unwrap();
+                let c = match self.state.piece_at(square) {
+                    Some((player, piece)) if self.unicode => piece.to_unicode(player),
+                    Some((player, piece)) => piece.to_char(player),
+                    None => ' ',
+                };
+                write!(f, " {} |", c)?;
+            }
+            writeln!(f)?;
+            writeln!(f, "{}", LINE)?;
+        }
+        let files: Vec<String> = (0..8)
+            .map(|i| ((b'a' + 7 - order(i)) as char).to_string())
+            .collect();
+        writeln!(f, "    {}", files.join("   "))?;
+        writeln!(f)?;
+
+        let fen = self.state.to_fen();
+        let parts: Vec<&str> = fen.split(' ').collect();
+        let side = match self.state.player {
+            Player::White => "white",
+            Player::Black => "black",
+        };
+        writeln!(f, "side to move: {}", side)?;
+        writeln!(f, "castling: {}", parts[2])?;
+        writeln!(f, "en passant: {}", parts[3])?;
+        if self.state.variant.counts_checks() {
+            writeln!(f, "checks remaining: {}", parts[4])?;
+        }
+        if self.state.variant != Variant::Standard {
+            writeln!(f, "variant: {}", self.state.variant.name())?;
+        }
+        writeln!(f, "fen: {}", fen)?;
+        write!(f, "hash: {:#018x}", self.state.hash)
+    }
+}
+
+/// the diagram from white's side. `{:#}` draws unicode pieces.
+impl Display for GameState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.diagram(f.alternate(), false), f)
+    }
+}
+
+impl Debug for GameState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GameState")
+            .field("fen", &self.to_fen())
+            .field("variant", &self.variant)
+            .field("hash", &format_args!("{:#018x}", self.hash))
+            .finish()
+    }
+}
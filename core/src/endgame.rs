@@ -0,0 +1,408 @@
+use std::sync::OnceLock;
+
+use hashbrown::HashMap;
+
+use crate::{
+    config::NUM_PIECES,
+    eval::PHASE_MAX,
+    grid::Grid,
+    markers::{BlackMarker, WhiteMarker},
+    player::Player,
+    types_for_io::Piece,
+    GameState,
+};
+
+// scale factors only apply to the endgame half of the tapered score
+pub const SCALE_FACTOR_NORMAL: i32 = 64;
+pub const SCALE_FACTOR_DRAW: i32 = 0;
+const SCALE_FACTOR_OPPOSITE_BISHOPS: i32 = 22;
+const SCALE_FACTOR_NO_PAWNS_SMALL_EDGE: i32 = 14;
+
+// large enough to dominate the generic terms, small enough to never look like a mate
+const KNOWN_WIN: i32 = 10_000 * PHASE_MAX;
+
+// non-pawn material in centipawns, for deciding whether a side can still win
+const MINOR_VALUE: i32 = 330;
+const ROOK_VALUE: i32 = 500;
+const QUEEN_VALUE: i32 = 950;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MaterialKey(u64);
+
+/// piece counts of a position, indexed by [player][piece]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MaterialCount {
+    counts: [[u8; NUM_PIECES]; 2],
+}
+
+impl MaterialCount {
+    pub fn new(state: &GameState) -> Self {
+        let count = |grid: Grid| grid.num_pieces() as u8;
+        let grid = &state.piece_grid;
+        Self {
+            counts: [
+                [
+                    count(grid.get_pawn_pos::<WhiteMarker>()),
+                    count(grid.get_knight_pos::<WhiteMarker>()),
+                    count(grid.get_bishop_pos::<WhiteMarker>()),
+                    count(grid.get_rook_pos::<WhiteMarker>()),
+                    count(grid.get_queen_pos::<WhiteMarker>()),
+                    count(grid.get_king_pos::<WhiteMarker>()),
+                ],
+                [
+                    count(grid.get_pawn_pos::<BlackMarker>()),
+                    count(grid.get_knight_pos::<BlackMarker>()),
+                    count(grid.get_bishop_pos::<BlackMarker>()),
+                    count(grid.get_rook_pos::<BlackMarker>()),
+                    count(grid.get_queen_pos::<BlackMarker>()),
+                    count(grid.get_king_pos::<BlackMarker>()),
+                ],
+            ],
+        }
+    }
+
+    /// parses a signature such as "KBNK", where the pieces before the second king belong
+    /// to `strong_side`
+    fn from_signature(signature: &str, strong_side: Player) -> Self {
+        let mut counts = [[0; NUM_PIECES]; 2];
+        let mut player = strong_side;
+        for (i, char) in signature.chars().enumerate() {
+            if char == 'K' && i > 0 {
+                player = strong_side.opp();
+            }
+            let piece = Piece::try_from(char).expect("invalid endgame signature");
+            counts[player as usize][piece as usize] += 1;
+        }
+        Self { counts }
+    }
+
+    #[inline(always)]
+    pub fn count(&self, player: Player, piece: Piece) -> u8 {
+        self.counts[player as usize][piece as usize]
+    }
+
+    pub fn key(&self) -> MaterialKey {
+        // 4 bits per piece count is plenty: a side has at most 10 of any piece
+        let key = self
+            .counts
+            .iter()
+            .flatten()
+            .fold(0u64, |acc, &count| (acc << 4) | count.min(15) as u64);
+        MaterialKey(key)
+    }
+
+    /// number of pieces on the board, excluding kings
+    pub fn num_pieces(&self) -> u32 {
+        self.counts
+            .iter()
+            .map(|side| side[..Piece::King as usize].iter().map(|&x| x as u32).sum::<u32>())
+            .sum()
+    }
+
+    pub fn non_pawn_material(&self, player: Player) -> i32 {
+        let side = &self.counts[player as usize];
+        (side[Piece::Knight as usize] + side[Piece::Bishop as usize]) as i32 * MINOR_VALUE
+            + side[Piece::Rook as usize] as i32 * ROOK_VALUE
+            + side[Piece::Queen as usize] as i32 * QUEEN_VALUE
+    }
+
+    fn is_bare_king(&self, player: Player) -> bool {
+        self.counts[player as usize][..Piece::King as usize]
+            .iter()
+            .all(|&x| x == 0)
+    }
+}
+
+/// result of consulting the endgame table
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EndgameEval {
+    /// the position is evaluated by a specialised function. value is from white's
+    /// perspective, in the same units as `eval::evaluate`.
+    Exact { name: &'static str, value: i32 },
+    /// the generic evaluation applies, with its endgame half scaled by
+    /// factor / SCALE_FACTOR_NORMAL
+    Scaled { name: &'static str, factor: i32 },
+}
+
+/// evaluates the position from the strong side's perspective
+type EndgameFn = fn(&GameState, Player) -> i32;
+
+struct EndgameEntry {
+    name: &'static str,
+    strong_side: Player,
+    eval_fn: EndgameFn,
+}
+
+fn endgame_table() -> &'static HashMap<MaterialKey, EndgameEntry> {
+    static TABLE: OnceLock<HashMap<MaterialKey, EndgameEntry>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let endgames: [(&'static str, EndgameFn); 8] = [
+            ("KK", evaluate_draw),
+            ("KNK", evaluate_draw),
+            ("KBK", evaluate_draw),
+            ("KNNK", evaluate_draw),
+            ("KBBK", evaluate_kbbk),
+            ("KBNK", evaluate_kbnk),
+            ("KRK", evaluate_kxk),
+            ("KQK", evaluate_kxk),
+        ];
+        let mut table = HashMap::new();
+        for (name, eval_fn) in endgames {
+            for strong_side in [Player::White, Player::Black] {
+                let key = MaterialCount::from_signature(name, strong_side).key();
+                table.entry(key).or_insert(EndgameEntry {
+                    name,
+                    strong_side,
+                    eval_fn,
+                });
+            }
+        }
+        table
+    })
+}
+
+/// consulted by `eval::evaluate` before the generic terms. returns a specialised
+/// evaluation if the material signature has one.
+pub fn probe(state: &GameState, material: &MaterialCount) -> Option<EndgameEval> {
+    // the evaluators below need both kings, which the horde does not have
+    if material.count(Player::White, Piece::King) != 1
+        || material.count(Player::Black, Piece::King) != 1
+    {
+        return None;
+    }
+
+    if material.num_pieces() <= 4 {
+        if let Some(entry) = endgame_table().get(&material.key()) {
+            let value = (entry.eval_fn)(state, entry.strong_side);
+            return Some(EndgameEval::Exact {
+                name: entry.name,
+                value: from_white_perspective(value, entry.strong_side),
+            });
+        }
+    }
+
+    // generic mating material against a bare king
+    for strong_side in [Player::White, Player::Black] {
+        if material.is_bare_king(strong_side.opp())
+            && material.non_pawn_material(strong_side) >= ROOK_VALUE
+        {
+            let value = evaluate_kxk(state, strong_side);
+            return Some(EndgameEval::Exact {
+                name: "KXK",
+                value: from_white_perspective(value, strong_side),
+            });
+        }
+    }
+
+    None
+}
+
+fn from_white_perspective(value: i32, strong_side: Player) -> i32 {
+    match strong_side {
+        Player::White => value,
+        Player::Black => -value,
+    }
+}
+
+/// scaling of the generic endgame score for drawish material. `strong_side` is the side
+/// the generic evaluation favours.
+pub fn scale_factor(
+    state: &GameState,
+    material: &MaterialCount,
+    strong_side: Player,
+) -> Option<EndgameEval> {
+    let weak_side = strong_side.opp();
+
+    // without pawns, a small material edge is rarely enough to win
+    if material.count(strong_side, Piece::Pawn) == 0 {
+        let edge = material.non_pawn_material(strong_side) - material.non_pawn_material(weak_side);
+        if edge <= MINOR_VALUE {
+            let factor = if material.non_pawn_material(strong_side) < ROOK_VALUE {
+                SCALE_FACTOR_DRAW
+            } else {
+                SCALE_FACTOR_NO_PAWNS_SMALL_EDGE
+            };
+            return Some(EndgameEval::Scaled {
+                name: "no pawns, small material edge",
+                factor,
+            });
+        }
+    }
+
+    // bishops of opposite colours with only pawns besides them
+    let only_bishops = |player: Player| {
+        material.count(player, Piece::Bishop) == 1
+            && material.non_pawn_material(player) == MINOR_VALUE
+    };
+    if only_bishops(Player::White) && only_bishops(Player::Black) {
+        let white_bishop = state.piece_grid.get_bishop_pos::<WhiteMarker>().to_pos();
+        let black_bishop = state.piece_grid.get_bishop_pos::<BlackMarker>().to_pos();
+        if is_dark_square(white_bishop) != is_dark_square(black_bishop) {
+            return Some(EndgameEval::Scaled {
+                name: "opposite-coloured bishops",
+                factor: SCALE_FACTOR_OPPOSITE_BISHOPS,
+            });
+        }
+    }
+
+    None
+}
+
+#[inline(always)]
+fn row_col(pos: u8) -> (i32, i32) {
+    ((pos >> 3) as i32, (pos & 0b111) as i32)
+}
+
+// a1 (row 0, column 7) is a dark square
+#[inline(always)]
+fn is_dark_square(pos: u8) -> bool {
+    let (row, col) = row_col(pos);
+    (row + col) % 2 == 1
+}
+
+fn distance(a: u8, b: u8) -> i32 {
+    let (a_row, a_col) = row_col(a);
+    let (b_row, b_col) = row_col(b);
+    i32::max((a_row - b_row).abs(), (a_col - b_col).abs())
+}
+
+/// 0 in the centre, 6 in the corners
+fn centre_distance(pos: u8) -> i32 {
+    let (row, col) = row_col(pos);
+    let from_centre = |x: i32| if x < 4 { 3 - x } else { x - 4 };
+    from_centre(row) + from_centre(col)
+}
+
+fn king_pos(state: &GameState, player: Player) -> u8 {
+    match player {
+        Player::White => state.piece_grid.get_king_pos::<WhiteMarker>().to_pos(),
+        Player::Black => state.piece_grid.get_king_pos::<BlackMarker>().to_pos(),
+    }
+}
+
+fn evaluate_draw(_state: &GameState, _strong_side: Player) -> i32 {
+    0
+}
+
+/// drives the weak king to the edge and brings the strong king closer
+fn evaluate_kxk(state: &GameState, strong_side: Player) -> i32 {
+    let material = MaterialCount::new(state);
+    let strong_king = king_pos(state, strong_side);
+    let weak_king = king_pos(state, strong_side.opp());
+
+    let value = material.non_pawn_material(strong_side)
+        + material.count(strong_side, Piece::Pawn) as i32 * 100
+        + 20 * centre_distance(weak_king)
+        + 10 * (7 - distance(strong_king, weak_king));
+    KNOWN_WIN + value * PHASE_MAX
+}
+
+/// drives the weak king to a corner of the bishop's colour, where mate is possible
+fn evaluate_kbnk(state: &GameState, strong_side: Player) -> i32 {
+    let strong_king = king_pos(state, strong_side);
+    let weak_king = king_pos(state, strong_side.opp());
+    let bishop = match strong_side {
+        Player::White => state.piece_grid.get_bishop_pos::<WhiteMarker>(),
+        Player::Black => state.piece_grid.get_bishop_pos::<BlackMarker>(),
+    }
+    .to_pos();
+
+    // h1 = 0 and a8 = 63 are light, a1 = 7 and h8 = 56 are dark
+    let corners = if is_dark_square(bishop) { [7, 56] } else { [0, 63] };
+    let corner_distance = corners
+        .iter()
+        .map(|&corner| distance(weak_king, corner))
+        .min()
+        .expect("two corners");
+
+    let value = 2 * MINOR_VALUE
+        + 10 * centre_distance(weak_king)
+        + 40 * (7 - corner_distance)
+        + 10 * (7 - distance(strong_king, weak_king));
+    KNOWN_WIN + value * PHASE_MAX
+}
+
+/// bishops of the same colour cannot force mate
+fn evaluate_kbbk(state: &GameState, strong_side: Player) -> i32 {
+    let bishops = match strong_side {
+        Player::White => state.piece_grid.get_bishop_pos::<WhiteMarker>(),
+        Player::Black => state.piece_grid.get_bishop_pos::<BlackMarker>(),
+    };
+    let mut colours = bishops.into_iter().map(is_dark_square);
+    if colours.next() == colours.next() {
+        0
+    } else {
+        evaluate_kxk(state, strong_side)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zoborist_state::ZoboristState;
+
+    fn state(fen: &str) -> GameState {
+        GameState::new_from_fen(fen, &ZoboristState::new(0)).unwrap()
+    }
+
+    /// the specialised evaluation of `fen`, from white's perspective
+    fn exact(fen: &str) -> (&'static str, i32) {
+        let state = state(fen);
+        match probe(&state, &MaterialCount::new(&state)) {
+            Some(EndgameEval::Exact { name, value }) => (name, value),
+            other => panic!("no exact evaluation for {}: {:?}", fen, other),
+        }
+    }
+
+    #[test]
+    fn bare_minors_are_drawn() {
+        assert_eq!(exact("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), ("KK", 0));
+        assert_eq!(exact("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"), ("KNK", 0));
+        assert_eq!(exact("3nkn2/8/8/8/8/8/8/4K3 w - - 0 1"), ("KNNK", 0));
+    }
+
+    #[test]
+    fn mating_material_drives_the_king_to_the_edge() {
+        for (name, white, black) in [("KRK", "4K2R", "4k2r"), ("KQK", "4K2Q", "4k2q")] {
+            // both weak kings are four squares from the strong king
+            let centre = exact(&format!("8/8/8/4k3/8/8/8/{} w - - 0 1", white));
+            let edge = exact(&format!("8/8/8/k7/8/8/8/{} w - - 0 1", white));
+            assert_eq!(centre.0, name);
+            assert!(centre.1 > 0 && edge.1 > centre.1, "{}: {:?} {:?}", name, centre, edge);
+
+            let black_strong = exact(&format!("8/8/8/4K3/8/8/8/{} w - - 0 1", black));
+            assert!(black_strong.1 < 0, "{}: {:?}", name, black_strong);
+        }
+    }
+
+    #[test]
+    fn bishop_and_knight_drive_the_king_to_the_right_corner() {
+        // the bishop on c1 is dark squared, so mate is on a1 or h8
+        let right = exact("7k/8/8/8/3K4/8/8/2B3N1 w - - 0 1");
+        let wrong = exact("k7/8/8/8/3K4/8/8/2B3N1 w - - 0 1");
+        let centre = exact("8/8/8/5k2/3K4/8/8/2B3N1 w - - 0 1");
+        assert_eq!(right.0, "KBNK");
+        assert!(centre.1 > 0, "{:?}", centre);
+        assert!(
+            right.1 > wrong.1 && right.1 > centre.1,
+            "{:?} {:?} {:?}",
+            right,
+            wrong,
+            centre
+        );
+    }
+
+    #[test]
+    fn opposite_coloured_bishops_scale_down() {
+        let opposite = state("2b1k3/pp6/8/8/8/8/PPP5/2B1K3 w - - 0 1");
+        assert_eq!(
+            scale_factor(&opposite, &MaterialCount::new(&opposite), Player::White),
+            Some(EndgameEval::Scaled {
+                name: "opposite-coloured bishops",
+                factor: SCALE_FACTOR_OPPOSITE_BISHOPS,
+            })
+        );
+        let same = state("4kb2/pp6/8/8/8/8/PPP5/2B1K3 w - - 0 1");
+        assert_eq!(scale_factor(&same, &MaterialCount::new(&same), Player::White), None);
+    }
+}
@@ -1,17 +1,21 @@
-use std::fmt::Display;
+use std::{collections::HashSet, fmt::Display};
 
 use crate::{
-    config::{MOVE_TABLE_SIZE, NULL_MOVES_PER_BRANCH, NULL_MOVE_DEPTH_REDUCTION, HashType},
+    attacks,
+    config::{MAX_SEARCH_DEPTH, NULL_MOVES_PER_BRANCH, NULL_MOVE_DEPTH_REDUCTION, HashType},
     eval,
-    grid::Grid,
-    markers::{BlackMarker, WhiteMarker},
+    evaluator::{Evaluator, PestoEvaluator},
+    game::{self, Game, MoveError},
     move_buffer::MoveBuffer,
     move_orderer::MoveOrderer,
-    move_table::{MoveEntry, MoveTable},
+    move_table::{Bound, MoveEntry, MoveTable, PerftTable},
     player::Player,
+    square::Square,
+    stop_signal::StopSignal,
+    syzygy::Tablebases,
     types::{Move, ValueMovePair},
     zoborist_state::ZoboristState,
-    GameState, game_data::Metadata, evaluate, Piece, square_type::{SquareType, CompressedSquareType},
+    GameState, game_data::Metadata, evaluate, Piece,
 };
 
 struct EngineStatistics {
@@ -24,6 +28,7 @@ struct EngineStatistics {
     null_move_fail_highs: usize,
     cutoffs: usize,
     cutoffs_perfect_move_orderings: usize,
+    tb_hits: u64,
 }
 
 impl Default for EngineStatistics {
@@ -38,6 +43,7 @@ impl Default for EngineStatistics {
             null_move_fail_highs: Default::default(),
             cutoffs: 0,
             cutoffs_perfect_move_orderings: 0,
+            tb_hits: 0,
         }
     }
 }
@@ -45,17 +51,22 @@ impl Default for EngineStatistics {
 impl Display for EngineStatistics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let res = format!(
-            "nodes: {} ({}/{}), cached_nodes: {}, null move fail highs: {}",
+            "nodes: {} ({}/{}), cached_nodes: {}, null move fail highs: {}, tbhits: {}",
             self.nodes_explored + self.quiescence_nodes,
             self.nodes_explored,
             self.quiescence_nodes,
             self.cache_hits,
-            self.null_move_fail_highs
+            self.null_move_fail_highs,
+            self.tb_hits
         );
         f.write_str(&res)
     }
 }
 
+/// called with each new best root move and its score for the side to move, while the
+/// search is still looking at the other root moves
+pub type RootListener = Box<dyn FnMut(Move, i32) + Send>;
+
 pub struct ChessEngine {
     // state: GameState,
     move_bufs: Vec<MoveBuffer>,
@@ -63,11 +74,27 @@ pub struct ChessEngine {
     calculated_moves: Vec<ValueMovePair>,
     pub zoborist_state: ZoboristState,
     // state_cache: HashMap<HashType, (usize, i32), FxBuildHasher>
-    state_cache: MoveTable<MOVE_TABLE_SIZE>,
+    state_cache: MoveTable,
+    perft_cache: PerftTable,
+    // searches may go deeper, the buffers grow as needed. the quiescence search stops
+    // at this many plies above its bottom.
     normal_depth: usize,
     quiescence_depth: usize,
     visited_nodes: Vec<HashType>,
     stats: EngineStatistics,
+    evaluator: Box<dyn Evaluator>,
+    tablebases: Tablebases,
+    excluded_root_moves: Vec<Move>,
+    stop_signal: StopSignal,
+    // set once the stop signal is seen, until the next search
+    stopped: bool,
+    root_listener: Option<RootListener>,
+    // searches stop once `nodes()` reaches this
+    node_limit: Option<u64>,
+    // moves played since the start of the game or the last position set up
+    game_ply: usize,
+    // the search prints its progress to stdout
+    debug_output: bool,
 }
 
 // impl<const normal_depth: usize, const MAX_DEPTH_CAPTURE: usize, const quiescence_depth: usize>
@@ -80,9 +107,24 @@ pub struct ChessEngine {
 
 impl ChessEngine {
     pub fn new(normal_depth: usize, quiescence_depth: usize, zoborist_state_seed: u64) -> Self {
+        Self::with_evaluator(
+            normal_depth,
+            quiescence_depth,
+            zoborist_state_seed,
+            Box::new(PestoEvaluator),
+        )
+    }
+
+    pub fn with_evaluator(
+        normal_depth: usize,
+        quiescence_depth: usize,
+        zoborist_state_seed: u64,
+        evaluator: Box<dyn Evaluator>,
+    ) -> Self {
         if normal_depth > quiescence_depth {
             panic!("invalid depth parameters");
         }
+        attacks::init();
         // const BUF: Vec<Move> = vec![];
         ChessEngine {
             move_bufs: vec![MoveBuffer::default(); normal_depth + quiescence_depth],
@@ -91,16 +133,125 @@ impl ChessEngine {
             zoborist_state: ZoboristState::new(zoborist_state_seed),
             // state_cache: HashMap::with_hasher(FxBuildHasher::default())
             state_cache: Default::default(),
+            perft_cache: Default::default(),
             quiescence_depth,
             normal_depth,
             visited_nodes: Vec::with_capacity(normal_depth*2),
             stats: Default::default(),
+            evaluator,
+            tablebases: Default::default(),
+            excluded_root_moves: vec![],
+            stop_signal: Default::default(),
+            stopped: false,
+            root_listener: None,
+            node_limit: None,
+            game_ply: 0,
+            debug_output: true,
         }
     }
 
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator>) {
+        self.evaluator = evaluator;
+    }
+
+    pub fn evaluator(&self) -> &dyn Evaluator {
+        self.evaluator.as_ref()
+    }
+
+    /// loads syzygy tables from the given directories. an empty path disables probing.
+    pub fn set_syzygy_path(&mut self, path: &str) {
+        self.tablebases = Tablebases::new(path);
+    }
+
+    /// replaces the transposition table with an empty one of at most `megabytes`. the old
+    /// table is kept if the new one can't be allocated.
+    pub fn set_hash_size(&mut self, megabytes: usize) -> Result<(), &'static str> {
+        self.state_cache = MoveTable::with_size_mb(megabytes)?;
+        Ok(())
+    }
+
+    pub fn hash_size(&self) -> usize {
+        self.state_cache.size_mb()
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.state_cache.clear();
+    }
+
+    /// plies of quiescence search below the main search. it may not be less than the
+    /// maximum main search depth.
+    pub fn set_quiescence_depth(&mut self, quiescence_depth: usize) -> Result<(), &'static str> {
+        if self.normal_depth > quiescence_depth {
+            return Err("quiescence depth below the maximum search depth");
+        }
+        if quiescence_depth + MAX_SEARCH_DEPTH > u8::MAX as usize {
+            return Err("quiescence depth too large for the transposition table");
+        }
+        self.quiescence_depth = quiescence_depth;
+        self.move_bufs = vec![MoveBuffer::default(); self.normal_depth + quiescence_depth];
+        self.move_orderer = MoveOrderer::new(self.normal_depth + quiescence_depth);
+        // stored depths count the quiescence plies
+        self.state_cache.clear();
+        Ok(())
+    }
+
+    /// switches to new zobrist keys. positions hashed with the old keys must be rehashed
+    /// with `GameState::rehash`.
+    pub fn set_zoborist_seed(&mut self, seed: u64) {
+        self.zoborist_state = ZoboristState::new(seed);
+        self.state_cache.clear();
+        self.perft_cache = Default::default();
+        self.visited_nodes.clear();
+    }
+
+    /// root moves the following searches skip, so that the best of the remaining moves can
+    /// be found for multipv
+    pub fn set_excluded_root_moves(&mut self, moves: Vec<Move>) {
+        self.excluded_root_moves = moves;
+    }
+
+    /// ends the searches once `nodes` more nodes have been searched from now on, until
+    /// the limit is removed with none
+    pub fn set_node_limit(&mut self, nodes: Option<u64>) {
+        self.node_limit = nodes.map(|nodes| self.nodes() + nodes);
+    }
+
+    /// whether the search prints the static evaluation, the root moves and node counts as
+    /// it goes, which it does by default
+    pub fn set_debug_output(&mut self, enabled: bool) {
+        self.debug_output = enabled;
+    }
+
+    pub fn set_root_listener(&mut self, listener: Option<RootListener>) {
+        self.root_listener = listener;
+    }
+
+    /// a handle to end the searches of this engine from another thread
+    pub fn stop_signal(&self) -> StopSignal {
+        self.stop_signal.clone()
+    }
+
+    /// whether the last search was ended by the stop signal or the node limit. its result
+    /// only covers the root moves searched before that.
+    pub fn search_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn tablebases(&self) -> &Tablebases {
+        &self.tablebases
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.stats.nodes_explored + self.stats.quiescence_nodes
+    }
+
+    pub fn tb_hits(&self) -> u64 {
+        self.stats.tb_hits
+    }
+
     // returns (score, initial piece pos, move piece pos)
-    fn scoring_function(state: &GameState) -> i32 {
-        let cur = eval::evaluate(state);
+    fn scoring_function(&self, state: &GameState) -> i32 {
+        let cur = self.evaluator.evaluate(state);
         if state.player == Player::Black {
             -cur
         } else {
@@ -113,12 +264,12 @@ impl ChessEngine {
     }
 
     fn try_print_debug(&self, alpha: i32, beta: i32, state: &GameState) {
-        if (self.stats.quiescence_nodes + self.stats.nodes_explored) % 100000 == 0 {
+        if self.debug_output && (self.stats.quiescence_nodes + self.stats.nodes_explored) % 100000 == 0 {
             println!(
                 "explored {} nodes, {} terminal nodes, {} branching factor, {}/{} normal/quiescence nodes, {} cutoffs, {} perfect cutoffs, alpha: {}, beta: {}, direct cache hits: {}, cache hits: {}, current_score: {}, max_depth_encountered: {}",
                 self.stats.nodes_explored + self.stats.quiescence_nodes, self.stats.terminal_nodes, 
                 (self.stats.nodes_explored as f64 + self.stats.quiescence_nodes as f64) / (self.stats.nodes_explored as f64+ self.stats.quiescence_nodes as f64 - self.stats.terminal_nodes as f64),
-                 self.stats.nodes_explored, self.stats.quiescence_nodes, self.stats.cutoffs, self.stats.cutoffs_perfect_move_orderings, alpha, beta, self.stats.cache_direct_cutoff_hits, self.stats.cache_hits, eval::evaluate(state), self.stats.max_depth_encountered
+                 self.stats.nodes_explored, self.stats.quiescence_nodes, self.stats.cutoffs, self.stats.cutoffs_perfect_move_orderings, alpha, beta, self.stats.cache_direct_cutoff_hits, self.stats.cache_hits, self.evaluator.evaluate(state), self.stats.max_depth_encountered
             );
         }
     }
@@ -133,7 +284,10 @@ impl ChessEngine {
     ) -> i32 {
         self.stats.quiescence_nodes += 1;
         self.stats.terminal_nodes += 1;
-        let stand_pat = Self::scoring_function(state);
+        if let Some(score) = Self::variant_result(state, depth) {
+            return score;
+        }
+        let stand_pat = self.scoring_function(state);
         // self.stats.max_depth_encountered = self.stats.max_depth_encountered.min(depth);
         if depth == self.normal_depth + 1 {
             self.stats.terminal_nodes -= 1;
@@ -206,10 +360,8 @@ impl ChessEngine {
                         // first_move_explored = false;
                         // continue;
                     }
-                    let metadata = state.metadata;
-                    state.apply_meta_hash(&self.zoborist_state);
-                    state.advance_state(next_move, &self.zoborist_state);
-                    state.apply_meta_hash(&self.zoborist_state);
+                    self.evaluator.make_move(state, next_move);
+                    let metadata = state.make_move(next_move, &self.zoborist_state);
                     
                     // futility pruning
                     // if evaluate(state) + 2 * eval::PAWN_VALUE < alpha {
@@ -225,12 +377,8 @@ impl ChessEngine {
                     // update meta values
                     let score = -self.quiescence(state, -beta, -alpha, depth - 1, last_move_pos);
 
-                    state.apply_meta_hash(&self.zoborist_state);
-                    state.revert_state(next_move, &self.zoborist_state);
-
-                    // revert metadata: easiest to do this way
-                    state.metadata = metadata;
-                    state.apply_meta_hash(&self.zoborist_state);
+                    state.unmake_move(next_move, metadata, &self.zoborist_state);
+                    self.evaluator.unmake_move(state, next_move);
 
                     if score > alpha {
                         alpha = score;
@@ -262,6 +410,21 @@ impl ChessEngine {
         alpha
     }
 
+    /// score of a position the variant has already decided, from the side to move's
+    /// perspective
+    #[inline(always)]
+    fn variant_result(state: &GameState, depth: usize) -> Option<i32> {
+        if !state.is_variant_loss() {
+            return None;
+        }
+        // the move that got here may have left its own king in check. normally the king
+        // capture that follows gives it away, so do the same here.
+        if state.attacks_opponent_king() {
+            return Some(eval::SCORE_AFTER_KING_CAPTURED);
+        }
+        Some(-eval::WIN_THRESHOLD - (depth as i32))
+    }
+
     fn calc(
         &mut self,
         state: &mut GameState,
@@ -274,6 +437,14 @@ impl ChessEngine {
         is_root: bool,
     ) -> i32 {
         self.stats.nodes_explored += 1;
+        if self.stats.nodes_explored.is_multiple_of(1024)
+            && (self.stop_signal.poll() || self.node_limit.is_some_and(|limit| self.nodes() >= limit))
+        {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
         self.stats.terminal_nodes += 1;
         if self.visited_nodes.contains(&state.hash){
             // repetition.
@@ -282,14 +453,19 @@ impl ChessEngine {
         }
         self.visited_nodes.push(state.hash);
 
+        if let Some(score) = Self::variant_result(state, depth) {
+            self.visited_nodes.pop().unwrap();
+            return score;
+        }
+
         // transposition table hit
-        if let Some(MoveEntry { value, .. }) = self.state_cache.get_entry_for_direct_cutoff(
+        if let Some(MoveEntry { value, bound, .. }) = self.state_cache.get_entry_for_direct_cutoff(
             state.hash,
             self.quiescence_depth as u8 + depth as u8,
             state,
         ) {
             self.stats.cache_hits += 1;
-            if value >= beta {
+            if value >= beta && bound != Bound::Upper {
                 self.stats.cutoffs += 1;
                 self.stats.cutoffs_perfect_move_orderings += 1;
                 self.visited_nodes.pop().unwrap();
@@ -308,6 +484,16 @@ impl ChessEngine {
 
         self.try_print_debug(alpha, beta, state);
 
+        // tablebase hit. the root is handled by solve using DTZ instead.
+        if !is_root && self.tablebases.can_probe(state) {
+            if let Some(wdl) = self.tablebases.probe_wdl(state, &self.zoborist_state) {
+                self.stats.tb_hits += 1;
+                self.visited_nodes.pop().unwrap();
+                self.stats.terminal_nodes -= 1;
+                return wdl.to_score(depth);
+            }
+        }
+
 
         let move_entry = self.state_cache.get_entry_for_ordering(
             state.hash,
@@ -342,6 +528,10 @@ impl ChessEngine {
             state.metadata = metadata;
             state.apply_meta_hash(&self.zoborist_state);
 
+            if self.stopped {
+                self.visited_nodes.pop().unwrap();
+                return 0;
+            }
             if next_val >= beta {
                 self.stats.null_move_fail_highs += 1;
                 self.visited_nodes.pop().unwrap();
@@ -350,6 +540,7 @@ impl ChessEngine {
         }
 
         let mut value = -eval::SCORE_MAX;
+        let original_alpha = alpha;
 
         // let mut move_buf = MoveBuffer::default();
         // move_buf.clear(depth);
@@ -372,7 +563,10 @@ impl ChessEngine {
         macro_rules! loop_inner {
             ($next_move:ident) => {{
                 // while let Some(next_move) = self.move_buf.pop(depth){
-                if is_root {
+                if is_root && self.excluded_root_moves.contains(&$next_move) {
+                    continue;
+                }
+                if is_root && self.debug_output {
                     println!("{}", $next_move);
                 }
 
@@ -407,10 +601,8 @@ impl ChessEngine {
                 // do the state transition
                 // let last_state = state.clone();
 
-                let metadata = state.metadata;
-                state.apply_meta_hash(&self.zoborist_state);
-                state.advance_state($next_move, &self.zoborist_state);
-                state.apply_meta_hash(&self.zoborist_state);
+                self.evaluator.make_move(state, $next_move);
+                let metadata = state.make_move($next_move, &self.zoborist_state);
 
                 let last_move_pos = if let Move::Move { new_pos, .. } = $next_move {
                     new_pos
@@ -442,12 +634,14 @@ impl ChessEngine {
                     false,
                 );
 
-                state.apply_meta_hash(&self.zoborist_state);
-                state.revert_state($next_move, &self.zoborist_state);
+                state.unmake_move($next_move, metadata, &self.zoborist_state);
+                self.evaluator.unmake_move(state, $next_move);
 
-                // revert metadata: easiest to do this way
-                state.metadata = metadata;
-                state.apply_meta_hash(&self.zoborist_state);
+                // the value of an unfinished subtree means nothing
+                if self.stopped {
+                    self.visited_nodes.pop().unwrap();
+                    return 0;
+                }
 
                 // if *state != last_state{
                 //     panic!("state no match");
@@ -457,6 +651,11 @@ impl ChessEngine {
                 if next_val > value {
                     value = next_val;
                     best_move = Some($next_move);
+                    if is_root {
+                        if let Some(listener) = self.root_listener.as_mut() {
+                            listener($next_move, next_val);
+                        }
+                    }
                 }
                 // value = i32::max(value, next_val);
 
@@ -535,17 +734,6 @@ impl ChessEngine {
 
         // no legal moves
         if best_move == None || value < -eval::SCORE_AFTER_KING_CAPTURED_CUTOFF {
-            // determine if currently in check
-            let (attacked_grid, king_pos) = match state.player {
-                Player::White => (
-                    MoveBuffer::get_attacked_grid::<BlackMarker>(state),
-                    state.piece_grid.get_king_pos::<WhiteMarker>(),
-                ),
-                Player::Black => (
-                    MoveBuffer::get_attacked_grid::<WhiteMarker>(state),
-                    state.piece_grid.get_king_pos::<BlackMarker>(),
-                ),
-            };
             // self.move_bufs[depth].clear();
             // self.move_bufs[depth].get_squares_under_attack(state);
             // for pos in 0u8..64 {
@@ -559,7 +747,7 @@ impl ChessEngine {
             // }
 
             // checkmate
-            if attacked_grid & king_pos != Grid::EMPTY {
+            if state.is_in_check() {
                 self.visited_nodes.pop().unwrap();
                 return -eval::WIN_THRESHOLD - (depth as i32);
             }
@@ -570,12 +758,23 @@ impl ChessEngine {
         }
 
         if let Some(mov) = best_move {
-            self.state_cache.insert_entry(MoveEntry {
-                hash: state.hash,
-                mov,
-                depth: depth as u8,
-                value,
-            });
+            let bound = if value >= beta {
+                Bound::Lower
+            } else if value > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            self.state_cache.insert_entry(
+                state.hash,
+                MoveEntry {
+                    mov,
+                    depth: depth as u8,
+                    value,
+                    bound,
+                },
+                state,
+            );
             self.move_orderer
                 .update_history(mov, state.player, depth - self.quiescence_depth + 1);
         }
@@ -593,7 +792,9 @@ impl ChessEngine {
     pub fn solve(&mut self, state: &GameState, depth: usize) -> i32 {
         // the engine will never visit the same state more than once. hence, to avoid threefold repetition,
         // we only care about previous moves that appear more than once. 
-        println!("static eval: {}", eval::evaluate(state));
+        if self.debug_output {
+            println!("static eval: {}", self.evaluator.evaluate(state));
+        }
         let mut new_visited_nodes = vec![];
         for item in self.visited_nodes.iter(){
             if self.visited_nodes.iter().filter(|x|**x == *item).count() > 1{
@@ -601,20 +802,45 @@ impl ChessEngine {
             }
         }
         self.visited_nodes = new_visited_nodes;
-        assert!(depth <= self.normal_depth);
+        assert!(depth <= MAX_SEARCH_DEPTH, "search depth above {}", MAX_SEARCH_DEPTH);
+        // one buffer per ply, from the bottom of the quiescence search up to the root
+        let root_index = depth + self.quiescence_depth;
+        if self.move_bufs.len() <= root_index {
+            self.move_bufs.resize(root_index + 1, MoveBuffer::default());
+        }
+        self.move_orderer.new_search(root_index, self.game_ply);
         let mut state = state.clone();
+        self.evaluator.reset(&state);
         // state.setup(&self.zoborist_state);
         self.calculated_moves.clear();
-        let mut result = self.calc(
-            &mut state,
-            -eval::SCORE_MAX,
-            eval::SCORE_MAX,
-            depth + self.quiescence_depth,
-            0,
-            true,
-            0,
-            true,
-        );
+        self.state_cache
+            .allocate()
+            .expect("cannot allocate the transposition table");
+        self.state_cache.new_search();
+        self.stopped = self.stop_signal.is_stopped();
+        // the tablebase move may be one of the excluded ones
+        let root_probe = if self.excluded_root_moves.is_empty() {
+            self.tablebases.probe_root(&mut state, &self.zoborist_state)
+        } else {
+            None
+        };
+        let mut result = if let Some((mov, wdl)) = root_probe {
+            self.stats.tb_hits += 1;
+            let value = wdl.to_score(depth + self.quiescence_depth);
+            self.calculated_moves.push(ValueMovePair(value, mov));
+            value
+        } else {
+            self.calc(
+                &mut state,
+                -eval::SCORE_MAX,
+                eval::SCORE_MAX,
+                depth + self.quiescence_depth,
+                0,
+                true,
+                0,
+                true,
+            )
+        };
         if state.player == Player::Black {
             /*let mut new_moves: BTreeSet<ValueMovePair> = Default::default();
             for item in self.calculated_moves.iter() {
@@ -640,6 +866,60 @@ impl ChessEngine {
         }
     }
 
+    /// the best `count` root moves with their principal variations, best first. each one
+    /// is found by searching again without the moves before it. scores are from white's
+    /// side like those of `solve`, and the root listener only hears about the first line.
+    /// a stopped search returns the lines finished before it.
+    pub fn search_lines(
+        &mut self,
+        state: &GameState,
+        depth: usize,
+        count: usize,
+    ) -> Vec<(i32, Vec<Move>)> {
+        let mut lines: Vec<(i32, Vec<Move>)> = Vec::with_capacity(count);
+        let legal_moves = state.legal_moves();
+        while lines.len() < count.min(legal_moves.len()) {
+            self.excluded_root_moves = lines.iter().map(|(_, pv)| pv[0]).collect();
+            let score = self.solve(state, depth);
+            if self.stopped {
+                break;
+            }
+            let pv = self.principal_variation(state, depth);
+            // without legal moves left, the search settles for one leaving the king in check
+            if pv.is_empty() || !legal_moves.contains(&pv[0]) {
+                break;
+            }
+            lines.push((score, pv));
+            self.root_listener = None;
+        }
+        self.excluded_root_moves.clear();
+        lines
+    }
+
+    /// the best move of the last search followed by the replies stored in the
+    /// transposition table, at most `max_len` moves. the second move is the one to ponder on.
+    pub fn principal_variation(&mut self, state: &GameState, max_len: usize) -> Vec<Move> {
+        let mut pv = vec![];
+        let Some(mut mov) = self.get_best_calculated_move(state.player) else {
+            return pv;
+        };
+        let mut state = state.clone();
+        let mut seen = HashSet::from([state.hash]);
+        while pv.len() < max_len {
+            pv.push(mov);
+            state.make_move(mov, &self.zoborist_state);
+            // stop at a repetition, or the line would go round in circles
+            if !seen.insert(state.hash) {
+                break;
+            }
+            match self.state_cache.get_move(state.hash, &state) {
+                Some(next) if state.legal_moves().contains(&next) => mov = next,
+                _ => break,
+            }
+        }
+        pv
+    }
+
     pub fn get_result(&mut self) {
         println!("{}", self.stats);
         self.calculated_moves.sort();
@@ -648,21 +928,47 @@ impl ChessEngine {
         }
     }
 
-    pub fn perft(&mut self, state: &mut GameState, depth: usize) -> i64 {
-        // determine if cn capture the opposing king
-        let (attacked_grid, king_pos) = match state.player {
-            Player::Black => (
-                MoveBuffer::get_attacked_grid::<BlackMarker>(state),
-                state.piece_grid.get_king_pos::<WhiteMarker>(),
-            ),
-            Player::White => (
-                MoveBuffer::get_attacked_grid::<WhiteMarker>(state),
-                state.piece_grid.get_king_pos::<BlackMarker>(),
-            ),
-        };
+    /// the perft count below each legal move, to find the move that move generation gets
+    /// wrong. empty at depth 0. none if the stop signal ended it early.
+    pub fn perft_divide(&mut self, state: &GameState, depth: usize) -> Option<Vec<(Move, i64)>> {
+        if depth == 0 {
+            return Some(vec![]);
+        }
+        if self.move_bufs.len() <= depth {
+            self.move_bufs.resize(depth + 1, MoveBuffer::default());
+        }
+        self.stopped = self.stop_signal.is_stopped();
+        let mut state = state.clone();
+        let divide = state
+            .legal_moves()
+            .into_iter()
+            .map(|mov| {
+                let metadata = state.make_move(mov, &self.zoborist_state);
+                let count = self.count_leaves(&mut state, depth - 1);
+                state.unmake_move(mov, metadata, &self.zoborist_state);
+                (mov, count)
+            })
+            .collect();
+        (!self.stopped).then_some(divide)
+    }
+
+    /// leaf nodes `depth` plies below `state`. none if the stop signal ended it early.
+    pub fn perft(&mut self, state: &mut GameState, depth: usize) -> Option<i64> {
+        if self.move_bufs.len() <= depth {
+            self.move_bufs.resize(depth + 1, MoveBuffer::default());
+        }
+        self.stopped = self.stop_signal.is_stopped();
+        let count = self.count_leaves(state, depth);
+        (!self.stopped).then_some(count)
+    }
+
+    fn count_leaves(&mut self, state: &mut GameState, depth: usize) -> i64 {
+        if self.stopped {
+            return 0;
+        }
 
-        // checkmate
-        if attacked_grid & king_pos != Grid::EMPTY {
+        // the previous move left its king in check
+        if state.attacks_opponent_king() {
             return 0;
         }
 
@@ -670,116 +976,87 @@ impl ChessEngine {
             return 1;
         }
 
-        let move_entry = self
-            .state_cache
-            .get_entry_perft(state.hash, depth.try_into().unwrap());
+        // the game is over, so there are no moves to count
+        if state.is_variant_loss() {
+            return 0;
+        }
 
-        if let Some(MoveEntry { value, .. }) = move_entry {
+        if let Some(count) = self.perft_cache.get(state.hash, depth.try_into().unwrap()) {
             self.stats.cache_hits += 1;
-            return value as i64;
+            return count;
+        }
+
+        // nodes aren't counted here, so the deadline is checked a few plies above the
+        // leaves, every few thousand of them
+        if depth >= 3 && self.stop_signal.poll() {
+            self.stopped = true;
+            return 0;
         }
 
-        // let mut move_buf = MoveBuffer::default();
-        // move_buf.clear(depth);
         self.move_bufs[depth].clear();
         self.move_bufs[depth].get_all_moves(state);
 
-        // if !self.move_bufs[depth].is_legal() {
-        //     // println!("hi2");
-        //     return -1;
-        // }
-
-        // if depth == 0 {
-        //     return 1;
-        // }
-
         let mut cnt: i64 = 0;
 
-        // println!("hi");
-
-        // let map = HashMap::default();
-
         while let Some(next_move) = self.move_bufs[depth].pop() {
-            // if depth == 2 {
-            //     map.get(k)
-            //     println!("move: {}", next_move);
-            // }
-            let metadata = state.metadata;
-            state.apply_meta_hash(&self.zoborist_state);
-            state.advance_state(next_move, &self.zoborist_state);
-            state.apply_meta_hash(&self.zoborist_state);
+            let metadata = state.make_move(next_move, &self.zoborist_state);
 
-            let res = self.perft(state, depth - 1);
+            let res = self.count_leaves(state, depth - 1);
 
             cnt += res;
 
-            state.apply_meta_hash(&self.zoborist_state);
-            state.revert_state(next_move, &self.zoborist_state);
-            state.metadata = metadata;
-            state.apply_meta_hash(&self.zoborist_state);
+            state.unmake_move(next_move, metadata, &self.zoborist_state);
         }
 
-        // if no legal children, it is a checkmate
-        cnt = cnt.max(1);
-
-        self.state_cache.insert_entry(MoveEntry {
-            hash: state.hash,
-            mov: Move::Castle { is_short: false },
-            depth: depth.try_into().unwrap(),
-            value: cnt as i32,
-        });
+        // a count cut short by the stop signal is too low
+        if !self.stopped {
+            self.perft_cache.insert(state.hash, depth.try_into().unwrap(), cnt);
+        }
 
         cnt
     }
     
     pub fn clear_move_history_threefold_repetition(&mut self){
         self.visited_nodes.clear();
+        self.game_ply = 0;
+    }
+
+    /// takes the positions played in `game` before its current one, for the searches to
+    /// avoid repetitions, as if its moves had been made with `make_move_raw_parts`
+    pub fn set_game(&mut self, game: &Game) {
+        let mut game = game.clone();
+        self.visited_nodes.clear();
+        while game.undo().is_some() {
+            self.visited_nodes.push(game.state().hash);
+        }
+        self.visited_nodes.reverse();
+        self.game_ply = self.visited_nodes.len();
     }
 
+    /// makes the move from `from` to `to`, as sent by a GUI, after checking it is legal.
+    /// castling may be given as the king's two-square move or as the king taking its rook.
     pub fn make_move_raw_parts(
         &mut self,
         game_state: &mut GameState,
-        prev_pos: u8,
-        new_pos: u8,
+        from: Square,
+        to: Square,
         promoted_to_piece: Option<Piece>,
-    ) -> Result<(), &'static str> {
-        let piece = game_state.piece_grid.get_square_type(prev_pos);
-        let captured_piece = game_state.piece_grid.get_square_type(new_pos);
-        let next_move = if let Some(promoted_to_piece) = promoted_to_piece {
-            let promoted_to_piece = SquareType::create_for_parsing(promoted_to_piece, game_state.player);
-            let pieces = CompressedSquareType::from_square_types(promoted_to_piece, captured_piece);
-            Move::PawnPromote {
-                prev_pos,
-                new_pos,
-                pieces
-            }
-        } else if piece.is_king() && i32::abs(prev_pos as i32 - new_pos as i32) == 2 {
-            Move::Castle {
-                is_short: new_pos < prev_pos,
-            }
-        } else if piece.is_pawn()
-            && (new_pos & 0b111 != prev_pos & 0b111)
-            && captured_piece.is_empty()
-        {
-            Move::EnPassant {
-                prev_column: prev_pos & 0b111,
-                new_column: new_pos & 0b111,
-            }
-        } else {
-            let pieces = CompressedSquareType::from_square_types(piece, captured_piece);
-            Move::Move {
-                prev_pos,
-                new_pos,
-                pieces
-            }
-        };
+    ) -> Result<Move, MoveError> {
+        let next_move = game::find_move(game_state, from, to, promoted_to_piece)?;
 
         self.visited_nodes.push(game_state.hash);
-        game_state.advance_state(next_move, &self.zoborist_state);
-        Ok(())
+        self.game_ply += 1;
+        game_state.make_move(next_move, &self.zoborist_state);
+        Ok(next_move)
     }
 
-    pub fn lift_killer_moves(&mut self, depth_to_lift: usize){
-        self.move_orderer.lift_killer_moves(depth_to_lift)
+    /// forgets what was learned in the last game: the transposition table, the move
+    /// ordering and the positions played. positions hash the same in every variant, so
+    /// this is needed after switching variants as well.
+    pub fn new_game(&mut self) {
+        self.state_cache.clear();
+        self.perft_cache = Default::default();
+        self.move_orderer.clear();
+        self.clear_move_history_threefold_repetition();
     }
 }
@@ -1,6 +1,13 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
 use crate::{
     config::NUM_PIECES,
+    endgame::{self, EndgameEval, MaterialCount, SCALE_FACTOR_NORMAL},
+    grid::Grid,
     markers::{BlackMarker, PlayerMarker, WhiteMarker},
+    types_for_io::Piece,
     GameState, Player,
 };
 
@@ -9,6 +16,10 @@ pub const SCORE_AFTER_KING_CAPTURED_CUTOFF: i32 = (WIN_THRESHOLD * 3) / 2;
 pub const SCORE_AFTER_KING_CAPTURED: i32 = WIN_THRESHOLD * 2;
 pub const SCORE_MAX: i32 = WIN_THRESHOLD * 10;
 
+/// scores are tapered between midgame and endgame by the game phase, so one centipawn
+/// is worth PHASE_MAX units
+pub const PHASE_MAX: i32 = 24;
+
 const KING_VALUE: i32 = 2_000_000;
 
 const PIECE_PHASE: [u32; NUM_PIECES] = [0, 1, 1, 2, 4, 0];
@@ -256,15 +267,267 @@ fn calc_phase<P: PlayerMarker>(state: &GameState) -> u32 {
         + state.piece_grid.get_queen_pos::<P>().num_pieces() * PIECE_PHASE[4]
 }
 
+fn strong_side(eg_score: i32) -> Player {
+    if eg_score >= 0 {
+        Player::White
+    } else {
+        Player::Black
+    }
+}
+
 pub fn evaluate(state: &GameState) -> i32 {
+    let material = MaterialCount::new(state);
+    if let Some(EndgameEval::Exact { value, .. }) = endgame::probe(state, &material) {
+        return value;
+    }
+
     let mg_score = evaluate_player::<WhiteMarker>(state, &MG_PIECE_VAL, &MG_SQUARE_TABLE)
         - evaluate_player::<BlackMarker>(state, &MG_PIECE_VAL, &MG_SQUARE_TABLE);
-    let eg_score = evaluate_player::<WhiteMarker>(state, &EG_PIECE_VAL, &EG_SQUARE_TABLE)
+    let mut eg_score = evaluate_player::<WhiteMarker>(state, &EG_PIECE_VAL, &EG_SQUARE_TABLE)
         - evaluate_player::<BlackMarker>(state, &EG_PIECE_VAL, &EG_SQUARE_TABLE);
 
+    if let Some(EndgameEval::Scaled { factor, .. }) =
+        endgame::scale_factor(state, &material, strong_side(eg_score))
+    {
+        eg_score = eg_score * factor / SCALE_FACTOR_NORMAL;
+    }
+
     let mut phase = (calc_phase::<WhiteMarker>(state) + calc_phase::<BlackMarker>(state)) as i32;
-    if phase > 24 {
-        phase = 24
+    if phase > PHASE_MAX {
+        phase = PHASE_MAX
     };
-    mg_score * phase + eg_score * (24 - phase)
+    mg_score * phase + eg_score * (PHASE_MAX - phase)
+}
+
+/// midgame and endgame values of one evaluation term for one side, in centipawns
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize)]
+pub struct TaperedScore {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl std::ops::Add for TaperedScore {
+    type Output = TaperedScore;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        TaperedScore {
+            mg: self.mg + rhs.mg,
+            eg: self.eg + rhs.eg,
+        }
+    }
+}
+
+impl std::ops::Sub for TaperedScore {
+    type Output = TaperedScore;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        TaperedScore {
+            mg: self.mg - rhs.mg,
+            eg: self.eg - rhs.eg,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EvalTerm {
+    pub name: String,
+    pub white: TaperedScore,
+    pub black: TaperedScore,
+}
+
+impl EvalTerm {
+    pub fn total(&self) -> TaperedScore {
+        self.white - self.black
+    }
+}
+
+/// per-term breakdown of a static evaluation. all scores are from white's perspective.
+#[derive(Clone, Debug, Serialize)]
+pub struct EvalTrace {
+    pub evaluator: &'static str,
+    pub terms: Vec<EvalTerm>,
+    /// 0 (bare kings and pawns) to PHASE_MAX (all pieces on the board)
+    pub phase: i32,
+    /// the endgame half of the total is multiplied by scale_factor / SCALE_FACTOR_NORMAL
+    pub scale_factor: i32,
+    /// specialised endgame evaluation or scaling rule that applied, if any
+    pub endgame: Option<&'static str>,
+    /// final tapered score in centipawns
+    pub score: i32,
+}
+
+impl EvalTrace {
+    pub fn total(&self) -> TaperedScore {
+        self.terms
+            .iter()
+            .fold(TaperedScore::default(), |acc, term| acc + term.total())
+    }
+
+    pub fn taper(score: TaperedScore, phase: i32, scale_factor: i32) -> i32 {
+        let eg = score.eg * scale_factor / SCALE_FACTOR_NORMAL;
+        (score.mg * phase + eg * (PHASE_MAX - phase)) / PHASE_MAX
+    }
+
+    /// builds a trace from the given terms, tapering their sum by `phase`
+    pub fn from_terms(evaluator: &'static str, terms: Vec<EvalTerm>, phase: i32) -> Self {
+        let mut res = Self {
+            evaluator,
+            terms,
+            phase,
+            scale_factor: SCALE_FACTOR_NORMAL,
+            endgame: None,
+            score: 0,
+        };
+        res.score = Self::taper(res.total(), phase, res.scale_factor);
+        res
+    }
+
+    fn with_scale_factor(mut self, name: &'static str, scale_factor: i32) -> Self {
+        self.scale_factor = scale_factor;
+        self.endgame = Some(name);
+        self.score = Self::taper(self.total(), self.phase, scale_factor);
+        self
+    }
+}
+
+impl Display for EvalTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fmt_cp = |cp: i32| format!("{:6.2}", cp as f64 / 100.0);
+        let fmt_score = |score: TaperedScore| format!("{} {}", fmt_cp(score.mg), fmt_cp(score.eg));
+        let separator = " ----------------+---------------+---------------+---------------";
+
+        writeln!(f, " Evaluator: {}", self.evaluator)?;
+        writeln!(f)?;
+        writeln!(f, "            Term |     White     |     Black     |     Total")?;
+        writeln!(f, "                 |   MG     EG   |   MG     EG   |   MG     EG")?;
+        writeln!(f, "{}", separator)?;
+        for term in self.terms.iter() {
+            writeln!(
+                f,
+                " {:>15} | {} | {} | {}",
+                term.name,
+                fmt_score(term.white),
+                fmt_score(term.black),
+                fmt_score(term.total())
+            )?;
+        }
+        writeln!(f, "{}", separator)?;
+        writeln!(
+            f,
+            " {:>15} |               |               | {}",
+            "Total",
+            fmt_score(self.total())
+        )?;
+        writeln!(f)?;
+        if let Some(endgame) = self.endgame {
+            writeln!(f, " Endgame: {}", endgame)?;
+        }
+        if self.scale_factor != SCALE_FACTOR_NORMAL {
+            writeln!(
+                f,
+                " Endgame scale factor: {}/{}",
+                self.scale_factor, SCALE_FACTOR_NORMAL
+            )?;
+        }
+        writeln!(f, " Phase: {}/{}", self.phase, PHASE_MAX)?;
+        write!(
+            f,
+            " Final evaluation: {:+.2} (white side)",
+            self.score as f64 / 100.0
+        )
+    }
+}
+
+#[inline(always)]
+fn get_piece_pos<P: PlayerMarker>(state: &GameState, piece: Piece) -> Grid {
+    match piece {
+        Piece::Pawn => state.piece_grid.get_pawn_pos::<P>(),
+        Piece::Knight => state.piece_grid.get_knight_pos::<P>(),
+        Piece::Bishop => state.piece_grid.get_bishop_pos::<P>(),
+        Piece::Rook => state.piece_grid.get_rook_pos::<P>(),
+        Piece::Queen => state.piece_grid.get_queen_pos::<P>(),
+        Piece::King => state.piece_grid.get_king_pos::<P>(),
+    }
+}
+
+/// (material, piece-square) scores of one piece type for one side
+fn trace_piece<P: PlayerMarker>(state: &GameState, piece: Piece) -> (TaperedScore, TaperedScore) {
+    let modify_pos = |pos: u8| {
+        let pos = match P::PLAYER {
+            Player::White => flip_pos(pos),
+            Player::Black => pos,
+        };
+        ((pos & !0b111) | (7 - (pos & 0b111))) as usize
+    };
+    let index = piece as usize;
+    let pieces = get_piece_pos::<P>(state, piece);
+
+    // kings are always on the board, so their material is left out of the trace
+    let material = if piece == Piece::King {
+        TaperedScore::default()
+    } else {
+        TaperedScore {
+            mg: MG_PIECE_VAL[index] * pieces.num_pieces() as i32,
+            eg: EG_PIECE_VAL[index] * pieces.num_pieces() as i32,
+        }
+    };
+    let pst = pieces
+        .into_iter()
+        .map(|pos| TaperedScore {
+            mg: MG_SQUARE_TABLE[index][modify_pos(pos)],
+            eg: EG_SQUARE_TABLE[index][modify_pos(pos)],
+        })
+        .fold(TaperedScore::default(), |acc, x| acc + x);
+    (material, pst)
+}
+
+/// same evaluation as `evaluate`, broken down into material and per-piece PST terms
+pub fn evaluate_with_trace(state: &GameState) -> EvalTrace {
+    let phase = ((calc_phase::<WhiteMarker>(state) + calc_phase::<BlackMarker>(state)) as i32)
+        .min(PHASE_MAX);
+
+    let material_count = MaterialCount::new(state);
+    if let Some(EndgameEval::Exact { name, value }) = endgame::probe(state, &material_count) {
+        let value = TaperedScore {
+            mg: value / PHASE_MAX,
+            eg: value / PHASE_MAX,
+        };
+        let term = EvalTerm {
+            name: format!("Endgame {}", name),
+            white: value,
+            black: TaperedScore::default(),
+        };
+        let mut trace = EvalTrace::from_terms("pesto", vec![term], phase);
+        trace.endgame = Some(name);
+        return trace;
+    }
+
+    let mut material = EvalTerm {
+        name: "Material".to_owned(),
+        white: TaperedScore::default(),
+        black: TaperedScore::default(),
+    };
+    let mut terms = vec![];
+    for index in 0..NUM_PIECES {
+        let piece = Piece::from(index);
+        let (white_material, white_pst) = trace_piece::<WhiteMarker>(state, piece);
+        let (black_material, black_pst) = trace_piece::<BlackMarker>(state, piece);
+        material.white = material.white + white_material;
+        material.black = material.black + black_material;
+
+        let mut name = format!("{} PST", piece);
+        name[..1].make_ascii_uppercase();
+        terms.push(EvalTerm {
+            name,
+            white: white_pst,
+            black: black_pst,
+        });
+    }
+    terms.insert(0, material);
+
+    let trace = EvalTrace::from_terms("pesto", terms, phase);
+    match endgame::scale_factor(state, &material_count, strong_side(trace.total().eg)) {
+        Some(EndgameEval::Scaled { name, factor }) => trace.with_scale_factor(name, factor),
+        _ => trace,
+    }
 }
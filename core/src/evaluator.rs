@@ -0,0 +1,102 @@
+use crate::{
+    config::PIECE_SCORES,
+    eval::{self, EvalTerm, EvalTrace, TaperedScore, PHASE_MAX},
+    markers::{BlackMarker, PlayerMarker, WhiteMarker},
+    types::Move,
+    GameState,
+};
+
+/// Static evaluation used by the search.
+///
+/// Scores are from white's perspective, in the same units as `eval::evaluate`:
+/// centipawns multiplied by `eval::PHASE_MAX`. The search negates the score for black.
+pub trait Evaluator: Send {
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&self, state: &GameState) -> i32;
+
+    /// per-term breakdown of `evaluate`. evaluators without terms of their own report a
+    /// single untapered total.
+    fn evaluate_with_trace(&self, state: &GameState) -> EvalTrace {
+        let score = self.evaluate(state) / PHASE_MAX;
+        let total = TaperedScore {
+            mg: score,
+            eg: score,
+        };
+        let term = EvalTerm {
+            name: "Total".to_owned(),
+            white: total,
+            black: TaperedScore::default(),
+        };
+        EvalTrace::from_terms(self.name(), vec![term], PHASE_MAX)
+    }
+
+    /// called once with the root position before each search. evaluators that keep
+    /// incremental state should rebuild it from scratch here.
+    fn reset(&mut self, _state: &GameState) {}
+
+    /// called with the position before `mov` is played on it
+    fn make_move(&mut self, _state: &GameState, _mov: Move) {}
+
+    /// called with the position after `mov` has been taken back, i.e. the same position
+    /// that was passed to the matching `make_move`
+    fn unmake_move(&mut self, _state: &GameState, _mov: Move) {}
+}
+
+/// tapered PeSTO piece-square evaluation. this is the default.
+#[derive(Default)]
+pub struct PestoEvaluator;
+
+impl Evaluator for PestoEvaluator {
+    fn name(&self) -> &'static str {
+        "pesto"
+    }
+
+    #[inline(always)]
+    fn evaluate(&self, state: &GameState) -> i32 {
+        eval::evaluate(state)
+    }
+
+    fn evaluate_with_trace(&self, state: &GameState) -> EvalTrace {
+        eval::evaluate_with_trace(state)
+    }
+}
+
+/// counts material only, using `PIECE_SCORES`. useful for debugging the search.
+#[derive(Default)]
+pub struct MaterialEvaluator;
+
+impl MaterialEvaluator {
+    const PAWN_UNITS: i32 = 100 * PHASE_MAX;
+
+    fn material<P: PlayerMarker>(state: &GameState) -> i32 {
+        // kings are always on the board, so they are left out
+        (state.piece_grid.get_pawn_pos::<P>().num_pieces() as i32 * PIECE_SCORES[0]
+            + state.piece_grid.get_knight_pos::<P>().num_pieces() as i32 * PIECE_SCORES[1]
+            + state.piece_grid.get_bishop_pos::<P>().num_pieces() as i32 * PIECE_SCORES[2]
+            + state.piece_grid.get_rook_pos::<P>().num_pieces() as i32 * PIECE_SCORES[3]
+            + state.piece_grid.get_queen_pos::<P>().num_pieces() as i32 * PIECE_SCORES[4])
+            * Self::PAWN_UNITS
+    }
+}
+
+impl Evaluator for MaterialEvaluator {
+    fn name(&self) -> &'static str {
+        "material"
+    }
+
+    fn evaluate(&self, state: &GameState) -> i32 {
+        Self::material::<WhiteMarker>(state) - Self::material::<BlackMarker>(state)
+    }
+}
+
+pub const EVALUATOR_NAMES: [&str; 2] = ["pesto", "material"];
+
+/// used by the front ends to pick an evaluator at startup
+pub fn evaluator_from_name(name: &str) -> Option<Box<dyn Evaluator>> {
+    match name.to_lowercase().as_str() {
+        "pesto" => Some(Box::new(PestoEvaluator)),
+        "material" => Some(Box::new(MaterialEvaluator)),
+        _ => None,
+    }
+}
@@ -0,0 +1,491 @@
+use std::fmt::Display;
+
+use crate::{
+    game_data::Metadata, player::Player, square::Square, types::Move, types_for_io::Piece,
+    variant::Variant, zoborist_state::ZoboristState, GameState,
+};
+
+/// why a move given from outside the engine was rejected
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    /// the text is not a move in the expected notation
+    InvalidNotation,
+    /// there is no piece on the starting square
+    NoPiece(Square),
+    /// the piece on the starting square belongs to the side not to move
+    OpponentPiece(Square),
+    /// a promotion piece is missing, is not allowed, or was given for a move that does not
+    /// promote
+    InvalidPromotion,
+    /// the SAN matches more than one legal move
+    Ambiguous,
+    /// the move is not possible in the position, e.g. it leaves the king in check
+    Illegal,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::InvalidNotation => f.write_str("invalid move notation"),
+            MoveError::NoPiece(square) => write!(f, "no piece on {}", square),
+            MoveError::OpponentPiece(square) => write!(f, "the piece on {} belongs to the opponent", square),
+            MoveError::InvalidPromotion => f.write_str("invalid promotion"),
+            MoveError::Ambiguous => f.write_str("ambiguous move"),
+            MoveError::Illegal => f.write_str("illegal move"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// how a game ended, see `Game::outcome`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Checkmate { winner: Player },
+    /// the side to move lost by a rule of the variant, e.g. the opponent's king reached the
+    /// centre in king of the hill
+    VariantWin { winner: Player },
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+}
+
+impl Outcome {
+    /// none for a draw
+    pub fn winner(self) -> Option<Player> {
+        match self {
+            Outcome::Checkmate { winner } | Outcome::VariantWin { winner } => Some(winner),
+            _ => None,
+        }
+    }
+
+    /// the result tag of a PGN, e.g. `1-0`
+    pub fn pgn_result(self) -> &'static str {
+        match self.winner() {
+            Some(Player::White) => "1-0",
+            Some(Player::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Checkmate { winner } => write!(f, "checkmate, {:?} wins", winner),
+            Outcome::VariantWin { winner } => write!(f, "{:?} wins by the rules of the variant", winner),
+            Outcome::Stalemate => f.write_str("stalemate"),
+            Outcome::Repetition => f.write_str("draw by threefold repetition"),
+            Outcome::FiftyMoves => f.write_str("draw by the fifty-move rule"),
+            Outcome::InsufficientMaterial => f.write_str("draw by insufficient material"),
+        }
+    }
+}
+
+/// a game played from a start position, for moves coming from outside the engine. every
+/// move is checked against the legal moves before it is made, and can be taken back.
+#[derive(Clone)]
+pub struct Game {
+    state: GameState,
+    zoborist_state: ZoboristState,
+    // each move with the metadata from before it, which `unmake_move` needs
+    history: Vec<(Move, Metadata)>,
+}
+
+impl Game {
+    pub fn new(zoborist_state: &ZoboristState) -> Self {
+        Self::new_variant(Variant::Standard, zoborist_state)
+    }
+
+    pub fn new_variant(variant: Variant, zoborist_state: &ZoboristState) -> Self {
+        Self::from_state(GameState::new_variant(variant, zoborist_state), zoborist_state)
+    }
+
+    pub fn from_fen(fen: &str, zoborist_state: &ZoboristState) -> Result<Self, &'static str> {
+        Self::from_fen_with_variant(fen, Variant::Standard, zoborist_state)
+    }
+
+    pub fn from_fen_with_variant(
+        fen: &str,
+        variant: Variant,
+        zoborist_state: &ZoboristState,
+    ) -> Result<Self, &'static str> {
+        let state = GameState::new_from_fen_with_variant(fen, variant, zoborist_state)?;
+        Ok(Self::from_state(state, zoborist_state))
+    }
+
+    /// `state` must have been hashed with `zoborist_state`
+    pub fn from_state(state: GameState, zoborist_state: &ZoboristState) -> Self {
+        Self {
+            state,
+            zoborist_state: zoborist_state.clone(),
+            history: vec![],
+        }
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    /// moves played so far, oldest first
+    pub fn moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.history.iter().map(|&(mov, _)| mov)
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.state.legal_moves()
+    }
+
+    /// makes `mov` if it is legal in the current position
+    pub fn play(&mut self, mov: Move) -> Result<(), MoveError> {
+        if !self.state.legal_moves().contains(&mov) {
+            return Err(MoveError::Illegal);
+        }
+        self.make(mov);
+        Ok(())
+    }
+
+    /// makes a move in long algebraic notation, e.g. `e2e4` or `e7e8q`. castling may be
+    /// written as the king's two-square move or as the king taking its own rook.
+    pub fn play_uci(&mut self, text: &str) -> Result<Move, MoveError> {
+        let mov = parse_uci(&self.state, text)?;
+        self.make(mov);
+        Ok(mov)
+    }
+
+    /// makes a move in standard algebraic notation, e.g. `Nf3`, `exd5`, `e8=Q` or `O-O`
+    pub fn play_san(&mut self, text: &str) -> Result<Move, MoveError> {
+        let mov = parse_san(&self.state, text)?;
+        self.make(mov);
+        Ok(mov)
+    }
+
+    /// takes back the last move, returning it. `None` if no moves were played.
+    pub fn undo(&mut self) -> Option<Move> {
+        let (mov, metadata) = self.history.pop()?;
+        self.state.unmake_move(mov, metadata, &self.zoborist_state);
+        Some(mov)
+    }
+
+    /// the position the game started from
+    pub fn start_state(&self) -> GameState {
+        let mut game = self.clone();
+        while game.undo().is_some() {}
+        game.state
+    }
+
+    /// `mov`, a legal move in the current position, in standard algebraic notation, e.g.
+    /// `Nbd7`, `exd5`, `e8=Q+` or `O-O#`
+    pub fn move_to_san(&self, mov: Move) -> String {
+        let state = &self.state;
+        let (from, to) = move_squares(state, mov);
+        let mut san = match mov {
+            Move::Castle { is_short: true } => "O-O".to_owned(),
+            Move::Castle { is_short: false } => "O-O-O".to_owned(),
+            _ => {
+                let piece = moved_piece(mov);
+                let mut san = String::new();
+                if piece != Piece::Pawn {
+                    san.push(piece.to_char(Player::White));
+                    // where the other pieces of the kind that can move to `to` stand
+                    let others: Vec<Square> = state
+                        .legal_moves()
+                        .into_iter()
+                        .filter(|&other| {
+                            other != mov
+                                && !matches!(other, Move::Castle { .. })
+                                && moved_piece(other) == piece
+                                && move_squares(state, other).1 == to
+                        })
+                        .map(|other| move_squares(state, other).0)
+                        .collect();
+                    if others.iter().any(|square| square.file() == from.file()) {
+                        if others.iter().any(|square| square.rank() == from.rank()) {
+                            san.push((b'a' + from.file()) as char);
+                        }
+                        san.push((b'1' + from.rank()) as char);
+                    } else if !others.is_empty() {
+                        san.push((b'a' + from.file()) as char);
+                    }
+                }
+                if is_capture(state, mov) {
+                    if piece == Piece::Pawn {
+                        san.push((b'a' + from.file()) as char);
+                    }
+                    san.push('x');
+                }
+                san.push_str(&to.to_string());
+                if let Some(promoted) = promotion(mov) {
+                    san.push('=');
+                    san.push(promoted.to_char(Player::White));
+                }
+                san
+            }
+        };
+        let mut after = state.clone();
+        after.make_move(mov, &self.zoborist_state);
+        if after.is_in_check() {
+            san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        san
+    }
+
+    /// how the game ended, if it did
+    pub fn outcome(&self) -> Option<Outcome> {
+        let state = &self.state;
+        if state.is_variant_loss() {
+            return Some(Outcome::VariantWin {
+                winner: state.player.opp(),
+            });
+        }
+        if state.legal_moves().is_empty() {
+            return Some(if state.is_in_check() {
+                Outcome::Checkmate {
+                    winner: state.player.opp(),
+                }
+            } else {
+                Outcome::Stalemate
+            });
+        }
+
+        let mut game = self.clone();
+        let mut repetitions = 1;
+        while game.undo().is_some() {
+            if game.state.hash == state.hash {
+                repetitions += 1;
+            }
+        }
+        if repetitions >= 3 {
+            Some(Outcome::Repetition)
+        } else if state.metadata.get_halfmove_clock() >= 100 {
+            Some(Outcome::FiftyMoves)
+        } else if state.variant == Variant::Standard && is_insufficient_material(state) {
+            Some(Outcome::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
+    fn make(&mut self, mov: Move) {
+        let metadata = self.state.make_move(mov, &self.zoborist_state);
+        self.history.push((mov, metadata));
+    }
+}
+
+impl GameState {
+    /// `mov` in long algebraic notation as `Game::play_uci` reads it, e.g. `e2e4` or
+    /// `e7e8q`, with castling as the king's two-square move
+    pub fn move_to_uci(&self, mov: Move) -> String {
+        let (from, to) = move_squares(self, mov);
+        match promotion(mov) {
+            Some(piece) => format!("{}{}{}", from, to, piece.to_char(Player::Black)),
+            None => format!("{}{}", from, to),
+        }
+    }
+}
+
+/// the squares a move is written with in long algebraic notation, with castling as the
+/// king's move to its destination
+pub(crate) fn move_squares(state: &GameState, mov: Move) -> (Square, Square) {
+    let (prev_pos, new_pos) = match mov {
+        Move::Move { prev_pos, new_pos, .. } | Move::PawnPromote { prev_pos, new_pos, .. } => {
+            (prev_pos, new_pos)
+        }
+        Move::Castle { is_short } => {
+            let (king_from, king_to, _, _) = state.castling.squares(state.player, is_short);
+            (king_from, king_to)
+        }
+        Move::EnPassant { prev_column, new_column } => match state.player {
+            Player::White => (32 + prev_column, 40 + new_column),
+            Player::Black => (24 + prev_column, 16 + new_column),
+        },
+    };
+    (Square::from_pos_unchecked(prev_pos), Square::from_pos_unchecked(new_pos))
+}
+
+fn moved_piece(mov: Move) -> Piece {
+    match mov {
+        Move::Move { pieces, .. } => {
+            pieces.to_square_types().0.to_piece_for_io().expect("cannot be empty")
+        }
+        Move::PawnPromote { .. } | Move::EnPassant { .. } => Piece::Pawn,
+        Move::Castle { .. } => Piece::King,
+    }
+}
+
+fn is_capture(state: &GameState, mov: Move) -> bool {
+    match mov {
+        Move::Move { new_pos, .. } | Move::PawnPromote { new_pos, .. } => {
+            state.piece_at(Square::from_pos_unchecked(new_pos)).is_some()
+        }
+        Move::EnPassant { .. } => true,
+        Move::Castle { .. } => false,
+    }
+}
+
+/// neither side can mate: bare kings, a single minor piece, or only bishops on squares
+/// of one colour
+fn is_insufficient_material(state: &GameState) -> bool {
+    let both = |piece| state.pieces(Player::White, piece) | state.pieces(Player::Black, piece);
+    if [Piece::Pawn, Piece::Rook, Piece::Queen]
+        .iter()
+        .any(|&piece| !both(piece).is_empty())
+    {
+        return false;
+    }
+    let knights = both(Piece::Knight).into_iter().len();
+    let mut bishop_colours = both(Piece::Bishop)
+        .into_iter()
+        .map(|square| (square.file() + square.rank()) % 2);
+    let bishops = bishop_colours.len();
+    match bishop_colours.next() {
+        None => knights <= 1,
+        Some(colour) => knights == 0 && (bishops == 1 || bishop_colours.all(|other| other == colour)),
+    }
+}
+
+fn promotion(mov: Move) -> Option<Piece> {
+    match mov {
+        Move::PawnPromote { pieces, .. } => pieces.to_square_types().0.to_piece_for_io(),
+        _ => None,
+    }
+}
+
+/// the legal move from `from` to `to`, checking the piece on `from` and the promotion
+pub(crate) fn find_move(
+    state: &GameState,
+    from: Square,
+    to: Square,
+    promoted_to_piece: Option<Piece>,
+) -> Result<Move, MoveError> {
+    match state.piece_at(from) {
+        None => return Err(MoveError::NoPiece(from)),
+        Some((player, _)) if player != state.player => return Err(MoveError::OpponentPiece(from)),
+        _ => {}
+    }
+    if matches!(promoted_to_piece, Some(Piece::Pawn | Piece::King)) {
+        return Err(MoveError::InvalidPromotion);
+    }
+    let candidates: Vec<Move> = state
+        .legal_moves()
+        .into_iter()
+        .filter(|&mov| {
+            let (prev, new) = move_squares(state, mov);
+            prev == from
+                && match mov {
+                    // the king's destination is only unambiguous with classical rook columns
+                    Move::Castle { is_short } => {
+                        to.pos() == state.castling.rook_pos(state.player, is_short)
+                            || (new == to && state.castling.is_classical())
+                    }
+                    _ => new == to,
+                }
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Err(MoveError::Illegal);
+    }
+    candidates
+        .into_iter()
+        .find(|&mov| promotion(mov) == promoted_to_piece)
+        .ok_or(MoveError::InvalidPromotion)
+}
+
+fn parse_uci(state: &GameState, text: &str) -> Result<Move, MoveError> {
+    let text = text.trim();
+    if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+        return Err(MoveError::InvalidNotation);
+    }
+    let from = text[0..2].parse::<Square>().map_err(|_| MoveError::InvalidNotation)?;
+    let to = text[2..4].parse::<Square>().map_err(|_| MoveError::InvalidNotation)?;
+    let promoted_to_piece = match text[4..].chars().next() {
+        None => None,
+        Some(c) => Some(Piece::try_from(c).map_err(|_| MoveError::InvalidNotation)?),
+    };
+    find_move(state, from, to, promoted_to_piece)
+}
+
+fn parse_san(state: &GameState, text: &str) -> Result<Move, MoveError> {
+    let san = text.trim().trim_end_matches(['+', '#', '!', '?']);
+    if !san.is_ascii() || san.is_empty() {
+        return Err(MoveError::InvalidNotation);
+    }
+    let legal = state.legal_moves();
+
+    let castle = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(is_short) = castle {
+        let mov = Move::Castle { is_short };
+        return if legal.contains(&mov) {
+            Ok(mov)
+        } else {
+            Err(MoveError::Illegal)
+        };
+    }
+
+    // piece letter, then the rest is [from file][from rank][x]to[=promotion]
+    let (piece, rest) = match san.as_bytes()[0] {
+        b'N' | b'B' | b'R' | b'Q' | b'K' => {
+            (Piece::try_from(san.as_bytes()[0] as char).unwrap(), &san[1..])
+        }
+        _ => (Piece::Pawn, san),
+    };
+    if rest.is_empty() {
+        return Err(MoveError::InvalidNotation);
+    }
+    // the promotion piece is the last character, with or without a `=` before it
+    let (rest, promoted_to_piece) = match rest.as_bytes()[rest.len() - 1] {
+        c if c.is_ascii_uppercase() => {
+            let promoted = Piece::try_from(c as char).map_err(|_| MoveError::InvalidNotation)?;
+            if matches!(promoted, Piece::Pawn | Piece::King) {
+                return Err(MoveError::InvalidPromotion);
+            }
+            (rest[..rest.len() - 1].trim_end_matches('='), Some(promoted))
+        }
+        _ => (rest, None),
+    };
+    if rest.len() < 2 {
+        return Err(MoveError::InvalidNotation);
+    }
+    let to = rest[rest.len() - 2..].parse::<Square>().map_err(|_| MoveError::InvalidNotation)?;
+    let disambiguation = rest[..rest.len() - 2].trim_end_matches('x');
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in disambiguation.bytes() {
+        match c {
+            b'a'..=b'h' if from_file.is_none() => from_file = Some(c - b'a'),
+            b'1'..=b'8' if from_rank.is_none() => from_rank = Some(c - b'1'),
+            _ => return Err(MoveError::InvalidNotation),
+        }
+    }
+    if piece != Piece::Pawn && promoted_to_piece.is_some() {
+        return Err(MoveError::InvalidPromotion);
+    }
+
+    let candidates: Vec<Move> = legal
+        .into_iter()
+        .filter(|&mov| {
+            let (from, new) = move_squares(state, mov);
+            !matches!(mov, Move::Castle { .. })
+                && moved_piece(mov) == piece
+                && new == to
+                && from_file.is_none_or(|file| from.file() == file)
+                && from_rank.is_none_or(|rank| from.rank() == rank)
+        })
+        .collect();
+    let matching: Vec<Move> = candidates
+        .iter()
+        .copied()
+        .filter(|&mov| promotion(mov) == promoted_to_piece)
+        .collect();
+    match matching.as_slice() {
+        [mov] => Ok(*mov),
+        [] if candidates.is_empty() => Err(MoveError::Illegal),
+        [] => Err(MoveError::InvalidPromotion),
+        _ => Err(MoveError::Ambiguous),
+    }
+}
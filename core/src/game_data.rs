@@ -1,10 +1,16 @@
 use serde::Deserialize;
 
+use crate::attacks;
+use crate::bitboard::Bitboard;
 use crate::grid::PieceGrid;
-use crate::markers::{player_to_marker, CastleTypeMarker, PlayerMarker};
+use crate::markers::{player_to_marker, BlackMarker, CastleTypeMarker, PlayerMarker, WhiteMarker};
+use crate::move_buffer::MoveBuffer;
+use crate::movegen::{King, Pawn};
 use crate::player::Player;
+use crate::square::Square;
 use crate::square_type::SquareType;
 use crate::types_for_io::Piece;
+use crate::variant::Variant;
 use crate::zoborist_state::ZoboristState;
 use crate::{config::HashType, grid::Grid, types::Move, util::coord_to_pos};
 use std::hash::Hash;
@@ -25,8 +31,10 @@ use std::hash::Hash;
 //     }
 // }
 
+// bits 0-3: castling rights, 4-7: en passant column, 8-9 and 10-11: checks given by
+// white and black, only used by three-check, 16-23: halfmove clock
 #[derive(PartialEq, Eq, Clone, Copy, Deserialize)]
-pub struct Metadata(u8);
+pub struct Metadata(u32);
 
 impl Default for Metadata {
     fn default() -> Self {
@@ -39,7 +47,7 @@ impl Metadata {
     pub const NO_EN_PASSANT: u8 = 8;
 
     #[inline(always)]
-    const fn castle_bits<P: PlayerMarker, C: CastleTypeMarker>() -> u8 {
+    const fn castle_bits<P: PlayerMarker, C: CastleTypeMarker>() -> u32 {
         if C::IS_SHORT {
             if P::IS_WHITE {
                 0b1
@@ -70,9 +78,9 @@ impl Metadata {
     }
 
     #[inline(always)]
-    fn castle_bits_dynamic(player: Player, is_short: bool) -> u8 {
-        let is_short = !is_short as u8;
-        ((1 << is_short) << is_short) << player as u8
+    fn castle_bits_dynamic(player: Player, is_short: bool) -> u32 {
+        let is_short = !is_short as u32;
+        ((1 << is_short) << is_short) << player as u32
     }
 
     #[inline(always)]
@@ -91,18 +99,155 @@ impl Metadata {
 
     #[inline(always)]
     pub fn get_en_passant_column(self) -> u8 {
-        self.0 >> 4
+        ((self.0 >> 4) & 0b1111) as u8
     }
 
     #[inline(always)]
     pub fn set_en_passant_column(&mut self, en_passant_column: u8) {
-        self.0 = (self.0 & 0b1111) | (en_passant_column << 4)
+        self.0 = (self.0 & !0b1111_0000) | ((en_passant_column as u32) << 4)
+    }
+
+    #[inline(always)]
+    pub fn get_checks_given(self, player: Player) -> u8 {
+        ((self.0 >> (8 + 2 * player as u32)) & 0b11) as u8
+    }
+
+    #[inline(always)]
+    pub fn set_checks_given(&mut self, player: Player, checks: u8) {
+        let shift = 8 + 2 * player as u32;
+        self.0 = (self.0 & !(0b11 << shift)) | ((checks.min(3) as u32) << shift)
+    }
+
+    /// plies since the last capture or pawn move, up to 255
+    #[inline(always)]
+    pub fn get_halfmove_clock(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    #[inline(always)]
+    pub fn set_halfmove_clock(&mut self, plies: u8) {
+        self.0 = (self.0 & !(0xff << 16)) | ((plies as u32) << 16)
     }
 
     #[inline(always)]
     pub fn get_meta_hash(self, zoborist_state: &ZoboristState) -> HashType {
         zoborist_state.castle[(self.0 & 0b1111) as usize]
-            ^ zoborist_state.en_passant[(self.0 >> 4) as usize]
+            ^ zoborist_state.en_passant[((self.0 >> 4) & 0b1111) as usize]
+            ^ zoborist_state.checks[((self.0 >> 8) & 0b1111) as usize]
+    }
+}
+
+/// start files of the castling king and rooks. classical chess has the king on the e-file
+/// and the rooks on the a- and h-files, chess960 positions may use any files.
+///
+/// columns are engine columns, i.e. the h-file is column 0. short castling is towards the
+/// h-file: the king ends up on the g-file and the rook on the f-file. long castling puts
+/// the king on the c-file and the rook on the d-file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Castling {
+    /// [player]
+    king_column: [u8; 2],
+    /// [player][is_short]
+    rook_column: [[u8; 2]; 2],
+    /// squares that must be empty, apart from the castling king and rook. [player][is_short]
+    free: [[Grid; 2]; 2],
+    /// squares the king passes through, including its start and end squares. [player][is_short]
+    safe: [[Grid; 2]; 2],
+}
+
+impl Default for Castling {
+    fn default() -> Self {
+        let mut res = Self {
+            king_column: [3; 2],
+            rook_column: [[7, 0]; 2],
+            free: Default::default(),
+            safe: Default::default(),
+        };
+        for player in [Player::White, Player::Black] {
+            res.update_masks(player, true);
+            res.update_masks(player, false);
+        }
+        res
+    }
+}
+
+impl Castling {
+    const SHORT_KING_COLUMN: u8 = 1;
+    const SHORT_ROOK_COLUMN: u8 = 2;
+    const LONG_KING_COLUMN: u8 = 5;
+    const LONG_ROOK_COLUMN: u8 = 4;
+
+    #[inline(always)]
+    fn home_row_offset(player: Player) -> u8 {
+        player as u8 * 56
+    }
+
+    /// squares between the two columns on the player's home row, both included
+    fn span(player: Player, a: u8, b: u8) -> Grid {
+        let offset = Self::home_row_offset(player);
+        (a.min(b)..=a.max(b)).fold(Grid::EMPTY, |grid, column| {
+            grid | Grid::from_pos(offset + column)
+        })
+    }
+
+    fn update_masks(&mut self, player: Player, is_short: bool) {
+        let (king_from, king_to, rook_from, rook_to) = self.squares(player, is_short);
+        let offset = Self::home_row_offset(player);
+        let (king_from, king_to) = (king_from - offset, king_to - offset);
+        let (rook_from, rook_to) = (rook_from - offset, rook_to - offset);
+
+        let pieces = Grid::from_pos(king_from + offset) | Grid::from_pos(rook_from + offset);
+        self.free[player as usize][is_short as usize] = (Self::span(player, king_from, king_to)
+            | Self::span(player, rook_from, rook_to))
+            & !pieces;
+        self.safe[player as usize][is_short as usize] = Self::span(player, king_from, king_to);
+    }
+
+    fn set_rook(&mut self, player: Player, is_short: bool, king_column: u8, rook_column: u8) {
+        self.king_column[player as usize] = king_column;
+        self.rook_column[player as usize][is_short as usize] = rook_column;
+        self.update_masks(player, is_short);
+        self.update_masks(player, !is_short);
+    }
+
+    /// (king from, king to, rook from, rook to)
+    #[inline(always)]
+    pub fn squares(&self, player: Player, is_short: bool) -> (u8, u8, u8, u8) {
+        let offset = Self::home_row_offset(player);
+        let (king_to, rook_to) = if is_short {
+            (Self::SHORT_KING_COLUMN, Self::SHORT_ROOK_COLUMN)
+        } else {
+            (Self::LONG_KING_COLUMN, Self::LONG_ROOK_COLUMN)
+        };
+        (
+            self.king_column[player as usize] + offset,
+            king_to + offset,
+            self.rook_column[player as usize][is_short as usize] + offset,
+            rook_to + offset,
+        )
+    }
+
+    #[inline(always)]
+    pub fn rook_pos(&self, player: Player, is_short: bool) -> u8 {
+        self.rook_column[player as usize][is_short as usize] + Self::home_row_offset(player)
+    }
+
+    /// squares that must be empty, apart from the castling king and rook
+    #[inline(always)]
+    pub fn free_grid(&self, player: Player, is_short: bool) -> Grid {
+        self.free[player as usize][is_short as usize]
+    }
+
+    /// squares the king passes through, which must not be attacked
+    #[inline(always)]
+    pub fn safe_grid(&self, player: Player, is_short: bool) -> Grid {
+        self.safe[player as usize][is_short as usize]
+    }
+
+    /// true if the pieces start on the classical files, so castling can be written as a
+    /// two-square king move
+    pub fn is_classical(&self) -> bool {
+        *self == Self::default()
     }
 }
 
@@ -113,6 +258,13 @@ pub struct GameState {
     pub player: Player,
     #[serde(default)]
     pub hash: HashType,
+    #[serde(skip)]
+    pub castling: Castling,
+    #[serde(skip)]
+    pub variant: Variant,
+    /// starts at 1 and goes up after black's move
+    #[serde(skip, default = "GameState::first_move_number")]
+    pub fullmove_number: u16,
 }
 
 impl Hash for GameState {
@@ -146,6 +298,9 @@ impl Default for GameState {
             metadata: Metadata::default(),
             player: Player::White,
             hash: 0,
+            castling: Castling::default(),
+            variant: Variant::Standard,
+            fullmove_number: 1,
         }
     }
 }
@@ -158,7 +313,17 @@ impl GameState {
     }
 
     // todo: maybe relax legality checking for performance
-    pub fn check_move_legal(&self, mov: Move) -> bool {
+    /// true if `mov` is one the move generator would produce in this position, i.e. it is
+    /// legal apart from possibly leaving the mover's king attacked. moves that do not come
+    /// from the generator, like the transposition table's, must pass this before being made.
+    pub fn is_pseudo_legal(&self, mov: Move) -> bool {
+        player_to_marker!(self.player, { self.is_pseudo_legal_for::<P>(mov) })
+    }
+
+    #[inline(always)]
+    fn is_pseudo_legal_for<P: PlayerMarker>(&self, mov: Move) -> bool {
+        let empty = self.piece_grid.get_empty_squares();
+        let opp = self.piece_grid.get_opp_player_pieces::<P>();
         match mov {
             Move::Move {
                 prev_pos,
@@ -166,34 +331,78 @@ impl GameState {
                 pieces,
             } => {
                 let (piece, captured_piece) = pieces.to_square_types();
-                self.piece_grid
-                    .get_squares_of_type(piece)
-                    .all_squares_occupied(Grid::from_pos(prev_pos))
-                    && self
-                        .piece_grid
-                        .get_squares_of_type(captured_piece)
-                        .all_squares_occupied(Grid::from_pos(new_pos))
-            }
-            Move::Castle { is_short } => {
-                self.metadata.get_can_castle_dynamic(self.player, is_short)
+                if prev_pos >= 64
+                    || new_pos >= 64
+                    || piece.is_empty()
+                    || piece.get_d() != P::PLAYER as u8
+                    || self.piece_grid.get_square_type(prev_pos) != piece
+                    || self.piece_grid.get_square_type(new_pos) != captured_piece
+                {
+                    return false;
+                }
+                let own = self.piece_grid.get_player_pieces::<P>();
+                let occupied = self.piece_grid.get_all_pieces();
+                let grid = Grid::from_pos(prev_pos);
+                let targets = match piece.to_piece_for_io() {
+                    Some(Piece::Pawn) => {
+                        let mut targets = Pawn::regular_moves_and_captures::<P>(grid, empty, opp)
+                            | Pawn::double_moves::<P>(grid, empty);
+                        if self.variant.allows_back_rank_double_moves(P::PLAYER) {
+                            targets |= Pawn::back_rank_double_moves::<P>(grid, empty);
+                        }
+                        targets
+                    }
+                    Some(Piece::Knight) => attacks::knight_attacks(prev_pos) & !own,
+                    Some(Piece::Bishop) => attacks::bishop_attacks(prev_pos, occupied) & !own,
+                    Some(Piece::Rook) => attacks::rook_attacks(prev_pos, occupied) & !own,
+                    Some(Piece::Queen) => attacks::queen_attacks(prev_pos, occupied) & !own,
+                    Some(Piece::King) => attacks::king_attacks(prev_pos) & !own,
+                    None => Grid::EMPTY,
+                };
+                targets.all_squares_occupied(Grid::from_pos(new_pos))
             }
             Move::PawnPromote {
                 prev_pos,
                 new_pos,
                 pieces,
-                ..
             } => {
-                let (_, captured_piece) = pieces.to_square_types();
-                player_to_marker!(self.player, { self.piece_grid.get_pawn_pos::<P>() })
-                    .all_squares_occupied(Grid::from_pos(prev_pos))
-                    && self
-                        .piece_grid
-                        .get_squares_of_type(captured_piece)
+                let (promoted_to_piece, captured_piece) = pieces.to_square_types();
+                prev_pos < 64
+                    && new_pos < 64
+                    && [
+                        SquareType::knight(P::PLAYER),
+                        SquareType::bishop(P::PLAYER),
+                        SquareType::rook(P::PLAYER),
+                        SquareType::queen(P::PLAYER),
+                    ]
+                    .contains(&promoted_to_piece)
+                    && self.piece_grid.get_square_type(prev_pos) == SquareType::pawn(P::PLAYER)
+                    && self.piece_grid.get_square_type(new_pos) == captured_piece
+                    && Pawn::promotions::<P>(Grid::from_pos(prev_pos), empty, opp)
                         .all_squares_occupied(Grid::from_pos(new_pos))
             }
-            // todo: maybe make this better
-            Move::EnPassant { new_column, .. } => {
-                self.metadata.get_en_passant_column() == new_column
+            Move::EnPassant {
+                prev_column,
+                new_column,
+            } => {
+                let prev_pos = match P::PLAYER {
+                    Player::White => 32 + prev_column,
+                    Player::Black => 24 + prev_column,
+                };
+                new_column != Metadata::NO_EN_PASSANT
+                    && new_column == self.metadata.get_en_passant_column()
+                    && prev_column < 8
+                    && self.piece_grid.get_square_type(prev_pos) == SquareType::pawn(P::PLAYER)
+                    && Pawn::en_passant::<P>(Grid::from_pos(prev_pos), new_column, empty) != Grid::EMPTY
+            }
+            Move::Castle { is_short } => {
+                self.metadata.get_can_castle_dynamic(P::PLAYER, is_short)
+                    && King::can_castle(
+                        player_to_marker!(P::PLAYER.opp(), { MoveBuffer::get_attacked_grid::<P>(self) }),
+                        self.piece_grid.get_all_pieces(),
+                        self.castling.free_grid(P::PLAYER, is_short),
+                        self.castling.safe_grid(P::PLAYER, is_short),
+                    )
             }
         }
     }
@@ -201,6 +410,7 @@ impl GameState {
     fn apply_fen(
         piece_grid: &mut PieceGrid,
         metadata: &mut Metadata,
+        castling: &mut Castling,
         player: Player,
         fen: &str,
     ) -> Result<(), &'static str> {
@@ -210,7 +420,8 @@ impl GameState {
 
         let parts: Vec<&str> = fen.split(' ').collect();
 
-        if parts.len() != 6 {
+        // three-check fens carry the remaining checks as an extra field, e.g. "3+3"
+        if parts.len() != 6 && parts.len() != 7 {
             return Err("fen not len 6");
         }
 
@@ -226,43 +437,73 @@ impl GameState {
 
         for char in parts[0].chars() {
             if char == '/' {
+                if column != -1 || row == 0 {
+                    return Err("fen ranks must have 8 squares each");
+                }
                 row -= 1;
                 column = 7;
                 continue;
             }
-            if row < 0 || column < 0 {
-                return Err("invalid fen");
-            }
             if let Some(num) = char.to_digit(10) {
+                if !(1..=(column + 1) as u32).contains(&num) {
+                    return Err("fen ranks must have 8 squares each");
+                }
                 column -= num as i8;
                 continue;
             }
+            if column < 0 {
+                return Err("fen ranks must have 8 squares each");
+            }
 
             if char_is_valid(char) {
                 let piece = Piece::try_from(char)?;
                 let pos = coord_to_pos((row, column));
-                println!("{} {}", piece, pos);
-
                 piece_grid.apply_square(pos, SquareType::create_for_parsing(piece, player))
             }
             column -= 1
         }
+        if row != 0 || column != -1 {
+            return Err("fen must have 8 ranks of 8 squares");
+        }
 
         metadata.set_can_castle_dynamic::<false>(player, true);
         metadata.set_can_castle_dynamic::<false>(player, false);
 
-        // handle castling
+        // handle castling. KQkq pick the outermost rook on that side (X-FEN), file letters
+        // name the rook directly (Shredder-FEN)
+        let home_row = Castling::home_row_offset(player);
+        let king_column = (piece_grid.get_squares_of_type(SquareType::king(player))
+            & Grid::from_u64(0xff << home_row))
+        .into_iter()
+        .next()
+        .map(|pos| pos - home_row);
+        let rook_columns: Vec<u8> = (piece_grid.get_squares_of_type(SquareType::rook(player))
+            & Grid::from_u64(0xff << home_row))
+        .into_iter()
+        .map(|pos| pos - home_row)
+        .collect();
+
         for char in parts[2].chars() {
             if char == '-' {
                 break;
             }
-            if char_is_valid(char) {
-                match char.to_ascii_lowercase() {
-                    'k' => metadata.set_can_castle_dynamic::<true>(player, true),
-                    'q' => metadata.set_can_castle_dynamic::<true>(player, false),
-                    _ => return Err("invalid castle char"),
-                }
+            if !char_is_valid(char) {
+                continue;
+            }
+            let king_column = king_column.ok_or("castling rights without a king on its home row")?;
+            let rook_column = match char.to_ascii_lowercase() {
+                'k' => rook_columns.iter().copied().filter(|&x| x < king_column).min(),
+                'q' => rook_columns.iter().copied().filter(|&x| x > king_column).max(),
+                file @ 'a'..='h' => Some(b'h' - file as u8).filter(|x| rook_columns.contains(x)),
+                _ => return Err("invalid castle char"),
             }
+            .ok_or("castling rights without a rook to castle with")?;
+            if rook_column == king_column {
+                return Err("invalid castle char");
+            }
+            let is_short = rook_column < king_column;
+            castling.set_rook(player, is_short, king_column, rook_column);
+            metadata.set_can_castle_dynamic::<true>(player, is_short);
         }
 
         // todo: everything else
@@ -273,29 +514,231 @@ impl GameState {
     }
 
     pub fn new_from_fen(fen: &str, zoborist_state: &ZoboristState) -> Result<Self, &'static str> {
+        Self::new_from_fen_with_variant(fen, Variant::Standard, zoborist_state)
+    }
+
+    pub fn new_variant(variant: Variant, zoborist_state: &ZoboristState) -> Self {
+        Self::new_from_fen_with_variant(variant.start_fen(), variant, zoborist_state)
+            .expect("start fen is valid")
+    }
+
+    pub fn new_from_fen_with_variant(
+        fen: &str,
+        variant: Variant,
+        zoborist_state: &ZoboristState,
+    ) -> Result<Self, &'static str> {
         let parts: Vec<_> = fen.split(' ').collect();
         let mut piece_grid = PieceGrid::default();
         let mut metadata = Metadata::default();
-        Self::apply_fen(&mut piece_grid, &mut metadata, Player::White, fen)?;
-        Self::apply_fen(&mut piece_grid, &mut metadata, Player::Black, fen)?;
-        metadata.set_en_passant_column(match parts[3].chars().nth(0).ok_or("invalid fen")? {
-            '-' => 8,
-            x if ('a'..='h').contains(&x) => x as u8 - 'a' as u8,
-            _ => return Err("invalid en passant value in fen"),
+        let mut castling = Castling::default();
+        Self::apply_fen(&mut piece_grid, &mut metadata, &mut castling, Player::White, fen)?;
+        Self::apply_fen(&mut piece_grid, &mut metadata, &mut castling, Player::Black, fen)?;
+        let player = Player::try_from(parts[1].chars().nth(0).ok_or("invalid fen")?)?;
+        metadata.set_en_passant_column(match parts[3] {
+            "-" => Metadata::NO_EN_PASSANT,
+            x => {
+                let square = x.parse::<Square>().map_err(|_| "invalid en passant value in fen")?;
+                // the square the pawn skipped, behind it from the side to move's view
+                let expected_rank = match player {
+                    Player::White => 5,
+                    Player::Black => 2,
+                };
+                if square.rank() != expected_rank {
+                    return Err("en passant square on the wrong rank in fen");
+                }
+                square.pos() & 0b111
+            }
         });
+        if parts.len() == 7 {
+            let remaining: Vec<u8> = parts[4]
+                .split('+')
+                .map(|x| x.parse::<u8>().map_err(|_| "invalid check count in fen"))
+                .collect::<Result<_, _>>()?;
+            if remaining.len() != 2 || remaining.iter().any(|&x| x > 3) {
+                return Err("invalid check count in fen");
+            }
+            metadata.set_checks_given(Player::White, 3 - remaining[0]);
+            metadata.set_checks_given(Player::Black, 3 - remaining[1]);
+        }
+        let counter = |part: &str| part.parse::<u32>().map_err(|_| "invalid move counter in fen");
+        let halfmove_clock = counter(parts[parts.len() - 2])?;
+        let fullmove_number = counter(parts[parts.len() - 1])?;
+        metadata.set_halfmove_clock(halfmove_clock.min(u8::MAX as u32) as u8);
 
         // println!("metadata: {:b}", metadata.0);
         let mut res = Self {
             piece_grid,
             metadata,
-            player: Player::try_from(parts[1].chars().nth(0).ok_or("invalid fen")?)?,
+            player,
             hash: 0,
+            castling,
+            variant,
+            fullmove_number: fullmove_number.clamp(1, u16::MAX as u32) as u16,
         };
         res.setup(zoborist_state);
+        res.check_position()?;
 
         Ok(res)
     }
 
+    /// rejects positions the search cannot handle, which a fen can describe all the same
+    fn check_position(&self) -> Result<(), &'static str> {
+        let first_rank = Grid::from_u64(0xff);
+        let last_rank = Grid::from_u64(0xff << 56);
+        let white_pawns = self.piece_grid.get_squares_of_type(SquareType::pawn(Player::White));
+        let black_pawns = self.piece_grid.get_squares_of_type(SquareType::pawn(Player::Black));
+        // horde starts with white pawns on the first rank
+        let white_pawn_ranks = if self.variant == Variant::Horde {
+            last_rank
+        } else {
+            first_rank | last_rank
+        };
+        if white_pawns & white_pawn_ranks != Grid::EMPTY
+            || black_pawns & (first_rank | last_rank) != Grid::EMPTY
+        {
+            return Err("pawns on the first or last rank");
+        }
+        for player in [Player::White, Player::Black] {
+            let kings = self.piece_grid.get_squares_of_type(SquareType::king(player));
+            if kings.num_pieces() != self.variant.has_king(player) as u32 {
+                return Err("wrong number of kings");
+            }
+        }
+        if self.attacks_opponent_king() {
+            return Err("the side not to move is in check");
+        }
+        self.check_en_passant()
+    }
+
+    /// the position as a fen, with castling in X-FEN: `KQkq` unless another rook stands
+    /// further out, then the rook's file. three-check adds the remaining checks.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for row in (0..8).rev() {
+            let mut empty = 0;
+            for column in (0..8).rev() {
+                match self.piece_at(Square::from_pos_unchecked(row * 8 + column)) {
+                    Some((player, piece)) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.to_char(player));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.player {
+            Player::White => " w ",
+            Player::Black => " b ",
+        });
+
+        let mut castling = String::new();
+        for player in [Player::White, Player::Black] {
+            let home_row = Castling::home_row_offset(player);
+            let rooks = self.piece_grid.get_squares_of_type(SquareType::rook(player))
+                & Grid::from_u64(0xff << home_row);
+            for is_short in [true, false] {
+                if !self.metadata.get_can_castle_dynamic(player, is_short) {
+                    continue;
+                }
+                let (king_pos, _, rook_pos, _) = self.castling.squares(player, is_short);
+                let outermost = if is_short {
+                    rooks.into_iter().filter(|&pos| pos < king_pos).min()
+                } else {
+                    rooks.into_iter().filter(|&pos| pos > king_pos).max()
+                };
+                let c = if outermost == Some(rook_pos) {
+                    if is_short {
+                        'k'
+                    } else {
+                        'q'
+                    }
+                } else {
+                    Square::from_pos_unchecked(rook_pos).to_string().as_bytes()[0] as char
+                };
+                castling.push(match player {
+                    Player::White => c.to_ascii_uppercase(),
+                    Player::Black => c,
+                });
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        // the square behind the pawn that just moved two squares
+        let column = self.metadata.get_en_passant_column();
+        if column < Metadata::NO_EN_PASSANT {
+            let pos = match self.player {
+                Player::White => 40 + column,
+                Player::Black => 16 + column,
+            };
+            fen.push(' ');
+            fen.push_str(&Square::from_pos_unchecked(pos).to_string());
+        } else {
+            fen.push_str(" -");
+        }
+
+        if self.variant.counts_checks() {
+            fen.push_str(&format!(
+                " {}+{}",
+                3 - self.metadata.get_checks_given(Player::White),
+                3 - self.metadata.get_checks_given(Player::Black)
+            ));
+        }
+        fen.push_str(&format!(
+            " {} {}",
+            self.metadata.get_halfmove_clock(),
+            self.fullmove_number
+        ));
+        fen
+    }
+
+    /// the position with the colours swapped: the board mirrored from top to bottom, each
+    /// side getting the other's pieces and castling rights, and the other side to move. the
+    /// evaluation from the side to move's view should not change.
+    pub fn flipped(&self, zoborist_state: &ZoboristState) -> Result<Self, &'static str> {
+        let swap_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+        let fen = self.to_fen();
+        let parts: Vec<&str> = fen.split(' ').collect();
+        let mut flipped = vec![
+            parts[0].split('/').rev().map(swap_case).collect::<Vec<_>>().join("/"),
+            if parts[1] == "w" { "b" } else { "w" }.to_owned(),
+            swap_case(parts[2]),
+            match parts[3].as_bytes() {
+                [file, b'3'] => format!("{}6", *file as char),
+                [file, b'6'] => format!("{}3", *file as char),
+                _ => "-".to_owned(),
+            },
+        ];
+        if self.variant.counts_checks() {
+            let (white, black) = parts[4].split_once('+').ok_or("invalid check count in fen")?;
+            flipped.push(format!("{}+{}", black, white));
+        }
+        flipped.extend(parts[parts.len() - 2..].iter().map(|&part| part.to_owned()));
+        Self::new_from_fen_with_variant(&flipped.join(" "), self.variant, zoborist_state)
+    }
+
     #[inline(always)]
     pub fn apply_meta_hash(&mut self, zoborist_state: &ZoboristState) {
         self.hash ^= self.metadata.get_meta_hash(zoborist_state)
@@ -332,12 +775,104 @@ impl GameState {
     // zoborist hash
     fn slow_compute_hash(&mut self, zoborist_state: &ZoboristState) {
         self.apply_meta_hash(zoborist_state);
-        if self.player == Player::Black {
-            self.hash ^= zoborist_state.player;
-        }
+        self.hash ^= self.board_hash(zoborist_state);
+    }
+
+    // the hash of the pieces and the player to move, without the metadata
+    fn board_hash(&self, zoborist_state: &ZoboristState) -> HashType {
+        let mut hash = if self.player == Player::Black {
+            zoborist_state.player
+        } else {
+            0
+        };
         for pos in 0..64u8 {
-            let square_type = self.piece_grid.get_square_type(pos);
-            self.apply_piece_hash(zoborist_state, square_type, pos);
+            hash ^= self.piece_grid.get_square_type(pos).get_piece_hash(pos, zoborist_state);
+        }
+        hash
+    }
+
+    /// checks what make and unmake must preserve: the incremental hash matches one computed
+    /// from scratch, each side has one king (none for the horde), no square holds two pieces
+    /// or an invalid one, and the castling rights and en passant column fit the board.
+    /// with the `debug-checks` feature this runs after every `make_move` and `unmake_move`.
+    pub fn check_invariants(&self, zoborist_state: &ZoboristState) -> Result<(), &'static str> {
+        if self.hash != self.metadata.get_meta_hash(zoborist_state) ^ self.board_hash(zoborist_state) {
+            return Err("hash does not match the position");
+        }
+        if (0..64u8).any(|pos| !self.piece_grid.get_square_type(pos).is_valid()) {
+            return Err("invalid square type");
+        }
+
+        let mut all = Grid::EMPTY;
+        let mut count = 0;
+        for player in [Player::White, Player::Black] {
+            for square_type in [
+                SquareType::pawn(player),
+                SquareType::knight(player),
+                SquareType::bishop(player),
+                SquareType::rook(player),
+                SquareType::queen(player),
+                SquareType::king(player),
+            ] {
+                let grid = self.piece_grid.get_squares_of_type(square_type);
+                all |= grid;
+                count += grid.num_pieces();
+            }
+        }
+        let white = self.piece_grid.get_player_pieces::<WhiteMarker>();
+        let black = self.piece_grid.get_player_pieces::<BlackMarker>();
+        if count != all.num_pieces()
+            || all != self.piece_grid.get_all_pieces()
+            || white & black != Grid::EMPTY
+            || white | black != all
+        {
+            return Err("piece bitboards overlap");
+        }
+
+        for player in [Player::White, Player::Black] {
+            let kings = self.piece_grid.get_squares_of_type(SquareType::king(player));
+            if kings.num_pieces() != self.variant.has_king(player) as u32 {
+                return Err("wrong number of kings");
+            }
+            for is_short in [true, false] {
+                let (king_from, _, rook_from, _) = self.castling.squares(player, is_short);
+                if self.metadata.get_can_castle_dynamic(player, is_short)
+                    && (self.piece_grid.get_square_type(king_from) != SquareType::king(player)
+                        || self.piece_grid.get_square_type(rook_from) != SquareType::rook(player))
+                {
+                    return Err("castling right without the king and rook on their squares");
+                }
+            }
+        }
+        self.check_en_passant()
+    }
+
+    /// the en passant column needs the pawn that just moved two squares, and the two squares
+    /// it passed empty
+    fn check_en_passant(&self) -> Result<(), &'static str> {
+        let column = self.metadata.get_en_passant_column();
+        if column > Metadata::NO_EN_PASSANT {
+            return Err("invalid en passant column");
+        }
+        if column != Metadata::NO_EN_PASSANT {
+            let (pawn_pos, passed, start) = match self.player {
+                Player::White => (32 + column, 40 + column, 48 + column),
+                Player::Black => (24 + column, 16 + column, 8 + column),
+            };
+            if self.piece_grid.get_square_type(pawn_pos) != SquareType::pawn(self.player.opp())
+                || !self.piece_grid.get_square_type(passed).is_empty()
+                || !self.piece_grid.get_square_type(start).is_empty()
+            {
+                return Err("en passant column without a pawn that just moved two squares");
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn debug_check(&self, zoborist_state: &ZoboristState, after: &str, mov: Move) {
+        if let Err(err) = self.check_invariants(zoborist_state) {
+            panic!("{} after {} of {}", err, after, mov);
         }
     }
 
@@ -345,6 +880,12 @@ impl GameState {
         self.slow_compute_hash(zoborist_state);
     }
 
+    /// recomputes the hash from scratch, e.g. after switching to other zobrist keys
+    pub fn rehash(&mut self, zoborist_state: &ZoboristState) {
+        self.hash = 0;
+        self.slow_compute_hash(zoborist_state);
+    }
+
     // pub fn get_state(&self, player: Player) -> &PlayerState {
     //     &self.states[player as usize]
     // }
@@ -363,7 +904,6 @@ impl GameState {
             self.metadata.set_en_passant_column(Metadata::NO_EN_PASSANT);
             // self.en_passant_column = None;
         }
-        let offset = self.player as u8 * 56;
         match next_move {
             Move::Move {
                 prev_pos,
@@ -377,32 +917,33 @@ impl GameState {
                 self.apply_piece_move(zoborist_state, piece, new_pos);
 
                 if APPLY_METADATA_CHANGES {
-                    if piece.is_king() || (piece.is_rook() && prev_pos == offset + 7) {
-                        self.metadata
-                            .set_can_castle_dynamic::<false>(self.player, false);
-                    }
-                    if piece.is_king() || (piece.is_rook() && prev_pos == offset) {
-                        self.metadata
-                            .set_can_castle_dynamic::<false>(self.player, true);
-                    }
-                    let offset_opp = (1 - self.player as u8) * 56;
-                    if captured_piece.is_rook() && new_pos == offset_opp {
-                        self.metadata
-                            .set_can_castle_dynamic::<false>(self.player.opp(), true);
-                    }
-                    if captured_piece.is_rook() && new_pos == offset_opp + 7 {
-                        self.metadata
-                            .set_can_castle_dynamic::<false>(self.player.opp(), false);
+                    for is_short in [true, false] {
+                        if piece.is_king()
+                            || (piece.is_rook()
+                                && prev_pos == self.castling.rook_pos(self.player, is_short))
+                        {
+                            self.metadata
+                                .set_can_castle_dynamic::<false>(self.player, is_short);
+                        }
+                        if captured_piece.is_rook()
+                            && new_pos == self.castling.rook_pos(self.player.opp(), is_short)
+                        {
+                            self.metadata
+                                .set_can_castle_dynamic::<false>(self.player.opp(), is_short);
+                        }
                     }
 
-                    // potential en passant next move
-                    if piece.is_pawn() && i32::abs(prev_pos as i32 - new_pos as i32) == 16 {
+                    // potential en passant next move. horde pawns moving two squares from the
+                    // first rank cannot be taken en passant.
+                    if piece.is_pawn()
+                        && i32::abs(prev_pos as i32 - new_pos as i32) == 16
+                        && matches!(prev_pos >> 3, 1 | 6)
+                    {
                         self.metadata.set_en_passant_column(prev_pos & 0b111);
                     }
                 }
             }
             Move::Castle { is_short } => {
-                const KING_POS: u8 = 3;
                 if APPLY_METADATA_CHANGES {
                     self.metadata
                         .set_can_castle_dynamic::<false>(self.player, false);
@@ -410,22 +951,20 @@ impl GameState {
                         .set_can_castle_dynamic::<false>(self.player, true);
                 }
 
-                let rook_pos = if is_short { 0 } else { 7 };
+                let (king_from, king_to, rook_from, rook_to) =
+                    self.castling.squares(self.player, is_short);
 
                 let king = SquareType::king(self.player);
                 let rook = SquareType::rook(self.player);
 
-                self.apply_piece_move(zoborist_state, king, KING_POS + offset);
-                self.apply_piece_move(zoborist_state, rook, rook_pos + offset);
+                // both pieces are lifted before either is placed, since in chess960 the
+                // destination squares may be the other piece's start square
+                self.apply_piece_move(zoborist_state, king, king_from);
+                self.apply_piece_move(zoborist_state, rook, rook_from);
 
                 // xor with post-castling rook/king positions
-                if is_short {
-                    self.apply_piece_move(zoborist_state, king, KING_POS + offset - 2);
-                    self.apply_piece_move(zoborist_state, rook, rook_pos + offset + 2);
-                } else {
-                    self.apply_piece_move(zoborist_state, king, KING_POS + offset + 2);
-                    self.apply_piece_move(zoborist_state, rook, rook_pos + offset - 3);
-                }
+                self.apply_piece_move(zoborist_state, king, king_to);
+                self.apply_piece_move(zoborist_state, rook, rook_to);
             }
             Move::PawnPromote {
                 prev_pos,
@@ -439,14 +978,13 @@ impl GameState {
                 self.apply_piece_move(zoborist_state, SquareType::pawn(self.player), prev_pos);
                 self.apply_piece_move(zoborist_state, promoted_to_piece, new_pos);
                 if APPLY_METADATA_CHANGES {
-                    let offset_opp = (1 - self.player as u8) * 56;
-                    if captured_piece.is_rook() && new_pos == offset_opp {
-                        self.metadata
-                            .set_can_castle_dynamic::<false>(self.player.opp(), true);
-                    }
-                    if captured_piece.is_rook() && new_pos == offset_opp + 7 {
-                        self.metadata
-                            .set_can_castle_dynamic::<false>(self.player.opp(), false);
+                    for is_short in [true, false] {
+                        if captured_piece.is_rook()
+                            && new_pos == self.castling.rook_pos(self.player.opp(), is_short)
+                        {
+                            self.metadata
+                                .set_can_castle_dynamic::<false>(self.player.opp(), is_short);
+                        }
                     }
                 }
             }
@@ -483,19 +1021,226 @@ impl GameState {
         }
     }
 
+    /// makes `mov`, keeping the hash up to date. returns the metadata `unmake_move` needs.
+    #[inline(always)]
+    pub fn make_move(&mut self, mov: Move, zoborist_state: &ZoboristState) -> Metadata {
+        let metadata = self.metadata;
+        self.apply_meta_hash(zoborist_state);
+        self.advance_state(mov, zoborist_state);
+        self.apply_meta_hash(zoborist_state);
+        #[cfg(feature = "debug-checks")]
+        self.debug_check(zoborist_state, "make", mov);
+        metadata
+    }
+
+    /// takes back `mov`, given the metadata `make_move` returned for it
+    #[inline(always)]
+    pub fn unmake_move(&mut self, mov: Move, metadata: Metadata, zoborist_state: &ZoboristState) {
+        self.apply_meta_hash(zoborist_state);
+        self.revert_state(mov, zoborist_state);
+        self.metadata = metadata;
+        self.apply_meta_hash(zoborist_state);
+        #[cfg(feature = "debug-checks")]
+        self.debug_check(zoborist_state, "unmake", mov);
+    }
+
     #[inline(always)]
     pub fn advance_state(&mut self, next_move: Move, zoborist_state: &ZoboristState) {
+        let halfmove_clock = if next_move.resets_halfmove_clock() {
+            0
+        } else {
+            self.metadata.get_halfmove_clock().saturating_add(1)
+        };
+        self.metadata.set_halfmove_clock(halfmove_clock);
         self.modify_state::<true>(next_move, zoborist_state);
+        if self.variant.counts_checks() {
+            self.record_check();
+        }
+        self.count_move::<true>();
         // switch the player
         self.change_player(zoborist_state);
     }
 
+    /// moves the fullmove number on after black's move, or back when it is taken back
+    #[inline(always)]
+    fn count_move<const FORWARD: bool>(&mut self) {
+        if self.player == Player::Black {
+            self.fullmove_number = if FORWARD {
+                self.fullmove_number.wrapping_add(1)
+            } else {
+                self.fullmove_number.wrapping_sub(1)
+            };
+        }
+    }
+
+    fn first_move_number() -> u16 {
+        1
+    }
+
+    /// counts a check given by the player who just moved. like the other metadata, the
+    /// count is restored by the caller when the move is taken back.
+    fn record_check(&mut self) {
+        if self.attacks_opponent_king() {
+            let checks = self.metadata.get_checks_given(self.player);
+            self.metadata.set_checks_given(self.player, checks + 1);
+        }
+    }
+
+    /// true if the side to move attacks the opponent's king. after a move has been made,
+    /// this means the move was illegal.
+    #[inline(always)]
+    pub fn attacks_opponent_king(&self) -> bool {
+        self.king_attackers(self.player.opp()) != Grid::EMPTY
+    }
+
+    /// pieces of `color` attacking `pos`
+    #[inline(always)]
+    fn attackers_to_pos(&self, pos: u8, color: Player) -> Grid {
+        let occupied = self.piece_grid.get_all_pieces();
+        player_to_marker!(color, {
+            King::pieces_attacking::<P>(&self.piece_grid, pos, occupied)
+        })
+    }
+
+    /// opposing pieces attacking the king of `king_owner`. empty if they have no king.
+    #[inline(always)]
+    fn king_attackers(&self, king_owner: Player) -> Grid {
+        let king = player_to_marker!(king_owner, { self.piece_grid.get_king_pos::<P>() });
+        match king.into_iter().next() {
+            Some(pos) => self.attackers_to_pos(pos, king_owner.opp()),
+            None => Grid::EMPTY,
+        }
+    }
+
+    /// pieces of `color` attacking `square`. a piece of either colour may stand on it.
+    pub fn attackers_to(&self, square: Square, color: Player) -> Bitboard {
+        Bitboard::from_grid(self.attackers_to_pos(square.pos(), color))
+    }
+
+    /// true if a piece of `color` attacks `square`
+    pub fn is_square_attacked(&self, square: Square, color: Player) -> bool {
+        self.attackers_to_pos(square.pos(), color) != Grid::EMPTY
+    }
+
+    /// opposing pieces giving check to the side to move
+    pub fn checkers(&self) -> Bitboard {
+        Bitboard::from_grid(self.king_attackers(self.player))
+    }
+
+    /// true if the side to move is in check
+    #[inline(always)]
+    pub fn is_in_check(&self) -> bool {
+        self.king_attackers(self.player) != Grid::EMPTY
+    }
+
+    /// pieces of `color` that are the only piece between their king and an opposing slider
+    /// aiming at it. a pinned piece may still move along the pin.
+    pub fn pinned_pieces(&self, color: Player) -> Bitboard {
+        let (king, own, snipers) = player_to_marker!(color, {
+            let king = self.piece_grid.get_king_pos::<P>();
+            let own = self.piece_grid.get_player_pieces::<P>();
+            let snipers = player_to_marker!(color.opp(), {
+                (
+                    self.piece_grid.get_rooks_queens::<P>(),
+                    self.piece_grid.get_bishops_queens::<P>(),
+                )
+            });
+            (king, own, snipers)
+        });
+        let Some(king_pos) = king.into_iter().next() else {
+            return Bitboard::EMPTY;
+        };
+        let occupied = self.piece_grid.get_all_pieces();
+        let mut pinned = Grid::EMPTY;
+        // attacks from both ends meet exactly on the squares in between
+        for (sniper_grid, slider_attacks) in [
+            (snipers.0, attacks::rook_attacks as fn(u8, Grid) -> Grid),
+            (snipers.1, attacks::bishop_attacks),
+        ] {
+            for sniper_pos in slider_attacks(king_pos, Grid::EMPTY) & sniper_grid {
+                let between = slider_attacks(king_pos, Grid::from_pos(sniper_pos))
+                    & slider_attacks(sniper_pos, Grid::from_pos(king_pos));
+                let blockers = between & occupied;
+                if blockers.num_pieces() == 1 {
+                    pinned |= blockers & own;
+                }
+            }
+        }
+        Bitboard::from_grid(pinned)
+    }
+
+    /// moves that do not leave the mover's king attacked. empty once the variant has
+    /// decided the game.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.is_variant_loss() {
+            return vec![];
+        }
+        let mut move_buf = MoveBuffer::default();
+        move_buf.get_all_moves(self);
+        let mut state = self.clone();
+        let mut moves = vec![];
+        while let Some(mov) = move_buf.pop() {
+            let metadata = state.metadata;
+            state.advance_state(mov, &ZoboristState::STATIC_EMPTY);
+            if !state.attacks_opponent_king() {
+                moves.push(mov);
+            }
+            state.revert_state(mov, &ZoboristState::STATIC_EMPTY);
+            state.metadata = metadata;
+        }
+        moves
+    }
+
+    /// true if making `mov` puts the opponent in check. `mov` must be pseudo-legal.
+    pub fn gives_check(&self, mov: Move) -> bool {
+        let mut state = self.clone();
+        state.modify_state::<false>(mov, &ZoboristState::STATIC_EMPTY);
+        state.attacks_opponent_king()
+    }
+
+    /// true if the side to move has lost by a rule of the variant
+    #[inline(always)]
+    pub fn is_variant_loss(&self) -> bool {
+        self.variant.is_loss(self)
+    }
+
+    /// the owner and type of the piece on `square`, if any
+    pub fn piece_at(&self, square: Square) -> Option<(Player, Piece)> {
+        let square_type = self.piece_grid.get_square_type(square.pos());
+        let player = if square_type.get_d() == 0 {
+            Player::White
+        } else {
+            Player::Black
+        };
+        square_type.to_piece_for_io().map(|piece| (player, piece))
+    }
+
+    /// squares holding a `piece` of `color`
+    pub fn pieces(&self, color: Player, piece: Piece) -> Bitboard {
+        let square_type = SquareType::create_for_parsing(piece, color);
+        Bitboard::from_grid(self.piece_grid.get_squares_of_type(square_type))
+    }
+
+    /// squares holding any piece of `color`
+    pub fn color_pieces(&self, color: Player) -> Bitboard {
+        Bitboard::from_grid(match color {
+            Player::White => self.piece_grid.get_player_pieces::<WhiteMarker>(),
+            Player::Black => self.piece_grid.get_player_pieces::<BlackMarker>(),
+        })
+    }
+
+    /// squares holding any piece
+    pub fn occupied(&self) -> Bitboard {
+        Bitboard::from_grid(self.piece_grid.get_all_pieces())
+    }
+
     pub fn advance_state_no_metadata_update(
         &mut self,
         next_move: Move,
         zoborist_state: &ZoboristState,
     ) {
         self.modify_state::<false>(next_move, zoborist_state);
+        self.count_move::<true>();
         // switch the player
         self.change_player(zoborist_state);
     }
@@ -504,6 +1249,7 @@ impl GameState {
     pub fn revert_state(&mut self, next_move: Move, zoborist_state: &ZoboristState) {
         // switch the player
         self.change_player(zoborist_state);
+        self.count_move::<false>();
 
         self.modify_state::<false>(next_move, zoborist_state);
     }
@@ -0,0 +1,100 @@
+use crate::{
+    config::MAX_SEARCH_DEPTH, engine::ChessEngine, skill::Skill, time_manager::TimeManager,
+    types::Move, GameState,
+};
+
+/// what a search may do, besides taking the time its `TimeManager` gives it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchLimits {
+    /// deepest iteration
+    pub depth: usize,
+    /// lines to report at each depth
+    pub multi_pv: usize,
+    pub skill: Skill,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            depth: MAX_SEARCH_DEPTH,
+            multi_pv: 1,
+            skill: Skill::default(),
+        }
+    }
+}
+
+/// an iteration of `ChessEngine::iterative_search` that was finished
+pub struct Iteration<'a> {
+    pub depth: usize,
+    /// at most `multi_pv` lines, best first, with scores from white's side
+    pub lines: &'a [(i32, Vec<Move>)],
+}
+
+/// hears about the progress of `ChessEngine::iterative_search`, to report it to a gui
+pub trait SearchObserver {
+    /// before the iteration at `depth`. `best` is the best move of the last iteration. a root
+    /// listener set here is removed when the iteration ends.
+    fn iteration_started(&mut self, _engine: &mut ChessEngine, _depth: usize, _best: Option<Move>) {}
+
+    fn iteration_finished(&mut self, engine: &ChessEngine, iteration: &Iteration);
+}
+
+impl ChessEngine {
+    /// iterative deepening, two plies at a time up to `limits.depth`, for as long as the
+    /// time manager and the skill level allow. returns the line to play with its score from
+    /// white's side, picked by the skill level among the lines of the last finished
+    /// iteration, or none without legal moves.
+    pub fn iterative_search(
+        &mut self,
+        state: &GameState,
+        limits: &SearchLimits,
+        time_manager: &TimeManager,
+        observer: &mut dyn SearchObserver,
+    ) -> Option<(i32, Vec<Move>)> {
+        let legal_moves = state.legal_moves();
+        if legal_moves.is_empty() {
+            return None;
+        }
+        let skill = limits.skill;
+        let multi_pv = limits.multi_pv.clamp(1, legal_moves.len());
+        // a weaker level needs a few lines to pick from, even if they are not reported
+        let lines_searched = multi_pv.max(skill.multi_pv()).min(legal_moves.len());
+        let depth = skill
+            .max_depth()
+            .map_or(limits.depth, |max_depth| limits.depth.min(max_depth))
+            .clamp(1, MAX_SEARCH_DEPTH);
+        self.set_node_limit(skill.max_nodes());
+        // the lines of the last finished iteration
+        let mut best: Option<Vec<(i32, Vec<Move>)>> = None;
+        for i in ((2 - (depth % 2))..=depth).step_by(2) {
+            if best.is_some() && !time_manager.can_start_iteration() {
+                break;
+            }
+            observer.iteration_started(self, i, best.as_ref().map(|lines| lines[0].1[0]));
+            let lines = self.search_lines(state, i, lines_searched);
+            self.set_root_listener(None);
+            // an unfinished iteration only counts if there is nothing better
+            if self.search_stopped() || lines.is_empty() {
+                break;
+            }
+            observer.iteration_finished(
+                self,
+                &Iteration {
+                    depth: i,
+                    lines: &lines[..multi_pv.min(lines.len())],
+                },
+            );
+            best = Some(lines);
+        }
+        self.set_node_limit(None);
+        let lines = best.unwrap_or_else(|| {
+            // the best root move seen so far, or any legal move when there is none
+            let mov = self
+                .get_best_calculated_move(state.player)
+                .filter(|mov| legal_moves.contains(mov))
+                .unwrap_or(legal_moves[0]);
+            vec![(0, vec![mov])]
+        });
+        Some(lines[skill.pick_line(&lines, state.player)].clone())
+    }
+}
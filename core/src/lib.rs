@@ -1,26 +1,55 @@
+mod attacks;
+mod bitboard;
 mod types;
+mod game;
 mod game_data;
+mod diagram;
 mod util;
 mod config;
 mod move_table;
 mod engine;
+mod endgame;
 mod eval;
+mod evaluator;
+mod polyglot;
+mod syzygy;
 mod move_orderer;
 mod move_buffer;
 mod grid;
+mod square;
 mod square_type;
 mod zoborist_state;
 mod types_for_io;
 mod movegen;
 mod player;
 mod markers;
+mod variant;
+mod stop_signal;
+mod skill;
+mod time_manager;
+mod iterative_search;
 mod move_buffer_entry;
 
-pub use engine::ChessEngine;
+pub use attacks::{slider_indexing, verify_attack_tables, SliderIndexing};
+pub use bitboard::{Bitboard, BitboardIterator};
+pub use config::MAX_SEARCH_DEPTH;
+pub use diagram::Diagram;
+pub use engine::{ChessEngine, RootListener};
+pub use game::{Game, MoveError, Outcome};
+pub use iterative_search::{Iteration, SearchLimits, SearchObserver};
 pub use game_data::GameState;
-pub use types::Move;
+pub use types::{Move, PackedMove};
 pub use player::Player;
 pub use types_for_io::Piece;
-pub use eval::evaluate;
-pub use util::canonical_to_pos;
-pub use util::pos_to_coord;
\ No newline at end of file
+pub use eval::{evaluate, evaluate_with_trace, EvalTerm, EvalTrace, TaperedScore, PHASE_MAX};
+pub use evaluator::{
+    evaluator_from_name, Evaluator, MaterialEvaluator, PestoEvaluator, EVALUATOR_NAMES,
+};
+pub use polyglot::{polyglot_key, Book, BookSelection};
+pub use skill::{Skill, MAX_SKILL_LEVEL};
+pub use square::Square;
+pub use stop_signal::StopSignal;
+pub use syzygy::{Tablebases, Wdl};
+pub use time_manager::{TimeControl, TimeManager};
+pub use variant::{Variant, VARIANT_NAMES};
+pub use zoborist_state::ZoboristState;
\ No newline at end of file
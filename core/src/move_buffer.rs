@@ -1,4 +1,5 @@
 use crate::{
+    attacks,
     config::MAX_CHILDREN_PER_NODE,
     game_data::Metadata,
     grid::Grid,
@@ -58,10 +59,15 @@ impl MoveBuffer {
             }
 
             // double moves
-            for new_pos in
-                movegen::Pawn::double_moves::<P>(grid, state.piece_grid.get_empty_squares())
-                    .into_iter()
-            {
+            let mut double_moves =
+                movegen::Pawn::double_moves::<P>(grid, state.piece_grid.get_empty_squares());
+            if state.variant.allows_back_rank_double_moves(P::PLAYER) {
+                double_moves |= movegen::Pawn::back_rank_double_moves::<P>(
+                    grid,
+                    state.piece_grid.get_empty_squares(),
+                );
+            }
+            for new_pos in double_moves {
                 let captured_piece = state.piece_grid.get_square_type(new_pos);
                 assert!(captured_piece.is_empty());
                 self.emit_move(Move::Move {
@@ -177,242 +183,121 @@ impl MoveBuffer {
         }
     }
 
+    #[inline(always)]
+    fn emit_moves_to(&mut self, state: &GameState, piece: SquareType, prev_pos: u8, targets: Grid) {
+        for new_pos in targets {
+            let captured_piece = state.piece_grid.get_square_type(new_pos);
+            self.emit_move(Move::Move {
+                prev_pos,
+                new_pos,
+                pieces: CompressedSquareType::from_square_types(piece, captured_piece),
+            })
+        }
+    }
+
     #[inline(always)]
     fn get_knight_moves<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::knight(P::PLAYER);
-        for prev_pos in state.piece_grid.get_knight_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            for new_pos in
-                movegen::Knight::moves(grid, state.piece_grid.get_player_pieces::<P>()).into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let own = state.piece_grid.get_player_pieces::<P>();
+        for prev_pos in state.piece_grid.get_knight_pos::<P>() {
+            self.emit_moves_to(state, piece, prev_pos, attacks::knight_attacks(prev_pos) & !own);
         }
     }
 
     #[inline(always)]
     fn get_knight_captures<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::knight(P::PLAYER);
-        for prev_pos in state.piece_grid.get_knight_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            for new_pos in
-                movegen::Knight::captures(grid, state.piece_grid.get_opp_player_pieces::<P>())
-                    .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let opp = state.piece_grid.get_opp_player_pieces::<P>();
+        for prev_pos in state.piece_grid.get_knight_pos::<P>() {
+            self.emit_moves_to(state, piece, prev_pos, attacks::knight_attacks(prev_pos) & opp);
         }
     }
 
     #[inline(always)]
     fn get_bishop_moves<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::bishop(P::PLAYER);
-        for prev_pos in state.piece_grid.get_bishop_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            for new_pos in (movegen::Rays::ray_diagonal_occluded_with_captures_and_non_captures(
-                grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) & !grid)
-                .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let own = state.piece_grid.get_player_pieces::<P>();
+        let occupied = state.piece_grid.get_all_pieces();
+        for prev_pos in state.piece_grid.get_bishop_pos::<P>() {
+            let targets = attacks::bishop_attacks(prev_pos, occupied) & !own;
+            self.emit_moves_to(state, piece, prev_pos, targets);
         }
     }
 
     #[inline(always)]
     fn get_bishop_captures<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::bishop(P::PLAYER);
-        for prev_pos in state.piece_grid.get_bishop_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            for new_pos in (movegen::Rays::ray_diagonal_captures(
-                grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) & !grid)
-                .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let opp = state.piece_grid.get_opp_player_pieces::<P>();
+        let occupied = state.piece_grid.get_all_pieces();
+        for prev_pos in state.piece_grid.get_bishop_pos::<P>() {
+            let targets = attacks::bishop_attacks(prev_pos, occupied) & opp;
+            self.emit_moves_to(state, piece, prev_pos, targets);
         }
     }
 
     #[inline(always)]
     fn get_rook_moves<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::rook(P::PLAYER);
-        for prev_pos in state.piece_grid.get_rook_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            for new_pos in
-                (movegen::Rays::ray_horizontal_vertical_occluded_with_captures_and_non_captures(
-                    grid,
-                    state.piece_grid.get_empty_squares(),
-                    state.piece_grid.get_opp_player_pieces::<P>(),
-                ) & !grid)
-                    .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let own = state.piece_grid.get_player_pieces::<P>();
+        let occupied = state.piece_grid.get_all_pieces();
+        for prev_pos in state.piece_grid.get_rook_pos::<P>() {
+            let targets = attacks::rook_attacks(prev_pos, occupied) & !own;
+            self.emit_moves_to(state, piece, prev_pos, targets);
         }
     }
 
     #[inline(always)]
     fn get_rook_captures<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::rook(P::PLAYER);
-        for prev_pos in state.piece_grid.get_rook_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            for new_pos in (movegen::Rays::ray_horizontal_vertical_captures(
-                grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) & !grid)
-                .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let opp = state.piece_grid.get_opp_player_pieces::<P>();
+        let occupied = state.piece_grid.get_all_pieces();
+        for prev_pos in state.piece_grid.get_rook_pos::<P>() {
+            let targets = attacks::rook_attacks(prev_pos, occupied) & opp;
+            self.emit_moves_to(state, piece, prev_pos, targets);
         }
     }
 
     #[inline(always)]
     fn get_queen_moves<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::queen(P::PLAYER);
-        for prev_pos in state.piece_grid.get_queen_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            // diagonal
-            for new_pos in (movegen::Rays::ray_diagonal_occluded_with_captures_and_non_captures(
-                grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) & !grid)
-                .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
-
-            // horizontal
-            for new_pos in
-                (movegen::Rays::ray_horizontal_vertical_occluded_with_captures_and_non_captures(
-                    grid,
-                    state.piece_grid.get_empty_squares(),
-                    state.piece_grid.get_opp_player_pieces::<P>(),
-                ) & !grid)
-                    .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(piece, captured_piece),
-                })
-            }
+        let own = state.piece_grid.get_player_pieces::<P>();
+        let occupied = state.piece_grid.get_all_pieces();
+        for prev_pos in state.piece_grid.get_queen_pos::<P>() {
+            let targets = attacks::queen_attacks(prev_pos, occupied) & !own;
+            self.emit_moves_to(state, piece, prev_pos, targets);
         }
     }
 
     #[inline(always)]
     fn get_queen_captures<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::queen(P::PLAYER);
-        for prev_pos in state.piece_grid.get_queen_pos::<P>().into_iter() {
-            let grid = Grid::from_pos(prev_pos);
-
-            // diagonal
-            for new_pos in (movegen::Rays::ray_diagonal_captures(
-                grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) & !grid)
-                .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(
-                    piece,
-                    captured_piece),
-                })
-            }
-
-            // horizontal
-            for new_pos in (movegen::Rays::ray_horizontal_vertical_captures(
-                grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) & !grid)
-                .into_iter()
-            {
-                let captured_piece = state.piece_grid.get_square_type(new_pos);
-                self.emit_move(Move::Move {
-                    prev_pos,
-                    new_pos,
-                    pieces: CompressedSquareType::from_square_types(
-                    piece,
-                    captured_piece),
-                })
-            }
+        let opp = state.piece_grid.get_opp_player_pieces::<P>();
+        let occupied = state.piece_grid.get_all_pieces();
+        for prev_pos in state.piece_grid.get_queen_pos::<P>() {
+            let targets = attacks::queen_attacks(prev_pos, occupied) & opp;
+            self.emit_moves_to(state, piece, prev_pos, targets);
         }
     }
 
     #[inline(always)]
-    /// P is the attacker
+    /// P is the attacker. squares defended by P's own pieces count as attacked.
     pub fn get_attacked_grid<P: PlayerMarker>(state: &GameState) -> Grid {
-        movegen::Pawn::squares_attacked::<P>(state.piece_grid.get_pawn_pos::<P>())
-            | movegen::Knight::moves(state.piece_grid.get_knight_pos::<P>(), Grid::EMPTY)
-            | movegen::Rays::ray_horizontal_vertical_occluded_with_captures_and_non_captures(
-                state.piece_grid.get_rooks_queens::<P>(),
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            )
-            | movegen::Rays::ray_diagonal_occluded_with_captures_and_non_captures(
-                state.piece_grid.get_bishops_queens::<P>(),
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            )
-            | movegen::King::regular_moves(
-                state.piece_grid.get_king_pos::<P>(),
-                state.piece_grid.get_player_pieces::<P>(),
-            )
+        let occupied = state.piece_grid.get_all_pieces();
+        let mut attacked =
+            movegen::Pawn::squares_attacked::<P>(state.piece_grid.get_pawn_pos::<P>());
+        for pos in state.piece_grid.get_knight_pos::<P>() {
+            attacked |= attacks::knight_attacks(pos);
+        }
+        for pos in state.piece_grid.get_bishops_queens::<P>() {
+            attacked |= attacks::bishop_attacks(pos, occupied);
+        }
+        for pos in state.piece_grid.get_rooks_queens::<P>() {
+            attacked |= attacks::rook_attacks(pos, occupied);
+        }
+        for pos in state.piece_grid.get_king_pos::<P>() {
+            attacked |= attacks::king_attacks(pos);
+        }
+        attacked
     }
 
     #[inline(always)]
@@ -420,37 +305,43 @@ impl MoveBuffer {
     fn get_king_moves<P: PlayerMarker, O: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::king(P::PLAYER);
         let grid = state.piece_grid.get_king_pos::<P>();
+        // the horde has no king
+        if grid == Grid::EMPTY {
+            return;
+        }
         if grid.num_pieces() != 1 {
             state.piece_grid.debug_print();
         }
         let prev_pos = grid.to_pos();
 
         // regular moves
-        for new_pos in movegen::King::regular_moves(grid, state.piece_grid.get_player_pieces::<P>())
-        {
-            let captured_piece = state.piece_grid.get_square_type(new_pos);
-            self.emit_move(Move::Move {
-                prev_pos,
-                new_pos,pieces: CompressedSquareType::from_square_types(
-                piece,
-                captured_piece),
-            })
-        }
+        let targets = attacks::king_attacks(prev_pos) & !state.piece_grid.get_player_pieces::<P>();
+        self.emit_moves_to(state, piece, prev_pos, targets);
 
         // castling
         if state.metadata.get_can_castle::<P, CastleShortMarker>()
             || state.metadata.get_can_castle::<P, CastleLongMarker>()
         {
             let attacked_grid = Self::get_attacked_grid::<O>(state);
-            let occlusion_grid = state.piece_grid.get_all_pieces() & !grid;
+            let occupied_grid = state.piece_grid.get_all_pieces();
 
             if state.metadata.get_can_castle::<P, CastleShortMarker>()
-                && movegen::King::can_castle::<P, CastleShortMarker>(attacked_grid, occlusion_grid)
+                && movegen::King::can_castle(
+                    attacked_grid,
+                    occupied_grid,
+                    state.castling.free_grid(P::PLAYER, true),
+                    state.castling.safe_grid(P::PLAYER, true),
+                )
             {
                 self.emit_move(Move::Castle { is_short: true })
             }
             if state.metadata.get_can_castle::<P, CastleLongMarker>()
-                && movegen::King::can_castle::<P, CastleLongMarker>(attacked_grid, occlusion_grid)
+                && movegen::King::can_castle(
+                    attacked_grid,
+                    occupied_grid,
+                    state.castling.free_grid(P::PLAYER, false),
+                    state.castling.safe_grid(P::PLAYER, false),
+                )
             {
                 self.emit_move(Move::Castle { is_short: false })
             }
@@ -462,19 +353,14 @@ impl MoveBuffer {
     fn get_king_captures<P: PlayerMarker>(&mut self, state: &GameState) {
         let piece = SquareType::king(P::PLAYER);
         let grid = state.piece_grid.get_king_pos::<P>();
+        if grid == Grid::EMPTY {
+            return;
+        }
         let prev_pos = grid.to_pos();
 
         // regular moves
-        for new_pos in movegen::King::captures(grid, state.piece_grid.get_opp_player_pieces::<P>())
-        {
-            let captured_piece = state.piece_grid.get_square_type(new_pos);
-            self.emit_move(Move::Move {
-                prev_pos,
-                new_pos,pieces: CompressedSquareType::from_square_types(
-                piece,
-                captured_piece),
-            })
-        }
+        let targets = attacks::king_attacks(prev_pos) & state.piece_grid.get_opp_player_pieces::<P>();
+        self.emit_moves_to(state, piece, prev_pos, targets);
     }
 
     #[inline(always)]
@@ -1,5 +1,5 @@
 use crate::{
-    grid::Grid, markers::PlayerMarker, movegen, player_to_marker, square_type::{SquareType, CompressedSquareType},
+    attacks, grid::Grid, markers::PlayerMarker, movegen, player_to_marker, square_type::{SquareType, CompressedSquareType},
     zoborist_state::ZoboristState, GameState, Move,
 };
 
@@ -21,195 +21,65 @@ impl MoveBufferEntry {
         captured_piece: SquareType,
     ) -> Option<Move> {
         let target_grid = Grid::from_pos(new_pos);
-
-        // pawn
+        let occupied = state.piece_grid.get_all_pieces();
+        let capture_from = |prev_pos: u8, piece: SquareType| Move::Move {
+            prev_pos,
+            new_pos,
+            pieces: CompressedSquareType::from_square_types(piece, captured_piece),
+        };
+
+        // pawn. pawns about to promote are not considered, as before.
+        // a pawn of P attacks new_pos from the squares an opposing pawn on new_pos attacks
+        for prev_pos in attacks::pawn_attacks(P::PLAYER.opp(), new_pos)
+            & state.piece_grid.get_pawn_pos::<P>()
         {
-            let all_grid = state.piece_grid.get_pawn_pos::<P>();
-            let all_captures = movegen::Pawn::captures::<P>(
-                all_grid,
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            );
-            if all_captures & target_grid != Grid::EMPTY {
-                for prev_pos in all_grid {
-                    let grid = Grid::from_pos(prev_pos);
-
-                    if movegen::Pawn::captures::<P>(
-                        grid,
-                        state.piece_grid.get_opp_player_pieces::<P>(),
-                    ) & target_grid
-                        != Grid::EMPTY
-                    {
-                        return Some(Move::Move {
-                            prev_pos,
-                            new_pos,
-                            pieces: CompressedSquareType::from_square_types(
-                            SquareType::pawn(P::PLAYER),
-                            captured_piece),
-                        });
-                    }
-                }
-                unreachable!("should not be reachable: pawn")
+            if movegen::Pawn::captures::<P>(Grid::from_pos(prev_pos), target_grid) != Grid::EMPTY {
+                return Some(capture_from(prev_pos, SquareType::pawn(P::PLAYER)));
             }
         }
 
         // knight
+        if let Some(prev_pos) = (attacks::knight_attacks(new_pos)
+            & state.piece_grid.get_knight_pos::<P>())
+        .into_iter()
+        .next()
         {
-            let all_grid = state.piece_grid.get_knight_pos::<P>();
-            let all_captures =
-                movegen::Knight::captures(all_grid, state.piece_grid.get_opp_player_pieces::<P>());
-            if all_captures & target_grid != Grid::EMPTY {
-                for prev_pos in all_grid {
-                    let grid = Grid::from_pos(prev_pos);
-
-                    if movegen::Knight::captures(
-                        grid,
-                        state.piece_grid.get_opp_player_pieces::<P>(),
-                    ) & target_grid
-                        != Grid::EMPTY
-                    {
-                        return Some(Move::Move {
-                            prev_pos,
-                            new_pos,
-                            pieces: CompressedSquareType::from_square_types(
-                            SquareType::knight(P::PLAYER),
-                            captured_piece),
-                        });
-                    }
-                }
-                unreachable!("should not be reachable: knight")
-            }
+            return Some(capture_from(prev_pos, SquareType::knight(P::PLAYER)));
         }
 
         // bishop
+        let diagonal = attacks::bishop_attacks(new_pos, occupied);
+        if let Some(prev_pos) = (diagonal & state.piece_grid.get_bishop_pos::<P>())
+            .into_iter()
+            .next()
         {
-            let all_grid = state.piece_grid.get_bishop_pos::<P>();
-            let all_captures = movegen::Rays::ray_diagonal_captures(
-                all_grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            );
-            if all_captures & target_grid != Grid::EMPTY {
-                for prev_pos in all_grid {
-                    let grid = Grid::from_pos(prev_pos);
-
-                    if movegen::Rays::ray_diagonal_captures(
-                        grid,
-                        state.piece_grid.get_empty_squares(),
-                        state.piece_grid.get_opp_player_pieces::<P>(),
-                    ) & target_grid
-                        != Grid::EMPTY
-                    {
-                        return Some(Move::Move {
-                            prev_pos,
-                            new_pos,
-                            pieces: CompressedSquareType::from_square_types(
-                            SquareType::bishop(P::PLAYER),
-                            captured_piece),
-                        });
-                    }
-                }
-                unreachable!("should not be reachable: bishop")
-            }
+            return Some(capture_from(prev_pos, SquareType::bishop(P::PLAYER)));
         }
 
         // rook
+        let horizontal = attacks::rook_attacks(new_pos, occupied);
+        if let Some(prev_pos) = (horizontal & state.piece_grid.get_rook_pos::<P>())
+            .into_iter()
+            .next()
         {
-            let all_grid = state.piece_grid.get_rook_pos::<P>();
-            let all_captures = movegen::Rays::ray_horizontal_vertical_captures(
-                all_grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            );
-            if all_captures & target_grid != Grid::EMPTY {
-                for prev_pos in all_grid {
-                    let grid = Grid::from_pos(prev_pos);
-
-                    if movegen::Rays::ray_horizontal_vertical_captures(
-                        grid,
-                        state.piece_grid.get_empty_squares(),
-                        state.piece_grid.get_opp_player_pieces::<P>(),
-                    ) & target_grid
-                        != Grid::EMPTY
-                    {
-                        return Some(Move::Move {
-                            prev_pos,
-                            new_pos,
-                            pieces: CompressedSquareType::from_square_types(
-                            SquareType::rook(P::PLAYER),
-                            captured_piece),
-                        });
-                    }
-                }
-                unreachable!("should not be reachable: rook")
-            }
+            return Some(capture_from(prev_pos, SquareType::rook(P::PLAYER)));
         }
 
         // queen
+        if let Some(prev_pos) = ((diagonal | horizontal) & state.piece_grid.get_queen_pos::<P>())
+            .into_iter()
+            .next()
         {
-            let all_grid = state.piece_grid.get_rook_pos::<P>();
-            let all_captures = movegen::Rays::ray_horizontal_vertical_captures(
-                all_grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            ) | movegen::Rays::ray_diagonal_captures(
-                all_grid,
-                state.piece_grid.get_empty_squares(),
-                state.piece_grid.get_opp_player_pieces::<P>(),
-            );
-            if all_captures & target_grid != Grid::EMPTY {
-                for prev_pos in all_grid {
-                    let grid = Grid::from_pos(prev_pos);
-
-                    if (movegen::Rays::ray_horizontal_vertical_captures(
-                        grid,
-                        state.piece_grid.get_empty_squares(),
-                        state.piece_grid.get_opp_player_pieces::<P>(),
-                    ) | movegen::Rays::ray_diagonal_captures(
-                        grid,
-                        state.piece_grid.get_empty_squares(),
-                        state.piece_grid.get_opp_player_pieces::<P>(),
-                    )) & target_grid
-                        != Grid::EMPTY
-                    {
-                        return Some(Move::Move {
-                            prev_pos,
-                            new_pos,
-                            pieces: CompressedSquareType::from_square_types(
-                            SquareType::queen(P::PLAYER),
-                            captured_piece),
-                        });
-                    }
-                }
-                unreachable!("should not be reachable: queen")
-            }
+            return Some(capture_from(prev_pos, SquareType::queen(P::PLAYER)));
         }
 
-        // king
+        // king. the king may be captured during see search
+        if let Some(prev_pos) = (attacks::king_attacks(new_pos)
+            & state.piece_grid.get_king_pos::<P>())
+        .into_iter()
+        .next()
         {
-            let grid = state.piece_grid.get_king_pos::<P>();
-            // the king may be captured during see search
-            if grid.num_pieces() > 0 {
-                if grid.num_pieces() != 1 {
-                    println!("pos: {}", new_pos);
-                    state.piece_grid.debug_print();
-                    
-                }
-                let prev_pos = grid.to_pos();
-                let grid = Grid::from_pos(prev_pos);
-
-                if movegen::King::captures(grid, state.piece_grid.get_opp_player_pieces::<P>())
-                    & target_grid
-                    != Grid::EMPTY
-                {
-                    return Some(Move::Move {
-                        prev_pos,
-                        new_pos,
-                        pieces: CompressedSquareType::from_square_types(
-                        SquareType::king(P::PLAYER),
-                        captured_piece),
-                    });
-                }
-            }
+            return Some(capture_from(prev_pos, SquareType::king(P::PLAYER)));
         }
 
         None
@@ -38,9 +38,20 @@ impl KillerEntry {
     }
 }
 
+// history scores have to fit below the next ordering class, see `get_move_cmp_key`
+const HISTORY_MAX: u32 = 0xffff;
+
+/// killer moves and history scores, kept from one search to the next. killers are stored
+/// by ply from the root, so that the iterations of a search share them, and the ones of
+/// plies played since the last search are dropped. history scores are halved between
+/// moves, so that the ones of the current position count most.
 pub struct MoveOrderer {
     killer_moves: Vec<KillerEntry>,
     history_table: [[[u32; 64]; 64]; 2],
+    // depth index of the root of the current search
+    root_depth: usize,
+    // plies played in the game before the current search
+    game_ply: usize,
 }
 
 impl MoveOrderer {
@@ -53,23 +64,56 @@ impl MoveOrderer {
         Self {
             killer_moves: vec![Default::default(); depth + 1],
             history_table: [ARRAY2; 2],
+            root_depth: depth,
+            game_ply: 0,
         }
     }
 
-    #[inline(always)]
-    pub fn lift_killer_moves(&mut self, depth_to_lift: usize) {
-        for _ in 0..depth_to_lift {
-            self.killer_moves.insert(0, KillerEntry::default());
+    /// forgets all killers and history, e.g. for a new game
+    pub fn clear(&mut self) {
+        self.killer_moves.fill(KillerEntry::default());
+        self.history_table = [[[0; 64]; 64]; 2];
+        self.game_ply = 0;
+    }
+
+    /// prepares a search from a root at depth index `root_depth`, `game_ply` plies into the
+    /// game. a root further into the game than the last one is a new move.
+    pub fn new_search(&mut self, root_depth: usize, game_ply: usize) {
+        if game_ply > self.game_ply {
+            let played = (game_ply - self.game_ply).min(self.killer_moves.len());
+            self.killer_moves.drain(..played);
+            self.age_history();
+        } else if game_ply < self.game_ply {
+            // moves were taken back, the killers belong to another line
+            self.killer_moves.fill(KillerEntry::default());
+            self.age_history();
+        }
+        self.game_ply = game_ply;
+        self.root_depth = root_depth;
+        if self.killer_moves.len() <= root_depth {
+            self.killer_moves.resize(root_depth + 1, Default::default());
+        }
+    }
+
+    fn age_history(&mut self) {
+        for value in self.history_table.iter_mut().flatten().flatten() {
+            *value /= 2;
         }
     }
 
+    #[inline(always)]
+    fn killers(&self, depth: usize) -> &KillerEntry {
+        &self.killer_moves[self.root_depth - depth]
+    }
+
     // pub fn get(&self, depth: usize) -> KillerEntry{
     //     self.killer_moves[depth]
     // }
     
     #[inline(always)]
     pub fn insert_killer_move(&mut self, mov: Move, depth: usize) {
-        self.killer_moves[depth].insert(mov);
+        let ply = self.root_depth - depth;
+        self.killer_moves[ply].insert(mov);
     }
 
     #[inline(always)]
@@ -85,14 +129,14 @@ impl MoveOrderer {
         let cmp1 = self.get_move_cmp_key(
             x,
             last_move_pos,
-            self.killer_moves[depth],
+            *self.killers(depth),
             player,
             transposition_move,
         );
         cmp1.cmp(&self.get_move_cmp_key(
             y,
             last_move_pos,
-            self.killer_moves[depth],
+            *self.killers(depth),
             player,
             transposition_move,
         ))
@@ -146,8 +190,11 @@ impl MoveOrderer {
     pub fn update_history(&mut self, mov: Move, player: Player, normalized_depth: usize) {
         match Self::get_move_index(mov, player) {
             Some((prev_pos, new_pos)) => {
-                self.history_table[player as usize][prev_pos as usize][new_pos as usize] +=
-                    (normalized_depth as u32) * (normalized_depth as u32)
+                let value = &mut self.history_table[player as usize][prev_pos as usize][new_pos as usize];
+                *value += (normalized_depth as u32) * (normalized_depth as u32);
+                if *value > HISTORY_MAX {
+                    self.age_history();
+                }
             }
             None => (),
         }
@@ -155,7 +202,7 @@ impl MoveOrderer {
 
     #[inline(always)]
     pub fn move_is_killer(&self, mov: Move, depth: usize) -> bool {
-        self.killer_moves[depth].contains(mov)
+        self.killers(depth).contains(mov)
     }
 
     #[inline(always)]
@@ -205,7 +252,8 @@ impl MoveOrderer {
                             (3 << 16) + 1
                         }
                     } else {
-                        (5 << 16) + (1 << 16) - 1 - see as u32
+                        // losing captures last, the bigger the loss the later
+                        (5 << 16) + (1 << 16) - 1 + see.unsigned_abs()
                     }
                 } else if killer_entry.contains(mov) {
                     // println!("killer move");
@@ -213,8 +261,7 @@ impl MoveOrderer {
                 } else if let Some((prev_pos, new_pos)) = Self::get_move_index(mov, player) {
                     (4 << 16) + (1 << 16)
                         - 1
-                        - (self.history_table[player as usize][prev_pos as usize][new_pos as usize]
-                            & 0xffff)
+                        - self.history_table[player as usize][prev_pos as usize][new_pos as usize]
                 } else {
                     (4 << 16) + (1 << 16) - 1
                 }
@@ -1,129 +1,301 @@
-use crate::{config::HashType, types::Move, GameState};
+use crate::{
+    config::{HashType, MOVE_TABLE_SIZE},
+    eval::{PHASE_MAX, WIN_THRESHOLD},
+    syzygy::TB_WIN,
+    types::{Move, PackedMove},
+    GameState,
+};
 
+/// how the stored value relates to the score of the position
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    /// the search failed low, the score is at most the value
+    Upper = 1,
+    /// the search failed high, the score is at least the value
+    Lower = 2,
+    Exact = 3,
+}
+
+/// a transposition table entry as seen by the search
 #[derive(Clone, Copy)]
 pub struct MoveEntry {
-    pub hash: HashType,
     pub mov: Move, // the move following this position to test for legality
     pub depth: u8,
     pub value: i32,
+    pub bound: Bound,
 }
 
-impl Default for MoveEntry {
-    fn default() -> Self {
-        Self {
-            hash: 0,
-            mov: Move::Castle { is_short: false },
-            depth: u8::MAX,
-            value: 0,
-        }
+// scores are stored in centipawns. won and lost positions keep their distance from the
+// threshold so that faster wins still sort first.
+const TB_CODE: i32 = 20_000;
+const MATE_CODE: i32 = 30_000;
+const MAX_DISTANCE: i32 = 2_000;
+
+fn pack_value(value: i32, bound: Bound) -> i16 {
+    let magnitude = value.abs();
+    let code = if magnitude >= WIN_THRESHOLD {
+        value.signum() * (MATE_CODE + (magnitude - WIN_THRESHOLD).min(MAX_DISTANCE))
+    } else if magnitude >= TB_WIN {
+        value.signum() * (TB_CODE + (magnitude - TB_WIN).min(MAX_DISTANCE))
+    } else {
+        // round towards the side the bound allows, so a cutoff on the stored value is
+        // still a cutoff on the real one
+        let code = match bound {
+            Bound::Upper => -(-value).div_euclid(PHASE_MAX),
+            Bound::Lower | Bound::Exact => value.div_euclid(PHASE_MAX),
+        };
+        code.clamp(-TB_CODE + 1, TB_CODE - 1)
+    };
+    code as i16
+}
+
+fn unpack_value(code: i16) -> i32 {
+    let code = code as i32;
+    let magnitude = code.abs();
+    if magnitude >= MATE_CODE {
+        code.signum() * (WIN_THRESHOLD + magnitude - MATE_CODE)
+    } else if magnitude >= TB_CODE {
+        code.signum() * (TB_WIN + magnitude - TB_CODE)
+    } else {
+        code * PHASE_MAX
     }
 }
 
-#[derive(Clone, Copy)]
-struct MoveTableBucket(MoveEntry, MoveEntry);
+const BOUND_MASK: u8 = 0b11;
+const AGE_SHIFT: u8 = 2;
+const AGE_MASK: u8 = 0b11_1111;
 
-impl Default for MoveTableBucket {
-    fn default() -> Self {
-        Self(Default::default(), Default::default())
+/// 8 bytes: the top 16 bits of the hash, the move, the value, depth, and bound and search
+/// age sharing a byte. a bound of 0 marks an empty slot.
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+struct PackedEntry {
+    key: u16,
+    mov: PackedMove,
+    value: i16,
+    depth: u8,
+    bound_and_age: u8,
+}
+
+impl PackedEntry {
+    #[inline(always)]
+    fn is_empty(self) -> bool {
+        self.bound_and_age & BOUND_MASK == 0
+    }
+
+    #[inline(always)]
+    fn age(self) -> u8 {
+        self.bound_and_age >> AGE_SHIFT
+    }
+
+    #[inline(always)]
+    fn bound(self) -> Bound {
+        match self.bound_and_age & BOUND_MASK {
+            1 => Bound::Upper,
+            2 => Bound::Lower,
+            _ => Bound::Exact,
+        }
+    }
+
+    #[inline(always)]
+    fn unpack(self, state: &GameState) -> Option<MoveEntry> {
+        let mov = self.mov.to_move(state)?;
+        Some(MoveEntry {
+            mov,
+            depth: self.depth,
+            value: unpack_value(self.value),
+            bound: self.bound(),
+        })
     }
 }
 
+const ENTRIES_PER_BUCKET: usize = 8;
+
+/// one cache line
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(64))]
+struct MoveTableBucket([PackedEntry; ENTRIES_PER_BUCKET]);
+
 impl MoveTableBucket {
-    fn insert(&mut self, entry: MoveEntry) {
-        if self.0.hash == entry.hash || self.1.hash == entry.hash{
-            if self.0.hash == entry.hash && self.0.depth < entry.depth{
-                self.0 = entry;
-            }
-            if self.1.hash == entry.hash && self.1.depth < entry.depth{
-                self.1 = entry;
+    #[inline(always)]
+    fn find(&self, key: u16) -> Option<PackedEntry> {
+        self.0.iter().copied().find(|entry| !entry.is_empty() && entry.key == key)
+    }
+
+    fn insert(&mut self, entry: PackedEntry, generation: u8) {
+        // same position: keep the deeper result, unless it is left over from an older search
+        if let Some(slot) = self.0.iter_mut().find(|slot| !slot.is_empty() && slot.key == entry.key) {
+            if slot.depth < entry.depth || slot.age() != generation {
+                *slot = entry;
             }
             return;
         }
-        // if self.0.depth > entry.depth {
-            // if self.0.hash != entry.hash{
-                self.1 = self.0;
-            // }
-            self.0 = entry;
-        // } else {
-            // self.1 = entry
-        // }
+        // otherwise an empty slot, or the entry worth least: old searches first, then shallow
+        let slot = self
+            .0
+            .iter_mut()
+            .min_by_key(|slot| {
+                if slot.is_empty() {
+                    i32::MIN
+                } else {
+                    let age = (generation.wrapping_sub(slot.age()) & AGE_MASK) as i32;
+                    slot.depth as i32 - 8 * age
+                }
+            })
+            .expect("buckets are not empty");
+        *slot = entry;
     }
 }
 
-pub struct MoveTable<const MOVE_TABLE_SIZE: usize> {
+/// transposition table of 64-byte buckets. the number of buckets is a power of two.
+pub struct MoveTable {
+    // empty until `allocate`, so a table that is resized before the first search never
+    // takes the default size
     table: Vec<MoveTableBucket>,
+    buckets: usize,
+    generation: u8,
 }
 
-impl<const MOVE_TABLE_SIZE: usize> Default for MoveTable<MOVE_TABLE_SIZE> {
+impl Default for MoveTable {
     fn default() -> Self {
         Self {
-            table: vec![MoveTableBucket::default(); MOVE_TABLE_SIZE],
+            table: vec![],
+            buckets: MOVE_TABLE_SIZE,
+            generation: 0,
         }
     }
 }
 
-impl<const MOVE_TABLE_SIZE: usize> MoveTable<MOVE_TABLE_SIZE> {
+impl MoveTable {
+    /// the largest table that fits in `megabytes`, at least one bucket. fails instead of
+    /// aborting when the memory isn't available.
+    pub fn with_size_mb(megabytes: usize) -> Result<Self, &'static str> {
+        let bytes = megabytes.checked_mul(1 << 20).ok_or("hash size too large")?;
+        let buckets = bytes / std::mem::size_of::<MoveTableBucket>();
+        let mut table = Self {
+            buckets: if buckets == 0 { 1 } else { 1 << buckets.ilog2() },
+            ..Default::default()
+        };
+        table.allocate()?;
+        Ok(table)
+    }
+
+    /// allocates the table if that hasn't happened yet. it must be allocated before any
+    /// lookups.
+    pub fn allocate(&mut self) -> Result<(), &'static str> {
+        if self.table.is_empty() {
+            self.table
+                .try_reserve_exact(self.buckets)
+                .map_err(|_| "not enough memory for the hash size")?;
+            self.table.resize(self.buckets, MoveTableBucket::default());
+        }
+        Ok(())
+    }
+
+    pub fn size_mb(&self) -> usize {
+        (self.buckets * std::mem::size_of::<MoveTableBucket>()) >> 20
+    }
+
+    /// forgets all entries
+    pub fn clear(&mut self) {
+        self.table.fill(MoveTableBucket::default());
+        self.generation = 0;
+    }
+
+    #[inline(always)]
+    fn bucket(&self, hash: HashType) -> usize {
+        hash as usize & (self.table.len() - 1)
+    }
+
+    #[inline(always)]
+    fn key(hash: HashType) -> u16 {
+        (hash >> 48) as u16
+    }
+
+    /// entries stored from now on count as newer than the ones already there
+    pub fn new_search(&mut self) {
+        self.generation = (self.generation + 1) & AGE_MASK;
+    }
+
     #[inline(always)]
     pub fn get_entry_for_direct_cutoff(&self, hash: HashType, depth: u8, state: &GameState) -> Option<MoveEntry> {
-        let ind = hash as usize & (MOVE_TABLE_SIZE - 1);
-        if self.table[ind].0.depth % 2 == depth % 2
-            && self.table[ind].0.depth >= depth
-            && self.table[ind].0.hash == hash
-            && state.check_move_legal(self.table[ind].0.mov)
-        {
-            Some(self.table[ind].0)
-        } else if self.table[ind].1.depth % 2 == depth % 2
-            && self.table[ind].1.depth >= depth
-            && self.table[ind].1.hash == hash
-            && state.check_move_legal(self.table[ind].1.mov)
-        {
-            Some(self.table[ind].1)
+        let entry = self.table[self.bucket(hash)].find(Self::key(hash))?;
+        if entry.depth % 2 == depth % 2 && entry.depth >= depth {
+            entry.unpack(state).filter(|entry| state.is_pseudo_legal(entry.mov))
         } else {
             None
         }
-        // None
+    }
+
+    /// the move stored for `hash` whatever its depth, e.g. to follow the principal variation
+    pub fn get_move(&self, hash: HashType, state: &GameState) -> Option<Move> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let entry = self.table[self.bucket(hash)].find(Self::key(hash))?;
+        entry
+            .unpack(state)
+            .map(|entry| entry.mov)
+            .filter(|&mov| state.is_pseudo_legal(mov))
     }
 
     #[inline(always)]
     pub fn get_entry_for_ordering(&self, hash: HashType, depth: u8, state: &GameState) -> Option<MoveEntry> {
-        let ind = hash as usize & (MOVE_TABLE_SIZE - 1);
-        if self.table[ind].0.depth % 2 == depth % 2
-            // && self.table[ind].0.depth >= depth
-            && self.table[ind].0.hash == hash
-            && state.check_move_legal(self.table[ind].0.mov)
-        {
-            Some(self.table[ind].0)
-        } else if self.table[ind].1.depth % 2 == depth % 2
-            // && self.table[ind].1.depth >= depth
-            && self.table[ind].1.hash == hash
-            && state.check_move_legal(self.table[ind].1.mov)
-        {
-            Some(self.table[ind].1)
+        let entry = self.table[self.bucket(hash)].find(Self::key(hash))?;
+        if entry.depth % 2 == depth % 2 {
+            entry.unpack(state).filter(|entry| state.is_pseudo_legal(entry.mov))
         } else {
             None
         }
-        // None
     }
 
+    /// `state` is the position the entry is for
+    #[inline(always)]
+    pub fn insert_entry(&mut self, hash: HashType, entry: MoveEntry, state: &GameState) {
+        let packed = PackedEntry {
+            key: Self::key(hash),
+            mov: PackedMove::from_move(entry.mov, state),
+            value: pack_value(entry.value, entry.bound),
+            depth: entry.depth,
+            bound_and_age: entry.bound as u8 | self.generation << AGE_SHIFT,
+        };
+        let generation = self.generation;
+        let bucket = self.bucket(hash);
+        self.table[bucket].insert(packed, generation);
+    }
+}
+
+/// node counts for perft, which do not fit the search table's 16-bit values
+#[derive(Default)]
+pub struct PerftTable {
+    // hash, and the count with the depth in the top byte
+    table: Vec<(HashType, u64)>,
+}
+
+impl PerftTable {
+    const SIZE: usize = 1 << 20;
+
     #[inline(always)]
-    pub fn get_entry_perft(
-        &self,
-        hash: HashType,
-        depth: u8,
-    ) -> Option<MoveEntry> {
-        let ind = hash as usize & (MOVE_TABLE_SIZE - 1);
-        if self.table[ind].0.depth == depth && self.table[ind].0.hash == hash {
-            Some(self.table[ind].0)
-        } else if self.table[ind].1.depth == depth && self.table[ind].1.hash == hash {
-            Some(self.table[ind].1)
+    pub fn get(&self, hash: HashType, depth: u8) -> Option<i64> {
+        let (stored_hash, packed) = *self.table.get(hash as usize & (Self::SIZE - 1))?;
+        if stored_hash == hash && (packed >> 56) as u8 == depth {
+            Some((packed & ((1 << 56) - 1)) as i64)
         } else {
             None
         }
     }
 
     #[inline(always)]
-    pub fn insert_entry(&mut self, entry: MoveEntry) {
-        let ind = entry.hash as usize & (MOVE_TABLE_SIZE - 1);
-        self.table[ind].insert(entry);
+    pub fn insert(&mut self, hash: HashType, depth: u8, count: i64) {
+        let index = hash as usize & (Self::SIZE - 1);
+        self.entries()[index] = (hash, (depth as u64) << 56 | count as u64);
+    }
+
+    // only allocated once perft runs
+    fn entries(&mut self) -> &mut Vec<(HashType, u64)> {
+        if self.table.is_empty() {
+            self.table = vec![(0, 0); Self::SIZE];
+        }
+        &mut self.table
     }
 }
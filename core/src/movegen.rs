@@ -1,4 +1,5 @@
 use crate::{
+    attacks,
     grid::{Grid, PieceGrid},
     markers::*,
     Player,
@@ -221,20 +222,6 @@ impl Rays {
             | Self::ray_down_occluded(grid, empty_grid)
     }
 
-    #[inline(always)]
-    pub fn ray_horizontal_vertical_captures(
-        grid: Grid,
-        empty_grid: Grid,
-        opposing_pieces: Grid,
-    ) -> Grid {
-        let left = Self::ray_left_occluded(grid, empty_grid);
-        let right = Self::ray_right_occluded(grid, empty_grid);
-        let up = Self::ray_up_occluded(grid, empty_grid);
-        let down = Self::ray_down_occluded(grid, empty_grid);
-        (Common::left(left) | Common::right(right) | Common::up(up) | Common::down(down))
-            & opposing_pieces
-    }
-
     #[inline(always)]
     /// includes the original piece positions
     pub fn ray_horizontal_vertical_occluded_with_captures_and_non_captures(
@@ -261,19 +248,6 @@ impl Rays {
             | Self::ray_top_right_occluded(grid, empty_grid)
     }
 
-    #[inline(always)]
-    pub fn ray_diagonal_captures(grid: Grid, empty_grid: Grid, opposing_pieces: Grid) -> Grid {
-        let bottom_left = Self::ray_bottom_left_occluded(grid, empty_grid);
-        let bottom_right = Self::ray_bottom_right_occluded(grid, empty_grid);
-        let top_left = Self::ray_top_left_occluded(grid, empty_grid);
-        let top_right = Self::ray_top_right_occluded(grid, empty_grid);
-        (Common::bottom_left(bottom_left)
-            | Common::bottom_right(bottom_right)
-            | Common::top_left(top_left)
-            | Common::top_right(top_right))
-            & opposing_pieces
-    }
-
     #[inline(always)]
     /// includes the original piece positions
     pub fn ray_diagonal_occluded_with_captures_and_non_captures(
@@ -314,22 +288,6 @@ impl Knight {
             | Common::top_right(right))
             & !player_pieces
     }
-    #[inline(always)]
-    pub fn captures(grid: Grid, opposing_pieces: Grid) -> Grid {
-        let up = Common::up(grid);
-        let down = Common::down(grid);
-        let left = Common::left(grid);
-        let right = Common::right(grid);
-        (Common::top_left(up)
-            | Common::top_right(up)
-            | Common::top_left(left)
-            | Common::bottom_left(left)
-            | Common::bottom_left(down)
-            | Common::bottom_right(down)
-            | Common::bottom_right(right)
-            | Common::top_right(right))
-            & opposing_pieces
-    }
 }
 
 impl Pawn {
@@ -338,6 +296,8 @@ impl Pawn {
     const RIGHTMOST_COLUMN: Grid = Grid::from_u64(0x01010101_01010101);
     const BLACK_EN_PASSANT_SQUARES: Grid = Grid::from_u64(0xff000000);
     const WHITE_EN_PASSANT_SQUARES: Grid = Grid::from_u64(0xff_00000000);
+    const BACK_RANK_WHITE: Grid = Grid::from_u64(0xff);
+    const BACK_RANK_BLACK: Grid = Grid::from_u64(0xff000000_00000000);
 
     #[inline(always)]
     const fn home_pawn_row<P: PlayerMarker>() -> Grid {
@@ -411,6 +371,16 @@ impl Pawn {
         }
     }
 
+    #[inline(always)]
+    /// double moves from the player's first rank, which only horde allows
+    pub fn back_rank_double_moves<P: PlayerMarker>(grid: Grid, empty_grid: Grid) -> Grid {
+        if P::IS_WHITE {
+            Common::up(Common::up(grid & Self::BACK_RANK_WHITE) & empty_grid) & empty_grid
+        } else {
+            Common::down(Common::down(grid & Self::BACK_RANK_BLACK) & empty_grid) & empty_grid
+        }
+    }
+
     #[inline(always)]
     pub fn regular_promotions<P: PlayerMarker>(grid: Grid, empty_grid: Grid) -> Grid {
         let grid = grid & Self::opposing_home_pawn_row::<P>();
@@ -457,36 +427,15 @@ impl Pawn {
 }
 
 impl King {
+    /// pieces of `P` attacking `pos`, found by looking outwards from `pos` with each kind of
+    /// piece. `occupied` decides which slider rays are blocked.
     #[inline(always)]
-    pub fn pieces_attacking<P: PlayerMarker>(
-        piece_grid: PieceGrid,
-        empty_grid: Grid,
-        opposing_pieces: Grid,
-    ) -> Grid {
-        Rays::ray_diagonal_occluded_with_captures_and_non_captures(
-            piece_grid.get_bishops_queens::<P>(),
-            empty_grid,
-            opposing_pieces,
-        ) | Rays::ray_horizontal_vertical_occluded_with_captures_and_non_captures(
-            piece_grid.get_rooks_queens::<P>(),
-            empty_grid,
-            opposing_pieces,
-        ) | Knight::moves(piece_grid.get_knight_pos::<P>(), Grid::EMPTY)
-            | Pawn::squares_attacked::<P>(piece_grid.get_pawn_pos::<P>())
-            | King::regular_moves(piece_grid.get_king_pos::<P>(), Grid::EMPTY)
-    }
-
-    #[inline(always)]
-    pub fn captures(grid: Grid, opposing_pieces: Grid) -> Grid {
-        (Common::up(grid)
-            | Common::left(grid)
-            | Common::down(grid)
-            | Common::right(grid)
-            | Common::top_left(grid)
-            | Common::top_right(grid)
-            | Common::bottom_left(grid)
-            | Common::bottom_right(grid))
-            & opposing_pieces
+    pub fn pieces_attacking<P: PlayerMarker>(piece_grid: &PieceGrid, pos: u8, occupied: Grid) -> Grid {
+        (attacks::bishop_attacks(pos, occupied) & piece_grid.get_bishops_queens::<P>())
+            | (attacks::rook_attacks(pos, occupied) & piece_grid.get_rooks_queens::<P>())
+            | (attacks::knight_attacks(pos) & piece_grid.get_knight_pos::<P>())
+            | (attacks::pawn_attacks(P::PLAYER.opp(), pos) & piece_grid.get_pawn_pos::<P>())
+            | (attacks::king_attacks(pos) & piece_grid.get_king_pos::<P>())
     }
 
     #[inline(always)]
@@ -502,34 +451,14 @@ impl King {
             & !player_pieces
     }
 
-    const WHITE_CASTLE_SHORT_FREE: Grid = Grid::from_u64(0xe);
-    const WHITE_CASTLE_LONG_FREE: Grid = Grid::from_u64(0xe << 2);
-    const BLACK_CASTLE_SHORT_FREE: Grid = Grid::from_u64(0xe << 56);
-    const BLACK_CASTLE_LONG_FREE: Grid = Grid::from_u64(0xe << 58);
-
-    #[inline(always)]
-    const fn castle_free_grid<P: PlayerMarker, C: CastleTypeMarker>() -> Grid {
-        if C::IS_SHORT {
-            if P::IS_WHITE {
-                Self::WHITE_CASTLE_SHORT_FREE
-            } else {
-                Self::BLACK_CASTLE_SHORT_FREE
-            }
-        } else {
-            if P::IS_WHITE {
-                Self::WHITE_CASTLE_LONG_FREE
-            } else {
-                Self::BLACK_CASTLE_LONG_FREE
-            }
-        }
-    }
-
     #[inline(always)]
-    /// occlusion grid must not include the player king.
-    pub fn can_castle<P: PlayerMarker, C: CastleTypeMarker>(
+    /// free_grid must not include the castling king and rook.
+    pub fn can_castle(
         attacked_grid: Grid,
-        occlusion_grid: Grid,
+        occupied_grid: Grid,
+        free_grid: Grid,
+        safe_grid: Grid,
     ) -> bool {
-        Self::castle_free_grid::<P, C>() & (attacked_grid | occlusion_grid) == Grid::EMPTY
+        (free_grid & occupied_grid) | (safe_grid & attacked_grid) == Grid::EMPTY
     }
 }
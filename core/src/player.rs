@@ -1,6 +1,6 @@
 use serde::{de::Error, Deserialize};
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub enum Player {
     White = 0,
     Black = 1,
@@ -0,0 +1,150 @@
+use rand::Rng;
+
+use crate::{eval::PHASE_MAX, player::Player, types::Move};
+
+pub const MAX_SKILL_LEVEL: u8 = 20;
+
+/// elo of each skill level, measured by self-play of each level against the next one (see
+/// the `skill-match` command of the cli, 20 games at depth 6) and anchored at
+/// `FULL_STRENGTH_ELO`
+const LEVEL_ELO: [(f64, f64); 6] = [
+    (0.0, 490.0),
+    (4.0, 830.0),
+    (8.0, 1190.0),
+    (12.0, 1570.0),
+    (16.0, 1890.0),
+    (20.0, FULL_STRENGTH_ELO),
+];
+
+/// rough rating of the unlimited engine at club time controls. the levels below are
+/// only measured relative to it.
+const FULL_STRENGTH_ELO: f64 = 2400.0;
+
+/// weakens the engine to a level from 0 to `MAX_SKILL_LEVEL`: the search is cut short by
+/// depth and nodes, and the move is picked among the best few root moves with some
+/// randomness, now and then with a deliberate inaccuracy. the top level plays at full
+/// strength.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Skill {
+    // fractional, so that an elo between two levels can be played
+    level: f64,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Self::from_level(MAX_SKILL_LEVEL)
+    }
+}
+
+impl Skill {
+    pub fn from_level(level: u8) -> Self {
+        Self {
+            level: level.min(MAX_SKILL_LEVEL) as f64,
+        }
+    }
+
+    /// the level expected to play at `elo`, within `min_elo()..=max_elo()`
+    pub fn from_elo(elo: u32) -> Self {
+        let elo = (elo as f64).clamp(LEVEL_ELO[0].1, FULL_STRENGTH_ELO);
+        let upper = LEVEL_ELO
+            .iter()
+            .position(|&(_, level_elo)| level_elo >= elo)
+            .unwrap()
+            .max(1);
+        let (low_level, low_elo) = LEVEL_ELO[upper - 1];
+        let (high_level, high_elo) = LEVEL_ELO[upper];
+        Self {
+            level: low_level + (high_level - low_level) * (elo - low_elo) / (high_elo - low_elo),
+        }
+    }
+
+    pub fn min_elo() -> u32 {
+        LEVEL_ELO[0].1 as u32
+    }
+
+    pub fn max_elo() -> u32 {
+        FULL_STRENGTH_ELO as u32
+    }
+
+    pub fn level(self) -> f64 {
+        self.level
+    }
+
+    pub fn is_full_strength(self) -> bool {
+        self.level >= MAX_SKILL_LEVEL as f64
+    }
+
+    /// deepest search, from 2 plies at level 0 to 9 just below full strength
+    pub fn max_depth(self) -> Option<usize> {
+        if self.is_full_strength() {
+            return None;
+        }
+        Some(2 + (self.level * 0.375) as usize)
+    }
+
+    /// nodes per move, from 1000 at level 0, doubling every two levels
+    pub fn max_nodes(self) -> Option<u64> {
+        if self.is_full_strength() {
+            return None;
+        }
+        Some((1000.0 * 2f64.powf(self.level / 2.0)) as u64)
+    }
+
+    /// root moves to search fully, so that there is something to pick from
+    pub fn multi_pv(self) -> usize {
+        if self.is_full_strength() {
+            1
+        } else {
+            4
+        }
+    }
+
+    /// chance of a deliberate inaccuracy, 15% at level 0 and none at full strength
+    fn inaccuracy_chance(self) -> f64 {
+        0.15 * (1.0 - self.level / MAX_SKILL_LEVEL as f64)
+    }
+
+    /// picks one of `lines`, which are best first with scores from white's side as
+    /// `ChessEngine::search_lines` returns them. returns its index.
+    ///
+    /// each line gets a random bonus that grows the weaker the level is and the further the
+    /// line is behind the best one, so a weak level often plays a move a little worse than
+    /// the best. sometimes it just plays any line that does not lose too much.
+    pub fn pick_line(self, lines: &[(i32, Vec<Move>)], player: Player) -> usize {
+        if self.is_full_strength() || lines.len() < 2 {
+            return 0;
+        }
+        let mut rng = rand::thread_rng();
+        // centipawns from the side to move's perspective
+        let scores: Vec<i32> = lines
+            .iter()
+            .map(|&(score, _)| match player {
+                Player::White => score / PHASE_MAX,
+                Player::Black => -score / PHASE_MAX,
+            })
+            .collect();
+        let top = scores[0];
+
+        if rng.gen::<f64>() < self.inaccuracy_chance() {
+            // up to three pawns at level 0, half a pawn close to full strength
+            let margin = 50 + (250.0 * (1.0 - self.level / MAX_SKILL_LEVEL as f64)) as i32;
+            let playable: Vec<usize> = (1..scores.len())
+                .filter(|&i| top - scores[i] <= margin)
+                .collect();
+            if !playable.is_empty() {
+                return playable[rng.gen_range(0, playable.len())];
+            }
+        }
+
+        let weakness = 120 - (2.0 * self.level) as i32;
+        let delta = (top - scores[scores.len() - 1]).min(100);
+        let mut best = (i32::MIN, 0);
+        for (i, &score) in scores.iter().enumerate() {
+            let push = (weakness * (top - score) + delta * rng.gen_range(0, weakness)) / 128;
+            if score + push > best.0 {
+                best = (score + push, i);
+            }
+        }
+        best.1
+    }
+}
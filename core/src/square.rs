@@ -0,0 +1,102 @@
+use std::{fmt::Display, str::FromStr};
+
+/// a square of the board.
+///
+/// the index is the usual little-endian rank-file numbering: a1 = 0, b1 = 1, ..., h8 = 63.
+/// internally the engine numbers squares the other way round within a rank (h1 = 0, a1 = 7);
+/// that numbering is called `pos` and is what the fields of `Move` hold.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    /// `file` 0 is the a-file, `rank` 0 is the first rank
+    pub const fn new(file: u8, rank: u8) -> Option<Self> {
+        if file < 8 && rank < 8 {
+            Some(Self((rank << 3) + 7 - file))
+        } else {
+            None
+        }
+    }
+
+    /// a1 = 0 ... h8 = 63
+    pub const fn from_index(index: u8) -> Option<Self> {
+        if index < 64 {
+            Some(Self(index ^ 7))
+        } else {
+            None
+        }
+    }
+
+    /// a1 = 0 ... h8 = 63
+    #[inline(always)]
+    pub const fn index(self) -> u8 {
+        self.0 ^ 7
+    }
+
+    /// 0 is the a-file
+    #[inline(always)]
+    pub const fn file(self) -> u8 {
+        7 - (self.0 & 0b111)
+    }
+
+    /// 0 is the first rank
+    #[inline(always)]
+    pub const fn rank(self) -> u8 {
+        self.0 >> 3
+    }
+
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(|index| Self(index ^ 7))
+    }
+
+    /// h1 = 0, a1 = 7 ... a8 = 63
+    pub const fn from_pos(pos: u8) -> Option<Self> {
+        if pos < 64 {
+            Some(Self(pos))
+        } else {
+            None
+        }
+    }
+
+    /// h1 = 0, a1 = 7 ... a8 = 63
+    #[inline(always)]
+    pub const fn pos(self) -> u8 {
+        self.0
+    }
+
+    #[inline(always)]
+    pub(crate) const fn from_pos_unchecked(pos: u8) -> Self {
+        Self(pos)
+    }
+}
+
+impl Display for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", (b'a' + self.file()) as char, self.rank() + 1)
+    }
+}
+
+impl std::fmt::Debug for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Square({})", self)
+    }
+}
+
+impl FromStr for Square {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Ok(Self::new(file - b'a', rank - b'1').unwrap()),
+            _ => Err("invalid square"),
+        }
+    }
+}
+
+impl TryFrom<&str> for Square {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
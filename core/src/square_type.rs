@@ -108,13 +108,16 @@ impl SquareType{
         self.0 >> 1 == 0
     }
     #[inline(always)]
-    pub fn check_valid(self) {
+    pub fn is_valid(self) -> bool {
         // cannot be empty + black, or with pieces bits set to 1
-        let check = self.0 != 1 && (self.0 >> 1) != 1;
-        if !check{
+        self.0 != 1 && (self.0 >> 1) != 1
+    }
+    #[inline(always)]
+    pub fn check_valid(self) {
+        if !self.is_valid(){
             println!("failing square_type: {}", self.0);
         }
-        assert! (self.0 != 1 && (self.0 >> 1) != 1)
+        assert! (self.is_valid())
     }
     #[inline(always)]
     pub fn get_piece_hash(self, pos: u8, zoborist_state: &ZoboristState) -> HashType{
@@ -0,0 +1,54 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// ends a running search from another thread, right away or at a deadline. clones share
+/// the same signal. see `ChessEngine::stop_signal`.
+#[derive(Clone, Default)]
+pub struct StopSignal {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    stopped: AtomicBool,
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl StopSignal {
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// stops the search once `deadline` has passed. none removes the deadline.
+    pub fn set_deadline(&self, deadline: Option<Instant>) {
+        *self.inner.deadline.lock().unwrap() = deadline;
+    }
+
+    /// clears the stop and the deadline before the next search
+    pub fn reset(&self) {
+        self.set_deadline(None);
+        self.inner.stopped.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::Relaxed)
+    }
+
+    /// checks the deadline as well, called by the search every few nodes
+    pub(crate) fn poll(&self) -> bool {
+        if self.is_stopped() {
+            return true;
+        }
+        let deadline = *self.inner.deadline.lock().unwrap();
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.stop();
+            return true;
+        }
+        false
+    }
+}
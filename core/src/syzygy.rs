@@ -0,0 +1,1345 @@
+//! Syzygy WDL/DTZ tablebase probing.
+//!
+//! Tables are read lazily from `.rtbw`/`.rtbz` files the first time a position with
+//! their material is probed. The decoding follows the reference implementation of the
+//! format (Ronald de Man's probing code, as adapted by Stockfish). Squares inside this
+//! module use the tablebase numbering (a1 = 0, h1 = 7, a8 = 56), which is the engine's
+//! position numbering with the file mirrored: `pos ^ 7`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use hashbrown::HashMap;
+
+use crate::{
+    eval::WIN_THRESHOLD,
+    move_buffer::MoveBuffer,
+    player::Player,
+    types::Move,
+    types_for_io::Piece,
+    variant::Variant,
+    zoborist_state::ZoboristState,
+    GameState,
+};
+
+/// score given to tablebase wins in the search. below mate scores, above any evaluation.
+pub const TB_WIN: i32 = WIN_THRESHOLD / 2;
+
+const TB_PIECES: usize = 7;
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// per-table flags, stored in the first byte of each PairsData
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// piece order used in table names
+const PIECE_CHARS: [(char, Piece); 6] = [
+    ('K', Piece::King),
+    ('Q', Piece::Queen),
+    ('R', Piece::Rook),
+    ('B', Piece::Bishop),
+    ('N', Piece::Knight),
+    ('P', Piece::Pawn),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_i32(value: i32) -> Self {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn neg(self) -> Self {
+        Self::from_i32(-(self as i32))
+    }
+
+    /// search score from the side to move's perspective. cursed wins and blessed losses
+    /// are drawn under the 50 move rule, so they score next to a draw.
+    pub fn to_score(self, depth: usize) -> i32 {
+        match self {
+            Wdl::Loss => -TB_WIN - depth as i32,
+            Wdl::BlessedLoss => -1,
+            Wdl::Draw => 0,
+            Wdl::CursedWin => 1,
+            Wdl::Win => TB_WIN + depth as i32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProbeState {
+    Ok,
+    /// the best move is a capture or pawn move, so the stored DTZ can't be used
+    ZeroingBestMove,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+struct Consts {
+    map_b1h1h7: [u64; 64],
+    map_a1d1d4: [u64; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; TB_PIECES],
+    map_pawns: [u64; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+#[inline(always)]
+fn file_of(sq: u8) -> i32 {
+    (sq & 7) as i32
+}
+
+#[inline(always)]
+fn rank_of(sq: u8) -> i32 {
+    (sq >> 3) as i32
+}
+
+#[inline(always)]
+fn off_a1h8(sq: u8) -> i32 {
+    rank_of(sq) - file_of(sq)
+}
+
+fn consts() -> &'static Consts {
+    static CONSTS: OnceLock<Consts> = OnceLock::new();
+    CONSTS.get_or_init(|| {
+        let mut c = Consts {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; TB_PIECES],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        // squares below the a1-h8 diagonal map to 0..27
+        let mut code = 0;
+        for sq in 0..64u8 {
+            if off_a1h8(sq) < 0 {
+                c.map_b1h1h7[sq as usize] = code;
+                code += 1;
+            }
+        }
+
+        // the a1-d1-d4 triangle maps to 0..9, with the diagonal squares last
+        let mut diagonal = vec![];
+        code = 0;
+        for sq in 0..=27u8 {
+            if off_a1h8(sq) < 0 && file_of(sq) <= 3 {
+                c.map_a1d1d4[sq as usize] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 && file_of(sq) <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            c.map_a1d1d4[sq as usize] = code;
+            code += 1;
+        }
+
+        // the 462 legal placements of two kings with the first in the a1-d1-d4 triangle
+        let mut both_on_diagonal = vec![];
+        code = 0;
+        for idx in 0..10u64 {
+            for s1 in 0..=27u8 {
+                if c.map_a1d1d4[s1 as usize] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64u8 {
+                    let adjacent = (file_of(s1) - file_of(s2)).abs() <= 1
+                        && (rank_of(s1) - rank_of(s2)).abs() <= 1;
+                    if adjacent || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                        continue;
+                    }
+                    if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        c.map_kk[idx as usize][s2 as usize] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            c.map_kk[idx as usize][s2 as usize] = code;
+            code += 1;
+        }
+
+        c.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..TB_PIECES.min(n + 1) {
+                c.binomial[k][n] = if k > 0 { c.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { c.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // a2-h7 map to 0..47, with the leading pawn having the highest value
+        let mut available_squares = 47;
+        for lead_pawns_cnt in 1..=5 {
+            for file in 0..4u8 {
+                let mut idx = 0;
+                for rank in 1..=6u8 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns_cnt == 1 {
+                        c.map_pawns[sq as usize] = available_squares;
+                        c.map_pawns[(sq ^ 7) as usize] = available_squares.saturating_sub(1);
+                        available_squares = available_squares.saturating_sub(2);
+                    }
+                    c.lead_pawn_idx[lead_pawns_cnt][sq as usize] = idx;
+                    idx += c.binomial[lead_pawns_cnt - 1][c.map_pawns[sq as usize] as usize];
+                }
+                c.lead_pawns_size[lead_pawns_cnt][file as usize] = idx;
+            }
+        }
+        c
+    })
+}
+
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    max_sym_len: u8,
+    min_sym_len: u8,
+    sizeof_block: u64,
+    span: u64,
+    num_blocks: u64,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: u64,
+    sparse_index: usize,
+    sparse_index_size: u64,
+    data: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    pieces: [u8; TB_PIECES],
+    group_idx: [u64; TB_PIECES + 1],
+    group_len: [usize; TB_PIECES + 1],
+    map_idx: [u16; 4],
+}
+
+struct Table {
+    kind: TableKind,
+    data: Vec<u8>,
+    /// pieces of the stronger side, e.g. "KQ" for KQvK
+    white_signature: String,
+    black_signature: String,
+    symmetric: bool,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawn_count: [u8; 2],
+    /// indexed by [file][side]
+    pairs: Vec<[PairsData; 2]>,
+    map: usize,
+}
+
+const TRUNCATED: &str = "truncated tablebase file";
+const CORRUPTED: &str = "corrupted tablebase file";
+
+// every read of the table checks its bounds, so a damaged file is an error rather than a
+// panic and the table is treated as missing
+fn bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], &'static str> {
+    data.get(offset..)
+        .and_then(|rest| rest.get(..N))
+        .map(|bytes| bytes.try_into().expect("N bytes"))
+        .ok_or(TRUNCATED)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, &'static str> {
+    Ok(bytes::<1>(data, offset)?[0])
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Result<u16, &'static str> {
+    Ok(u16::from_le_bytes(bytes(data, offset)?))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, &'static str> {
+    Ok(u32::from_le_bytes(bytes(data, offset)?))
+}
+
+// block data may be read slightly past the end of the file when refilling the buffer
+fn read_u32_be(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = data.get(offset + i).copied().unwrap_or(0);
+    }
+    u32::from_be_bytes(bytes)
+}
+
+impl Table {
+    fn new(kind: TableKind, name: &str, data: Vec<u8>) -> Result<Self, &'static str> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if data.len() < 5 || data[..4] != magic {
+            return Err("corrupted tablebase file");
+        }
+        let (white, black) = name.split_once('v').ok_or("invalid tablebase name")?;
+        let counts = |part: &str| {
+            let mut counts = [0u8; 6];
+            for char in part.chars() {
+                let (_, piece) = PIECE_CHARS.iter().find(|(c, _)| *c == char).unwrap();
+                counts[*piece as usize] += 1;
+            }
+            counts
+        };
+        let (white_counts, black_counts) = (counts(white), counts(black));
+        let white_pawns = white_counts[Piece::Pawn as usize];
+        let black_pawns = black_counts[Piece::Pawn as usize];
+
+        // the leading colour is the side with fewer pawns, for better compression
+        let lead_is_white = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if lead_is_white {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+
+        let mut table = Self {
+            kind,
+            data,
+            white_signature: white.to_owned(),
+            black_signature: black.to_owned(),
+            symmetric: white == black,
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: white_counts[..Piece::King as usize]
+                .iter()
+                .chain(black_counts[..Piece::King as usize].iter())
+                .any(|&count| count == 1),
+            pawn_count,
+            pairs: vec![],
+            map: 0,
+        };
+        table.init()?;
+        Ok(table)
+    }
+
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        match self.kind {
+            TableKind::Wdl => &self.pairs[file][stm],
+            TableKind::Dtz => &self.pairs[file][0],
+        }
+    }
+
+    fn init(&mut self) -> Result<(), &'static str> {
+        const SPLIT: u8 = 1;
+        const HAS_PAWNS: u8 = 2;
+
+        let data = std::mem::take(&mut self.data);
+        let mut pos = 4;
+        let header = read_u8(&data, pos)?;
+        if (header & HAS_PAWNS != 0) != self.has_pawns
+            || (header & SPLIT != 0) != (!self.symmetric && self.kind == TableKind::Wdl)
+        {
+            return Err("tablebase header does not match its name");
+        }
+        pos += 1;
+
+        let sides = if self.kind == TableKind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        };
+        let num_files = if self.has_pawns { 4 } else { 1 };
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        self.pairs = vec![Default::default(); num_files];
+
+        for file in 0..num_files {
+            let first = read_u8(&data, pos)?;
+            let second = if pp { read_u8(&data, pos + 1)? } else { 0xff };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            pos += 1 + pp as usize;
+            for k in 0..self.piece_count {
+                let pieces = read_u8(&data, pos)?;
+                for side in 0..sides {
+                    self.pairs[file][side].pieces[k] = if side == 1 {
+                        pieces >> 4
+                    } else {
+                        pieces & 0xf
+                    };
+                }
+                pos += 1;
+            }
+            for side in 0..sides {
+                self.check_pieces(&self.pairs[file][side])?;
+            }
+            for (side, order) in order.into_iter().enumerate().take(sides) {
+                self.set_groups(file, side, order);
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..num_files {
+            for side in 0..sides {
+                pos = Self::set_sizes(&mut self.pairs[file][side], &data, pos)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            self.map = pos;
+            for file in 0..num_files {
+                let d = &mut self.pairs[file][0];
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = ((pos - self.map) / 2 + 1) as u16;
+                        pos += 2 * read_u16_le(&data, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - self.map + 1) as u16;
+                        pos += read_u8(&data, pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        // the sections below are only read when probing, so their sizes are checked here
+        let section = |pos: usize, size: u64| -> Result<usize, &'static str> {
+            let end = usize::try_from(size)
+                .ok()
+                .and_then(|size| pos.checked_add(size))
+                .ok_or(CORRUPTED)?;
+            if end > data.len() {
+                return Err(TRUNCATED);
+            }
+            Ok(end)
+        };
+        for file in 0..num_files {
+            for side in 0..sides {
+                let d = &mut self.pairs[file][side];
+                d.sparse_index = pos;
+                pos = section(pos, d.sparse_index_size.saturating_mul(6))?;
+            }
+        }
+        for file in 0..num_files {
+            for side in 0..sides {
+                let d = &mut self.pairs[file][side];
+                d.block_length = pos;
+                pos = section(pos, d.block_length_size.saturating_mul(2))?;
+            }
+        }
+        for file in 0..num_files {
+            for side in 0..sides {
+                let d = &mut self.pairs[file][side];
+                pos = (pos + 0x3f) & !0x3f;
+                d.data = pos;
+                pos = section(pos, d.num_blocks.saturating_mul(d.sizeof_block))?;
+            }
+        }
+        self.data = data;
+        Ok(())
+    }
+
+    /// the pieces a table lists must be the ones in its name, starting with the pawns of
+    /// the leading colour and then those of the other colour. the indexing relies on it.
+    fn check_pieces(&self, d: &PairsData) -> Result<(), &'static str> {
+        let codes = |signature: &str, color: u8| -> Vec<u8> {
+            signature
+                .chars()
+                .map(|c| {
+                    let (_, piece) = PIECE_CHARS.iter().find(|(x, _)| *x == c).expect("valid name");
+                    piece_code(*piece) | color
+                })
+                .collect()
+        };
+        let mut expected = codes(&self.white_signature, 0);
+        expected.extend(codes(&self.black_signature, 8));
+        let mut pieces = d.pieces[..self.piece_count].to_vec();
+        expected.sort();
+        pieces.sort();
+        if pieces != expected {
+            return Err(CORRUPTED);
+        }
+        if self.has_pawns {
+            let pawn = piece_code(Piece::Pawn);
+            let [lead, other] = self.pawn_count.map(usize::from);
+            let lead_pawn = d.pieces[0];
+            if lead_pawn & 7 != pawn
+                || d.pieces[..lead].iter().any(|&code| code != lead_pawn)
+                || d.pieces[lead..lead + other].iter().any(|&code| code != lead_pawn ^ 8)
+            {
+                return Err(CORRUPTED);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_groups(&mut self, file: usize, side: usize, order: [u8; 2]) {
+        let c = consts();
+        let has_pawns = self.has_pawns;
+        let has_unique_pieces = self.has_unique_pieces;
+        let piece_count = self.piece_count;
+        let pp = has_pawns && self.pawn_count[1] > 0;
+        let d = &mut self.pairs[file][side];
+
+        let mut n = 0;
+        let mut first_len: i32 = if has_pawns {
+            0
+        } else if has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[n] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let mut next = if pp { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0u8;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                d.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    c.lead_pawns_size[d.group_len[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                d.group_idx[1] = idx;
+                idx *= c.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= c.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    fn set_sizes(d: &mut PairsData, data: &[u8], mut pos: usize) -> Result<usize, &'static str> {
+        d.flags = read_u8(data, pos)?;
+        pos += 1;
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            d.num_blocks = 0;
+            d.span = 0;
+            d.sparse_index_size = 0;
+            d.block_length_size = 0;
+            // the single value is stored in place of the minimum symbol length
+            d.min_sym_len = read_u8(data, pos)?;
+            return Ok(pos + 1);
+        }
+
+        let num_groups = d.group_len.iter().position(|&x| x == 0).unwrap();
+        let tb_size = d.group_idx[num_groups];
+
+        let [block_bits, span_bits, padding] = bytes(data, pos)?;
+        // real tables use small blocks, larger shifts would overflow
+        if block_bits >= 32 || span_bits >= 32 {
+            return Err(CORRUPTED);
+        }
+        d.sizeof_block = 1 << block_bits;
+        d.span = 1 << span_bits;
+        d.sparse_index_size = tb_size.div_ceil(d.span);
+        d.num_blocks = read_u32_le(data, pos + 3)? as u64;
+        d.block_length_size = d.num_blocks + padding as u64;
+        d.max_sym_len = read_u8(data, pos + 7)?;
+        d.min_sym_len = read_u8(data, pos + 8)?;
+        pos += 9;
+        d.lowest_sym = pos;
+
+        // symbols are 1 to 64 bits long
+        if d.min_sym_len == 0 || d.max_sym_len > 64 || d.min_sym_len > d.max_sym_len {
+            return Err(CORRUPTED);
+        }
+        let base64_size = (d.max_sym_len - d.min_sym_len + 1) as usize;
+        d.base64 = vec![0; base64_size];
+        for i in (0..base64_size - 1).rev() {
+            let lowest_sym = read_u16_le(data, d.lowest_sym + 2 * i)? as u64;
+            let next_lowest_sym = read_u16_le(data, d.lowest_sym + 2 * (i + 1))? as u64;
+            d.base64[i] = d.base64[i + 1]
+                .wrapping_add(lowest_sym)
+                .wrapping_sub(next_lowest_sym)
+                / 2;
+        }
+        for i in 0..base64_size {
+            d.base64[i] <<= 64 - i - d.min_sym_len as usize;
+        }
+        pos += base64_size * 2;
+
+        let symlen_size = read_u16_le(data, pos)? as usize;
+        pos += 2;
+        d.btree = pos;
+        let end = pos + symlen_size * 3 + (symlen_size & 1);
+        if end > data.len() {
+            return Err(TRUNCATED);
+        }
+        d.symlen = vec![0; symlen_size];
+        let mut visited = vec![false; symlen_size];
+        for sym in 0..symlen_size {
+            if !visited[sym] {
+                d.symlen[sym] = Self::set_symlen(d, data, sym, &mut visited)?;
+            }
+        }
+        Ok(end)
+    }
+
+    // each btree node is 3 bytes: 12 bits left symbol, 12 bits right symbol
+    fn btree_left(d: &PairsData, data: &[u8], sym: usize) -> usize {
+        let node = d.btree + 3 * sym;
+        (((data[node + 1] & 0xf) as usize) << 8) | data[node] as usize
+    }
+
+    fn btree_right(d: &PairsData, data: &[u8], sym: usize) -> usize {
+        let node = d.btree + 3 * sym;
+        ((data[node + 2] as usize) << 4) | (data[node + 1] >> 4) as usize
+    }
+
+    /// the btree has been checked to lie inside the file. its nodes must point inside it too.
+    fn set_symlen(
+        d: &mut PairsData,
+        data: &[u8],
+        sym: usize,
+        visited: &mut [bool],
+    ) -> Result<u8, &'static str> {
+        visited[sym] = true;
+        let right = Self::btree_right(d, data, sym);
+        if right == 0xfff {
+            return Ok(0);
+        }
+        let left = Self::btree_left(d, data, sym);
+        if left >= visited.len() || right >= visited.len() {
+            return Err(CORRUPTED);
+        }
+        if !visited[left] {
+            d.symlen[left] = Self::set_symlen(d, data, left, visited)?;
+        }
+        if !visited[right] {
+            d.symlen[right] = Self::set_symlen(d, data, right, visited)?;
+        }
+        d.symlen[left]
+            .checked_add(d.symlen[right])
+            .and_then(|len| len.checked_add(1))
+            .ok_or(CORRUPTED)
+    }
+
+    fn decompress_pairs(&self, d: &PairsData, idx: u64) -> Result<i32, &'static str> {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return Ok(d.min_sym_len as i32);
+        }
+        let data = &self.data;
+
+        let k = (idx / d.span) as usize;
+        let mut block = read_u32_le(data, d.sparse_index + 6 * k)? as usize;
+        let mut offset = read_u16_le(data, d.sparse_index + 6 * k + 4)? as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+        let block_length = |block: usize| -> Result<i64, &'static str> {
+            if block as u64 >= d.block_length_size {
+                return Err(CORRUPTED);
+            }
+            Ok(read_u16_le(data, d.block_length + 2 * block)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1).ok_or(CORRUPTED)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block * d.sizeof_block as usize;
+        let mut buf64 = ((read_u32_be(data, ptr) as u64) << 32) | read_u32_be(data, ptr + 4) as u64;
+        ptr += 8;
+        let mut buf64_size: usize = 64;
+        let min_sym_len = d.min_sym_len as usize;
+
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf64 < *d.base64.get(len).ok_or(CORRUPTED)? {
+                len += 1;
+            }
+            sym = ((buf64 - d.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            sym += read_u16_le(data, d.lowest_sym + 2 * len)? as usize;
+            let symlen = *d.symlen.get(sym).ok_or(CORRUPTED)? as i64;
+
+            if offset < symlen + 1 {
+                break;
+            }
+            offset -= symlen + 1;
+            len += min_sym_len;
+            buf64 = buf64.checked_shl(len as u32).unwrap_or(0);
+            buf64_size = buf64_size.checked_sub(len).ok_or(CORRUPTED)?;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= (read_u32_be(data, ptr) as u64) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        while d.symlen[sym] != 0 {
+            let left = Self::btree_left(d, data, sym);
+            if offset < d.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= d.symlen[left] as i64 + 1;
+                sym = Self::btree_right(d, data, sym);
+            }
+        }
+        Ok(Self::btree_left(d, data, sym) as i32)
+    }
+
+    fn map_score(&self, file: usize, value: i32, wdl: Wdl) -> Result<i32, &'static str> {
+        Ok(match self.kind {
+            TableKind::Wdl => value - 2,
+            TableKind::Dtz => {
+                const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+                let d = self.get(0, file);
+                let mut value = value;
+                if d.flags & FLAG_MAPPED != 0 {
+                    let idx = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]] as usize;
+                    value = if d.flags & FLAG_WIDE != 0 {
+                        read_u16_le(&self.data, self.map + 2 * (idx + value as usize))? as i32
+                    } else {
+                        read_u8(&self.data, self.map + idx + value as usize)? as i32
+                    };
+                }
+                // dtz is stored in moves or plies depending on the flags
+                if (wdl == Wdl::Win && d.flags & FLAG_WIN_PLIES == 0)
+                    || (wdl == Wdl::Loss && d.flags & FLAG_LOSS_PLIES == 0)
+                    || wdl == Wdl::CursedWin
+                    || wdl == Wdl::BlessedLoss
+                {
+                    value *= 2;
+                }
+                value + 1
+            }
+        })
+    }
+
+    /// looks up a position. returns None if a DTZ table stores the other side to move.
+    fn probe(
+        &self,
+        pieces: &[(u8, u8)],
+        white_to_move: bool,
+        wdl: Wdl,
+    ) -> Result<Option<i32>, &'static str> {
+        let c = consts();
+        let white_signature = signature(pieces, 0);
+
+        let symmetric_black_to_move = self.symmetric && !white_to_move;
+        let black_stronger = white_signature != self.white_signature;
+        let flip = symmetric_black_to_move || black_stronger;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip ^ !white_to_move) as usize;
+
+        let mut squares = [0u8; TB_PIECES];
+        let mut piece_codes = [0u8; TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns_cnt = 0;
+        let mut tb_file = 0;
+
+        let pawns_comp = |a: &u8, b: &u8| c.map_pawns[*a as usize].cmp(&c.map_pawns[*b as usize]);
+
+        // pawns of the leading colour, as it appears in the position
+        let lead_pawn = self
+            .has_pawns
+            .then(|| self.get(0, 0).pieces[0] ^ flip_color);
+        if let Some(lead_pawn) = lead_pawn {
+            for &(sq, code) in pieces.iter().filter(|(_, code)| *code == lead_pawn) {
+                squares[size] = sq ^ flip_squares;
+                piece_codes[size] = code ^ flip_color;
+                size += 1;
+            }
+            lead_pawns_cnt = size;
+
+            // the leading pawn is the first one with the highest MapPawns value
+            let mut best = 0;
+            for i in 1..lead_pawns_cnt {
+                if pawns_comp(&squares[i], &squares[best]).is_gt() {
+                    best = i;
+                }
+            }
+            squares.swap(0, best);
+            let file = file_of(squares[0]);
+            tb_file = file.min(7 - file) as usize;
+        }
+
+        // DTZ tables only store one side to move
+        if self.kind == TableKind::Dtz {
+            let flags = self.get(stm, tb_file).flags;
+            if (flags & FLAG_STM) as usize != stm && (!self.symmetric || self.has_pawns) {
+                return Ok(None);
+            }
+        }
+
+        for &(sq, code) in pieces.iter().filter(|(_, code)| Some(*code) != lead_pawn) {
+            squares[size] = sq ^ flip_squares;
+            piece_codes[size] = code ^ flip_color;
+            size += 1;
+        }
+
+        let d = self.get(stm, tb_file);
+
+        // reorder the pieces to the sequence stored in the table
+        for i in lead_pawns_cnt..size.saturating_sub(1) {
+            for j in (i + 1)..size {
+                if d.pieces[i] == piece_codes[j] {
+                    piece_codes.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // the leading piece goes in the a1-d1-d4 triangle
+        if file_of(squares[0]) > 3 {
+            for sq in squares[..size].iter_mut() {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx: u64;
+        if self.has_pawns {
+            idx = c.lead_pawn_idx[lead_pawns_cnt][squares[0] as usize];
+            squares[1..lead_pawns_cnt].sort_by(pawns_comp);
+            for (i, sq) in squares.iter().enumerate().take(lead_pawns_cnt).skip(1) {
+                idx += c.binomial[i][c.map_pawns[*sq as usize] as usize];
+            }
+        } else {
+            if rank_of(squares[0]) > 3 {
+                for sq in squares[..size].iter_mut() {
+                    *sq ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                if off_a1h8(squares[i]) == 0 {
+                    continue;
+                }
+                if off_a1h8(squares[i]) > 0 {
+                    for sq in squares[i..size].iter_mut() {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if self.has_unique_pieces {
+                let s = |i: usize| squares[i] as u64;
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                let rank = |i: usize| rank_of(squares[i]) as u64;
+
+                idx = if off_a1h8(squares[0]) != 0 {
+                    (c.map_a1d1d4[squares[0] as usize] * 63 + (s(1) - adjust1)) * 62 + s(2)
+                        - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + rank(0) * 28 + c.map_b1h1h7[squares[1] as usize]) * 62 + s(2)
+                        - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(0) * 7 * 28
+                        + (rank(1) - adjust1) * 28
+                        + c.map_b1h1h7[squares[2] as usize]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(0) * 7 * 6
+                        + (rank(1) - adjust1) * 6
+                        + (rank(2) - adjust2)
+                };
+            } else {
+                idx = c.map_kk[c.map_a1d1d4[squares[0] as usize] as usize][squares[1] as usize];
+            }
+        }
+
+        // encode the remaining groups
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_len = d.group_len[next];
+            squares[group_start..group_start + group_len].sort();
+            let mut n = 0;
+            for i in 0..group_len {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|&&s| sq > s).count();
+                let offset = sq as usize - adjust - if remaining_pawns { 8 } else { 0 };
+                n += c.binomial[i + 1][offset];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += group_len;
+            next += 1;
+        }
+
+        let value = self.decompress_pairs(d, idx)?;
+        self.map_score(tb_file, value, wdl).map(Some)
+    }
+}
+
+/// table name part for one side, e.g. "KRP"
+fn signature(pieces: &[(u8, u8)], color: u8) -> String {
+    let mut res = String::new();
+    for (char, piece) in PIECE_CHARS {
+        let code = piece_code(piece) | color;
+        for _ in pieces.iter().filter(|(_, c)| *c == code) {
+            res.push(char);
+        }
+    }
+    res
+}
+
+/// tablebase piece codes: 1-6 for white pawn to king, with bit 3 set for black
+fn piece_code(piece: Piece) -> u8 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight => 2,
+        Piece::Bishop => 3,
+        Piece::Rook => 4,
+        Piece::Queen => 5,
+        Piece::King => 6,
+    }
+}
+
+/// (square, piece code) of every piece, in ascending tablebase square order
+fn tb_pieces(state: &GameState) -> Vec<(u8, u8)> {
+    let mut pieces: Vec<(u8, u8)> = state
+        .piece_grid
+        .get_all_pieces()
+        .into_iter()
+        .map(|pos| {
+            let square_type = state.piece_grid.get_square_type(pos);
+            let piece = square_type.to_piece_for_io().expect("square is occupied");
+            (pos ^ 7, piece_code(piece) | (square_type.get_d() << 3))
+        })
+        .collect();
+    pieces.sort();
+    pieces
+}
+
+enum TableSlot {
+    Unloaded(PathBuf),
+    Loaded(Box<Table>),
+    Failed,
+}
+
+/// the set of tablebase files found in the configured directories
+#[derive(Default)]
+pub struct Tablebases {
+    wdl: HashMap<String, TableSlot>,
+    dtz: HashMap<String, TableSlot>,
+    max_pieces: usize,
+    /// fixed-size buffer reused for move generation while probing
+    move_bufs: Vec<MoveBuffer>,
+}
+
+impl Tablebases {
+    /// scans the given directories, separated by ':' (or ';' on windows), for table files.
+    /// an empty string or "<empty>" disables probing.
+    pub fn new(paths: &str) -> Self {
+        let mut res = Self::default();
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        for dir in paths
+            .split(separator)
+            .filter(|x| !x.is_empty() && *x != "<empty>")
+        {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for path in entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+            {
+                res.add_file(&path);
+            }
+        }
+        res
+    }
+
+    fn add_file(&mut self, path: &Path) {
+        let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+            return;
+        };
+        let stem = stem.to_string_lossy().to_string();
+        let Some((white, black)) = stem.split_once('v') else {
+            return;
+        };
+        let is_valid_side = |side: &str| {
+            side.starts_with('K')
+                && side
+                    .chars()
+                    .all(|c| PIECE_CHARS.iter().any(|(x, _)| *x == c))
+                && side.chars().filter(|c| *c == 'K').count() == 1
+        };
+        if !is_valid_side(white) || !is_valid_side(black) {
+            return;
+        }
+        let piece_count = white.len() + black.len();
+        if piece_count > TB_PIECES {
+            return;
+        }
+        match extension.to_string_lossy().as_ref() {
+            "rtbw" => {
+                self.max_pieces = self.max_pieces.max(piece_count);
+                self.wdl.insert(stem, TableSlot::Unloaded(path.to_owned()));
+            }
+            "rtbz" => {
+                self.dtz.insert(stem, TableSlot::Unloaded(path.to_owned()));
+            }
+            _ => {}
+        }
+    }
+
+    /// the largest number of pieces, kings included, that can be probed
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    pub fn num_tables(&self) -> usize {
+        self.wdl.len() + self.dtz.len()
+    }
+
+    /// whether the position is small enough to probe. positions with castling rights are
+    /// not in the tables.
+    pub fn can_probe(&self, state: &GameState) -> bool {
+        // tables only hold standard chess results
+        self.max_pieces > 0
+            && state.variant == Variant::Standard
+            && state.piece_grid.get_all_pieces().num_pieces() as usize <= self.max_pieces
+            && !state.metadata.get_can_castle_dynamic(Player::White, true)
+            && !state.metadata.get_can_castle_dynamic(Player::White, false)
+            && !state.metadata.get_can_castle_dynamic(Player::Black, true)
+            && !state.metadata.get_can_castle_dynamic(Player::Black, false)
+    }
+
+    fn probe_table(
+        &mut self,
+        kind: TableKind,
+        state: &GameState,
+        wdl: Wdl,
+    ) -> Result<Option<i32>, &'static str> {
+        let pieces = tb_pieces(state);
+        if pieces.len() == 2 {
+            return Ok(Some(0));
+        }
+        let white = signature(&pieces, 0);
+        let black = signature(&pieces, 8);
+        let tables = match kind {
+            TableKind::Wdl => &mut self.wdl,
+            TableKind::Dtz => &mut self.dtz,
+        };
+        let name = [
+            format!("{}v{}", white, black),
+            format!("{}v{}", black, white),
+        ]
+        .into_iter()
+        .find(|name| tables.contains_key(name))
+        .ok_or("missing table")?;
+
+        let slot = tables.get_mut(&name).unwrap();
+        if let TableSlot::Unloaded(path) = slot {
+            *slot = match fs::read(path)
+                .map_err(|_| "cannot read table")
+                .and_then(|data| Table::new(kind, &name, data))
+            {
+                Ok(table) => TableSlot::Loaded(Box::new(table)),
+                Err(_) => TableSlot::Failed,
+            };
+        }
+        match slot {
+            TableSlot::Loaded(table) => table.probe(&pieces, state.player == Player::White, wdl),
+            _ => Err("failed to load table"),
+        }
+    }
+
+    /// WDL value of the position for the side to move, or None if it can't be probed
+    pub fn probe_wdl(
+        &mut self,
+        state: &mut GameState,
+        zoborist_state: &ZoboristState,
+    ) -> Option<Wdl> {
+        // positions where the king can be captured are not legal
+        if !self.can_probe(state) || state.attacks_opponent_king() {
+            return None;
+        }
+        self.search(state, zoborist_state, false, 0)
+            .ok()
+            .map(|(wdl, _)| wdl)
+    }
+
+    /// the stored value may be a "don't care" if a capture wins, so captures are always
+    /// searched as well. with `check_zeroing_moves`, pawn moves are searched too (for DTZ).
+    fn search(
+        &mut self,
+        state: &mut GameState,
+        zoborist_state: &ZoboristState,
+        check_zeroing_moves: bool,
+        ply: usize,
+    ) -> Result<(Wdl, ProbeState), &'static str> {
+        let moves = self.legal_moves(state, zoborist_state, ply);
+        let mut best_value = Wdl::Loss;
+        let mut move_count = 0;
+
+        for &mov in moves.iter() {
+            if !is_capture(mov) && (!check_zeroing_moves || !is_pawn_move(mov)) {
+                continue;
+            }
+            move_count += 1;
+            let metadata = state.make_move(mov, zoborist_state);
+            let res = self.search(state, zoborist_state, false, ply + 1);
+            state.unmake_move(mov, metadata, zoborist_state);
+            let value = res?.0.neg();
+
+            if value as i32 > best_value as i32 {
+                best_value = value;
+                if value as i32 >= Wdl::Win as i32 {
+                    return Ok((value, ProbeState::ZeroingBestMove));
+                }
+            }
+        }
+
+        // with every move searched, the stored value could be wrong (e.g. en passant)
+        let no_more_moves = move_count > 0 && move_count == moves.len();
+        let value = if no_more_moves {
+            best_value
+        } else {
+            let value = self
+                .probe_table(TableKind::Wdl, state, Wdl::Draw)?
+                .ok_or("unexpected side to move in wdl table")?;
+            Wdl::from_i32(value)
+        };
+
+        if best_value as i32 >= value as i32 {
+            let probe_state = if best_value as i32 > Wdl::Draw as i32 || no_more_moves {
+                ProbeState::ZeroingBestMove
+            } else {
+                ProbeState::Ok
+            };
+            return Ok((best_value, probe_state));
+        }
+        Ok((value, ProbeState::Ok))
+    }
+
+    fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+        match wdl {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1,
+            Wdl::Draw => 0,
+        }
+    }
+
+    /// distance to zeroing the 50 move counter, in plies, signed by the WDL value.
+    /// 0 means a draw.
+    fn probe_dtz_inner(
+        &mut self,
+        state: &mut GameState,
+        zoborist_state: &ZoboristState,
+        ply: usize,
+    ) -> Result<i32, &'static str> {
+        let (wdl, probe_state) = self.search(state, zoborist_state, true, ply)?;
+        if wdl == Wdl::Draw {
+            return Ok(0);
+        }
+        if probe_state == ProbeState::ZeroingBestMove {
+            return Ok(Self::dtz_before_zeroing(wdl));
+        }
+
+        let sign = (wdl as i32).signum();
+        if let Some(dtz) = self.probe_table(TableKind::Dtz, state, wdl)? {
+            let cursed = wdl == Wdl::BlessedLoss || wdl == Wdl::CursedWin;
+            return Ok((dtz + if cursed { 100 } else { 0 }) * sign);
+        }
+
+        // the table stores the other side to move: do a 1 ply search instead
+        let mut min_dtz = 0xffff;
+        for mov in self.legal_moves(state, zoborist_state, ply) {
+            let zeroing = is_capture(mov) || is_pawn_move(mov);
+            let metadata = state.make_move(mov, zoborist_state);
+            let res = if zeroing {
+                self.search(state, zoborist_state, false, ply + 1)
+                    .map(|(wdl, _)| -Self::dtz_before_zeroing(wdl))
+            } else {
+                self.probe_dtz_inner(state, zoborist_state, ply + 1)
+                    .map(|dtz| -dtz)
+            };
+            let is_mate = res == Ok(1)
+                && state.is_in_check()
+                && self.legal_moves(state, zoborist_state, ply + 1).is_empty();
+            state.unmake_move(mov, metadata, zoborist_state);
+
+            let mut dtz = res?;
+            if is_mate {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == sign {
+                min_dtz = dtz;
+            }
+        }
+        Ok(if min_dtz == 0xffff { -1 } else { min_dtz })
+    }
+
+    pub fn probe_dtz(
+        &mut self,
+        state: &mut GameState,
+        zoborist_state: &ZoboristState,
+    ) -> Option<i32> {
+        // positions where the king can be captured are not legal
+        if !self.can_probe(state) || state.attacks_opponent_king() {
+            return None;
+        }
+        self.probe_dtz_inner(state, zoborist_state, 0).ok()
+    }
+
+    /// picks the tablebase-optimal move at the root: the fastest win, else a draw, else the
+    /// slowest loss. returns the move with its WDL value for the side to move. a win or loss
+    /// the fifty-move rule catches first, counting the plies already played, is cursed or
+    /// blessed.
+    pub fn probe_root(
+        &mut self,
+        state: &mut GameState,
+        zoborist_state: &ZoboristState,
+    ) -> Option<(Move, Wdl)> {
+        // positions where the king can be captured are not legal
+        if !self.can_probe(state) || state.attacks_opponent_king() {
+            return None;
+        }
+        const MAX_DTZ: i32 = 1 << 18;
+        let mut best: Option<(i32, Move, i32)> = None;
+        for mov in self.legal_moves(state, zoborist_state, 0) {
+            let zeroing = is_capture(mov) || is_pawn_move(mov);
+            let metadata = state.make_move(mov, zoborist_state);
+            let res = if zeroing {
+                self.search(state, zoborist_state, false, 1)
+                    .map(|(wdl, _)| Self::dtz_before_zeroing(wdl.neg()))
+            } else {
+                self.probe_dtz_inner(state, zoborist_state, 1).map(|dtz| {
+                    let dtz = -dtz;
+                    dtz + dtz.signum()
+                })
+            };
+            let is_mate =
+                state.is_in_check() && self.legal_moves(state, zoborist_state, 1).is_empty();
+            state.unmake_move(mov, metadata, zoborist_state);
+
+            let mut dtz = res.ok()?;
+            if is_mate {
+                dtz = 1;
+            }
+            let rank = match dtz {
+                x if x > 0 => MAX_DTZ - x,
+                x if x < 0 => -MAX_DTZ - x,
+                _ => 0,
+            };
+            if best.is_none_or(|(best_rank, _, _)| rank > best_rank) {
+                best = Some((rank, mov, dtz));
+            }
+        }
+
+        let halfmove_clock = state.metadata.get_halfmove_clock() as i32;
+        best.map(|(_, mov, dtz)| {
+            let wdl = match dtz {
+                x if x > 0 && x + halfmove_clock > 100 => Wdl::CursedWin,
+                x if x > 0 => Wdl::Win,
+                x if x < 0 && x - halfmove_clock < -100 => Wdl::BlessedLoss,
+                x if x < 0 => Wdl::Loss,
+                _ => Wdl::Draw,
+            };
+            (mov, wdl)
+        })
+    }
+
+    fn legal_moves(
+        &mut self,
+        state: &mut GameState,
+        zoborist_state: &ZoboristState,
+        ply: usize,
+    ) -> Vec<Move> {
+        while self.move_bufs.len() <= ply {
+            self.move_bufs.push(MoveBuffer::default());
+        }
+        let move_buf = &mut self.move_bufs[ply];
+        move_buf.clear();
+        move_buf.get_all_moves(state);
+        let mut moves = vec![];
+        while let Some(mov) = move_buf.pop() {
+            moves.push(mov);
+        }
+        moves.retain(|&mov| {
+            let metadata = state.make_move(mov, zoborist_state);
+            let legal = !state.attacks_opponent_king();
+            state.unmake_move(mov, metadata, zoborist_state);
+            legal
+        });
+        moves
+    }
+}
+
+fn is_capture(mov: Move) -> bool {
+    match mov {
+        Move::Move { pieces, .. } | Move::PawnPromote { pieces, .. } => {
+            !pieces.to_square_types().1.is_empty()
+        }
+        Move::EnPassant { .. } => true,
+        Move::Castle { .. } => false,
+    }
+}
+
+fn is_pawn_move(mov: Move) -> bool {
+    match mov {
+        Move::Move { pieces, .. } => pieces.to_square_types().0.is_pawn(),
+        Move::PawnPromote { .. } | Move::EnPassant { .. } => true,
+        Move::Castle { .. } => false,
+    }
+}
+
+
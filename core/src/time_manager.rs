@@ -0,0 +1,100 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// how much time a search gets, as given by the gui
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeControl {
+    /// exactly this long for the move
+    MoveTime(Duration),
+    /// the clock of the side to move, spread over the moves up to the next time control
+    Clock {
+        time: Duration,
+        increment: Duration,
+        moves_to_go: Option<u32>,
+    },
+    /// until stopped, e.g. for analysis
+    Infinite,
+}
+
+/// decides how long a `go` may search. time spent pondering counts as time spent on the
+/// move, so after a `ponderhit` the search only gets what is left of its budget.
+pub struct TimeManager {
+    start: Instant,
+    // no further iteration is started after half of this
+    soft_limit: Option<Duration>,
+    // the search is stopped here even in the middle of an iteration
+    hard_limit: Option<Duration>,
+    pondering: AtomicBool,
+    // `go infinite` searches until stop
+    infinite: bool,
+}
+
+impl TimeManager {
+    /// the clock left is spread over 30 moves, and `overhead` is kept back for
+    /// communication delays. without a time control, or an infinite one, there is no limit.
+    pub fn new(time_control: Option<TimeControl>, overhead: Duration, pondering: bool) -> Self {
+        let limits = match time_control {
+            Some(TimeControl::MoveTime(time)) => {
+                let time = time.saturating_sub(overhead);
+                Some((time, time))
+            }
+            Some(TimeControl::Clock {
+                time,
+                increment,
+                moves_to_go,
+            }) => {
+                let time = time.saturating_sub(overhead);
+                let moves_left = moves_to_go.unwrap_or(30).clamp(1, 30);
+                let hard_limit = time * 3 / 4;
+                Some(((time / moves_left + increment / 2).min(hard_limit), hard_limit))
+            }
+            Some(TimeControl::Infinite) | None => None,
+        };
+        let at_least = |limit: Duration| limit.max(Duration::from_millis(1));
+        Self {
+            start: Instant::now(),
+            soft_limit: limits.map(|(soft, _)| at_least(soft)),
+            hard_limit: limits.map(|(_, hard)| at_least(hard)),
+            pondering: AtomicBool::new(pondering),
+            infinite: time_control == Some(TimeControl::Infinite),
+        }
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    /// bestmove has to wait for stop while pondering and in infinite mode, even if the
+    /// search is done
+    pub fn waits_for_stop(&self) -> bool {
+        self.infinite || self.is_pondering()
+    }
+
+    /// the opponent played the expected move, so the clock is running now
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    /// when the search has to be stopped. none while pondering.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.is_pondering() {
+            return None;
+        }
+        Some(self.start + self.hard_limit?)
+    }
+
+    /// whether there is time for another iteration, which takes several times as long
+    /// as all the ones before it
+    pub fn can_start_iteration(&self) -> bool {
+        match self.soft_limit {
+            Some(soft_limit) if !self.is_pondering() => self.start.elapsed() * 2 < soft_limit,
+            _ => true,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
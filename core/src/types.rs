@@ -1,9 +1,11 @@
 use std::fmt::Display;
 
 use crate::{
+    game,
+    square::Square,
     square_type::{CompressedSquareType, SquareType},
     types_for_io::Piece,
-    util,
+    util, GameState,
 };
 
 // #[derive(Clone, Copy, PartialEq, Debug, Eq)]
@@ -114,6 +116,19 @@ pub enum Move {
 }
 
 impl Move {
+    /// captures and pawn moves start the fifty-move count again
+    #[inline(always)]
+    pub fn resets_halfmove_clock(self) -> bool {
+        match self {
+            Move::Move { pieces, .. } => {
+                let (piece, captured_piece) = pieces.to_square_types();
+                piece.is_pawn() || !captured_piece.is_empty()
+            }
+            Move::Castle { .. } => false,
+            Move::PawnPromote { .. } | Move::EnPassant { .. } => true,
+        }
+    }
+
     // pub fn get_cmp_key(self, last_move_pos: u8, killer_entry: KillerEntry) -> (u8, i32) {
     //     match self {
     //         Move::Move {
@@ -239,6 +254,98 @@ impl Display for Move {
     }
 }
 
+/// a move in 16 bits: bits 0-5 the starting square, 6-11 the destination (both as `pos`),
+/// 12-15 the kind of move. castling is stored as the king's move. unlike `Move` it does not
+/// record which pieces are involved, so converting back needs the position it was made in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    pub const NONE: Self = Self(0);
+
+    const NORMAL: u16 = 0;
+    const EN_PASSANT: u16 = 1;
+    const CASTLE_SHORT: u16 = 2;
+    const CASTLE_LONG: u16 = 3;
+    // the promotion piece is `flags - PROMOTION_BASE`, from knight to queen
+    const PROMOTION_BASE: u16 = 3;
+
+    /// `state` is the position before `mov` is made
+    pub fn from_move(mov: Move, state: &GameState) -> Self {
+        let (from, to) = game::move_squares(state, mov);
+        let flags = match mov {
+            Move::Move { .. } => Self::NORMAL,
+            Move::EnPassant { .. } => Self::EN_PASSANT,
+            Move::Castle { is_short: true } => Self::CASTLE_SHORT,
+            Move::Castle { is_short: false } => Self::CASTLE_LONG,
+            Move::PawnPromote { pieces, .. } => {
+                let promoted_to_piece = pieces.to_square_types().0.to_piece_for_io().expect("cannot be empty");
+                Self::PROMOTION_BASE + promoted_to_piece as u16
+            }
+        };
+        Self(from.pos() as u16 | (to.pos() as u16) << 6 | flags << 12)
+    }
+
+    /// the move in `state`, if the starting square holds a piece of the side to move. the
+    /// move is not checked any further.
+    pub fn to_move(self, state: &GameState) -> Option<Move> {
+        if self == Self::NONE {
+            return None;
+        }
+        let (from, to) = (self.from().pos(), self.to().pos());
+        let piece = state.piece_grid.get_square_type(from);
+        if piece.is_empty() || piece.get_d() != state.player as u8 {
+            return None;
+        }
+        let captured_piece = state.piece_grid.get_square_type(to);
+        Some(match self.flags() {
+            Self::NORMAL => Move::Move {
+                prev_pos: from,
+                new_pos: to,
+                pieces: CompressedSquareType::from_square_types(piece, captured_piece),
+            },
+            Self::EN_PASSANT => Move::EnPassant {
+                prev_column: from & 0b111,
+                new_column: to & 0b111,
+            },
+            Self::CASTLE_SHORT => Move::Castle { is_short: true },
+            Self::CASTLE_LONG => Move::Castle { is_short: false },
+            flags @ 4..=7 => {
+                let promoted_to_piece = Piece::from((flags - Self::PROMOTION_BASE) as usize);
+                Move::PawnPromote {
+                    prev_pos: from,
+                    new_pos: to,
+                    pieces: CompressedSquareType::from_square_types(
+                        SquareType::create_for_parsing(promoted_to_piece, state.player),
+                        captured_piece,
+                    ),
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    pub const fn from_raw(raw: u16) -> Self {
+        Self(raw)
+    }
+
+    pub const fn to_raw(self) -> u16 {
+        self.0
+    }
+
+    pub fn from(self) -> Square {
+        Square::from_pos_unchecked((self.0 & 0b111111) as u8)
+    }
+
+    pub fn to(self) -> Square {
+        Square::from_pos_unchecked((self.0 >> 6 & 0b111111) as u8)
+    }
+
+    fn flags(self) -> u16 {
+        self.0 >> 12
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub struct ValueMovePair(pub i32, pub Move);
 
@@ -1,8 +1,10 @@
 use std::fmt::Display;
 
+use crate::player::Player;
+
 /// for ease of parsing only. not used in internal engine
 
-#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+#[derive(Clone, Copy, PartialEq, Debug, Eq, Hash)]
 // empty: !a & !b & !c = 0
 // bishop: !a & b & !c = 2
 // knight: !a & b & c  = 3
@@ -28,6 +30,24 @@ pub enum Piece {
 //     }
 // }
 
+impl Piece {
+    /// the fen letter, upper case for white
+    pub fn to_char(self, player: Player) -> char {
+        let c = ['p', 'n', 'b', 'r', 'q', 'k'][self as usize];
+        match player {
+            Player::White => c.to_ascii_uppercase(),
+            Player::Black => c,
+        }
+    }
+
+    pub fn to_unicode(self, player: Player) -> char {
+        match player {
+            Player::White => ['♙', '♘', '♗', '♖', '♕', '♔'][self as usize],
+            Player::Black => ['♟', '♞', '♝', '♜', '♛', '♚'][self as usize],
+        }
+    }
+}
+
 impl Display for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
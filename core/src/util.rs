@@ -1,6 +1,8 @@
+use crate::square::Square;
+
+// only for squares written in the source, use `Square` parsing for input
 pub fn canonical_to_pos(xy: &str) -> u8 {
-    // println!("item: {}", (xy.as_bytes()[1] << 3) as u8 + xy.as_bytes()[0] as u8 - 'a' as u8);
-    ((xy.as_bytes()[1] - b'1') << 3) + b'h' - xy.as_bytes()[0]
+    xy.parse::<Square>().expect("invalid square").pos()
 }
 
 // row number then col number
@@ -0,0 +1,126 @@
+use crate::{
+    grid::Grid,
+    markers::{BlackMarker, WhiteMarker},
+    player::Player,
+    GameState,
+};
+
+/// rules the game is played under. the variant is fixed for a game and stored on the
+/// `GameState`, so move generation and search can consult it without extra arguments.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// a king reaching d4, e4, d5 or e5 wins
+    KingOfTheHill,
+    /// giving check for the third time wins
+    ThreeCheck,
+    /// white has 36 pawns and no king, and loses once all of them are gone. white pawns on
+    /// the first rank may move two squares.
+    Horde,
+}
+
+/// names used by the `UCI_Variant` option, in the same order as `Variant::ALL`
+pub const VARIANT_NAMES: [&str; 4] = ["chess", "kingofthehill", "3check", "horde"];
+
+impl Variant {
+    pub const ALL: [Variant; 4] = [
+        Variant::Standard,
+        Variant::KingOfTheHill,
+        Variant::ThreeCheck,
+        Variant::Horde,
+    ];
+
+    const CENTRE: Grid = Grid::from_u64(0x0000_0018_1800_0000);
+
+    pub fn name(self) -> &'static str {
+        VARIANT_NAMES[self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chess" | "standard" => Some(Variant::Standard),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "3check" | "threecheck" => Some(Variant::ThreeCheck),
+            "horde" => Some(Variant::Horde),
+            _ => None,
+        }
+    }
+
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Variant::Standard | Variant::KingOfTheHill => {
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+            }
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            Variant::Horde => {
+                "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1"
+            }
+        }
+    }
+
+    /// position used to check move generation. for king of the hill and three-check the
+    /// start position plays like standard chess for the first few plies, so a position where
+    /// the extra win condition is a couple of moves away is used instead.
+    pub fn perft_fen(self) -> &'static str {
+        match self {
+            Variant::Standard | Variant::Horde => self.start_fen(),
+            Variant::KingOfTheHill => "rnbq1bnr/ppppkppp/8/4p3/4P3/8/PPPPKPPP/RNBQ1BNR w - - 2 3",
+            Variant::ThreeCheck => {
+                "rnbqkbnr/ppp2ppp/8/3pp3/4P3/5Q2/PPPP1PPP/RNB1KBNR w KQkq - 1+3 0 3"
+            }
+        }
+    }
+
+    /// perft node counts from `perft_fen`, starting at depth 1
+    pub fn perft_reference(self) -> &'static [i64] {
+        match self {
+            Variant::Standard => &[20, 400, 8902, 197281, 4865609],
+            Variant::KingOfTheHill => &[23, 531, 13337, 332538],
+            Variant::ThreeCheck => &[39, 1391, 52143, 1853724],
+            Variant::Horde => &[8, 128, 1274, 23310, 265223],
+        }
+    }
+
+    /// the number of checks given is part of the position
+    #[inline(always)]
+    pub fn counts_checks(self) -> bool {
+        self == Variant::ThreeCheck
+    }
+
+    /// pawns of `player` on their first rank may move two squares
+    #[inline(always)]
+    pub fn allows_back_rank_double_moves(self, player: Player) -> bool {
+        self == Variant::Horde && player == Player::White
+    }
+
+    /// false if `player` plays without a king
+    #[inline(always)]
+    pub fn has_king(self, player: Player) -> bool {
+        !(self == Variant::Horde && player == Player::White)
+    }
+
+    /// true if the side to move has already lost by a rule of the variant. checkmate and
+    /// stalemate are left to the search.
+    #[inline(always)]
+    pub fn is_loss(self, state: &GameState) -> bool {
+        match self {
+            Variant::Standard => false,
+            Variant::KingOfTheHill => {
+                let opp_king = match state.player {
+                    Player::White => state.piece_grid.get_king_pos::<BlackMarker>(),
+                    Player::Black => state.piece_grid.get_king_pos::<WhiteMarker>(),
+                };
+                opp_king & Self::CENTRE != Grid::EMPTY
+            }
+            Variant::ThreeCheck => state.metadata.get_checks_given(state.player.opp()) >= 3,
+            Variant::Horde => {
+                let pieces = match state.player {
+                    Player::White => state.piece_grid.get_player_pieces::<WhiteMarker>(),
+                    Player::Black => state.piece_grid.get_player_pieces::<BlackMarker>(),
+                };
+                pieces == Grid::EMPTY
+            }
+        }
+    }
+}
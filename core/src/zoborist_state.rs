@@ -8,6 +8,7 @@ pub struct ZoboristState {
     pub player: HashType,
     pub castle: [HashType; 16],               
     pub en_passant: [HashType; 9],            // index: row. 8 is no en passant
+    pub checks: [HashType; 16],               // index: checks given, 2 bits per player
 }
 
 impl ZoboristState {
@@ -16,6 +17,7 @@ impl ZoboristState {
         player: 0,
         castle: [0; 16],
         en_passant: [0; 9],
+        checks: [0; 16],
     };
 
     pub fn new(seed: u64) -> Self {
@@ -42,6 +44,11 @@ impl ZoboristState {
             *num = /*((rng.gen::<u64>() as HashType) << 64) + */rng.gen::<u64>() as HashType;
         }
 
+        // no checks given must have no hash, so standard chess keys are unaffected
+        for num in state.checks.iter_mut().skip(1) {
+            *num = rng.gen::<u64>() as HashType;
+        }
+
         state
     }
 }
\ No newline at end of file
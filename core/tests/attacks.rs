@@ -0,0 +1,18 @@
+use chess_engine_core::{slider_indexing, verify_attack_tables, SliderIndexing};
+
+#[test]
+fn attack_tables_match_the_ray_fills() {
+    // checks the magic indexing, and the PEXT indexing too when the cpu has BMI2
+    verify_attack_tables(200).unwrap();
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn picks_pext_when_the_cpu_has_bmi2() {
+    let expected = if is_x86_feature_detected!("bmi2") {
+        SliderIndexing::Pext
+    } else {
+        SliderIndexing::Magic
+    };
+    assert_eq!(slider_indexing(), expected);
+}
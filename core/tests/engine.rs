@@ -0,0 +1,28 @@
+use chess_engine_core::{ChessEngine, GameState, ZoboristState};
+
+#[test]
+fn keeps_the_hash_table_when_the_new_one_cannot_be_allocated() {
+    let mut engine = ChessEngine::new(4, 40, 13);
+    engine.set_hash_size(2).unwrap();
+    assert_eq!(engine.hash_size(), 2);
+
+    assert!(engine.set_hash_size(usize::MAX).is_err());
+    assert!(engine.set_hash_size(usize::MAX >> 20).is_err());
+    assert_eq!(engine.hash_size(), 2);
+}
+
+#[test]
+fn searches_with_a_table_set_before_the_first_search() {
+    let zoborist_state = ZoboristState::new(13);
+    let state = GameState::new_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", &zoborist_state).unwrap();
+    for megabytes in [None, Some(1)] {
+        let mut engine = ChessEngine::new(4, 40, 13);
+        engine.set_debug_output(false);
+        if let Some(megabytes) = megabytes {
+            engine.set_hash_size(megabytes).unwrap();
+        }
+        assert_eq!(engine.hash_size(), megabytes.unwrap_or(256));
+        engine.solve(&state, 4);
+        assert!(!engine.principal_variation(&state, 4).is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use chess_engine_core::{Game, GameState, Variant, ZoboristState};
+use proptest::prelude::*;
+
+const POSITIONS: [(&str, Variant); 6] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", Variant::Standard),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", Variant::Standard),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", Variant::Standard),
+    ("rbbknnqr/pppppppp/8/8/8/8/PPPPPPPP/RBBKNNQR w HAha - 0 1", Variant::Standard),
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1", Variant::ThreeCheck),
+    (
+        "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+        Variant::Horde,
+    ),
+];
+
+fn zoborist_state() -> ZoboristState {
+    ZoboristState::new(0x5eed)
+}
+
+proptest! {
+    // every legal move, made and taken back, leaves the position exactly as it was
+    #[test]
+    fn make_unmake_round_trips(position in 0..POSITIONS.len(), choices in prop::collection::vec(any::<usize>(), 0..80)) {
+        let zoborist_state = zoborist_state();
+        let (fen, variant) = POSITIONS[position];
+        let mut state = GameState::new_from_fen_with_variant(fen, variant, &zoborist_state).unwrap();
+        prop_assert_eq!(state.check_invariants(&zoborist_state), Ok(()));
+
+        for choice in choices {
+            let moves = state.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            for &mov in &moves {
+                let before = state.clone();
+                let metadata = state.make_move(mov, &zoborist_state);
+                prop_assert_eq!(state.check_invariants(&zoborist_state), Ok(()), "after {}", mov);
+                state.unmake_move(mov, metadata, &zoborist_state);
+                prop_assert!(state == before, "{} did not round-trip", mov);
+            }
+            state.make_move(moves[choice % moves.len()], &zoborist_state);
+        }
+    }
+
+    // taking back a whole game returns to the start position
+    #[test]
+    fn undo_restores_start(position in 0..POSITIONS.len(), choices in prop::collection::vec(any::<usize>(), 0..80)) {
+        let zoborist_state = zoborist_state();
+        let (fen, variant) = POSITIONS[position];
+        let mut game = Game::from_fen_with_variant(fen, variant, &zoborist_state).unwrap();
+        let start = game.state().clone();
+
+        for choice in choices {
+            let moves = game.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            game.play(moves[choice % moves.len()]).unwrap();
+        }
+        while game.undo().is_some() {}
+        prop_assert!(*game.state() == start);
+    }
+}
@@ -0,0 +1,62 @@
+use chess_engine_core::{ChessEngine, GameState, Variant};
+
+// node counts above this are left to `--perft` in release builds
+const MAX_NODES: i64 = 400_000;
+
+fn perft(fen: &str, variant: Variant, depth: usize) -> i64 {
+    let mut engine = ChessEngine::new(depth, 40, 42);
+    let mut state =
+        GameState::new_from_fen_with_variant(fen, variant, &engine.zoborist_state).unwrap();
+    engine.perft(&mut state, depth).unwrap()
+}
+
+#[test]
+fn matches_the_variant_references() {
+    for variant in Variant::ALL {
+        for (depth, &expected) in variant.perft_reference().iter().enumerate() {
+            if expected > MAX_NODES {
+                break;
+            }
+            let nodes = perft(variant.perft_fen(), variant, depth + 1);
+            assert_eq!(nodes, expected, "{} at depth {}", variant.name(), depth + 1);
+        }
+    }
+}
+
+#[test]
+fn matches_chess960_references() {
+    for (fen, expected) in [
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            [21, 528, 12189, 326672].as_slice(),
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            &[21, 807, 18002],
+        ),
+        // outer rooks given as KQkq, with the king next to one of them
+        (
+            "r1k1r2q/p1ppp1pp/8/8/8/8/P1PPP1PP/R1K1R2Q w KQkq - 0 1",
+            &[23, 522, 12333, 285754],
+        ),
+    ] {
+        for (depth, &expected) in expected.iter().enumerate() {
+            let nodes = perft(fen, Variant::Standard, depth + 1);
+            assert_eq!(nodes, expected, "{} at depth {}", fen, depth + 1);
+        }
+    }
+}
+
+#[test]
+fn stops_at_the_deadline() {
+    let mut engine = ChessEngine::new(6, 40, 42);
+    let state = GameState::new_from_fen(Variant::Standard.perft_fen(), &engine.zoborist_state)
+        .unwrap();
+    let stop_signal = engine.stop_signal();
+    stop_signal.set_deadline(Some(std::time::Instant::now()));
+    assert!(engine.perft_divide(&state, 6).is_none());
+    // nothing cut short was kept in the cache
+    stop_signal.reset();
+    let divide = engine.perft_divide(&state, 4).unwrap();
+    assert_eq!(divide.iter().map(|&(_, nodes)| nodes).sum::<i64>(), 197281);
+}
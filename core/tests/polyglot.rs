@@ -0,0 +1,114 @@
+use chess_engine_core::{polyglot_key, Book, BookSelection, Game, GameState, ZoboristState};
+
+/// the test positions of the Polyglot book format, reached by the moves before them
+const KEYS: [(&str, &str, u64); 9] = [
+    (
+        "",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        0x463b96181691fc9c,
+    ),
+    // the en passant square doesn't count without a pawn to take on it
+    (
+        "e2e4",
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        0x823c9b50fd114196,
+    ),
+    (
+        "e2e4 d7d5",
+        "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        0x0756b94461c50fb0,
+    ),
+    (
+        "e2e4 d7d5 e4e5",
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+        0x662fafb965db29d4,
+    ),
+    // e5 can take on f6
+    (
+        "e2e4 d7d5 e4e5 f7f5",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        0x22a48b5a8e47ff78,
+    ),
+    (
+        "e2e4 d7d5 e4e5 f7f5 e1e2",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+        0x652a607ca3f242c1,
+    ),
+    (
+        "e2e4 d7d5 e4e5 f7f5 e1e2 e8f7",
+        "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+        0x00fdd303c946bdd9,
+    ),
+    (
+        "a2a4 b7b5 h2h4 b5b4 c2c4",
+        "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+        0x3c8123ea7b067637,
+    ),
+    // only white's queenside rook is gone
+    (
+        "a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3",
+        "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+        0x5c3f9b829b279560,
+    ),
+];
+
+#[test]
+fn matches_the_reference_keys() {
+    let zoborist_state = ZoboristState::new(0x5eed);
+    for (moves, fen, key) in KEYS {
+        let state = GameState::new_from_fen(fen, &zoborist_state).unwrap();
+        assert_eq!(polyglot_key(&state), key, "{}", fen);
+
+        // the same position reached by playing the moves
+        let mut game = Game::new(&zoborist_state);
+        for text in moves.split_whitespace() {
+            game.play_uci(text).unwrap();
+        }
+        assert_eq!(polyglot_key(game.state()), key, "after {}", moves);
+    }
+}
+
+/// a book file entry: key, move, weight and the unused learn field
+fn entry(key: u64, mov: u16, weight: u16) -> Vec<u8> {
+    [&key.to_be_bytes()[..], &mov.to_be_bytes(), &weight.to_be_bytes(), &[0; 4]].concat()
+}
+
+/// a polyglot move: destination file and rank in the low six bits, then the origin
+fn book_move(from: (u16, u16), to: (u16, u16)) -> u16 {
+    to.0 | to.1 << 3 | from.0 << 6 | from.1 << 9
+}
+
+#[test]
+fn probes_castling_as_the_king_taking_its_rook() {
+    let zoborist_state = ZoboristState::new(0x5eed);
+    let white = GameState::new_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &zoborist_state)
+        .unwrap();
+    let black = GameState::new_from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", &zoborist_state)
+        .unwrap();
+    let (white_key, black_key) = (polyglot_key(&white), polyglot_key(&black));
+    let mut data = [
+        // e1h1, short castling
+        entry(white_key, book_move((4, 0), (7, 0)), 30),
+        entry(white_key, book_move((4, 0), (4, 1)), 20),
+        // the king's own two-square move isn't how polyglot writes castling
+        entry(white_key, book_move((4, 0), (6, 0)), 50),
+        // e8a8, long castling
+        entry(black_key, book_move((4, 7), (0, 7)), 10),
+        entry(black_key, book_move((0, 7), (0, 0)), 5),
+    ]
+    .concat();
+    // books are sorted by key, but the reader shouldn't rely on it
+    data.rotate_left(16);
+    let book = Book::from_bytes(&data).unwrap();
+    assert_eq!(book.len(), 5);
+
+    let white_move = book.probe(&white, BookSelection::Best).unwrap();
+    assert_eq!(white.move_to_uci(white_move), "e1g1");
+    assert_eq!(book.moves(&white).len(), 2);
+    let black_move = book.probe(&black, BookSelection::Best).unwrap();
+    assert_eq!(black.move_to_uci(black_move), "e8c8");
+    let start = Game::new(&zoborist_state);
+    assert!(book.probe(start.state(), BookSelection::Best).is_none());
+
+    assert!(Book::from_bytes(&data[..20]).is_err());
+}
@@ -0,0 +1,142 @@
+use chess_engine_core::{Game, Outcome, Player, ZoboristState};
+
+fn game(fen: &str) -> Game {
+    Game::from_fen(fen, &ZoboristState::new(0x5eed)).unwrap()
+}
+
+/// the SAN of each of `moves`, given in long algebraic notation, played from `fen`
+fn san(fen: &str, moves: &[&str]) -> Vec<String> {
+    let mut game = game(fen);
+    moves
+        .iter()
+        .map(|text| {
+            let mut next = game.clone();
+            let mov = next.play_uci(text).unwrap();
+            let san = game.move_to_san(mov);
+            game = next;
+            san
+        })
+        .collect()
+}
+
+#[test]
+fn writes_san() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(
+        san(
+            start,
+            &["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "f1c4"]
+        ),
+        ["e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5", "Bc4"]
+    );
+    assert_eq!(
+        san(start, &["f2f3", "e7e5", "g2g4", "d8h4"]),
+        ["f3", "e5", "g4", "Qh4#"]
+    );
+    // knights on b1 and f3 both reach d2, rooks on a1 and a5 both reach a3
+    let fen = "4k3/8/8/R7/8/8/8/RN2KB2 w - - 0 1";
+    assert_eq!(san(fen, &["a5a3"]), ["R5a3"]);
+    assert_eq!(san(fen, &["b1d2"]), ["Nd2"]);
+    assert_eq!(
+        san("4k3/8/8/8/8/5N2/8/RN2K3 w - - 0 1", &["b1d2"]),
+        ["Nbd2"]
+    );
+    assert_eq!(
+        san(
+            "r3k2r/6P1/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            &["e1g1", "e8c8", "g7h8q"]
+        ),
+        ["O-O", "O-O-O", "gxh8=Q"]
+    );
+    assert_eq!(
+        san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", &["e5d6"]),
+        ["exd6"]
+    );
+}
+
+#[test]
+fn detects_the_end_of_the_game() {
+    let mut fools_mate = game("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    for text in ["f2f3", "e7e5", "g2g4"] {
+        fools_mate.play_uci(text).unwrap();
+        assert_eq!(fools_mate.outcome(), None);
+    }
+    fools_mate.play_uci("d8h4").unwrap();
+    let outcome = fools_mate.outcome().unwrap();
+    assert_eq!(
+        outcome,
+        Outcome::Checkmate {
+            winner: Player::Black
+        }
+    );
+    assert_eq!(outcome.pgn_result(), "0-1");
+
+    assert_eq!(
+        game("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").outcome(),
+        Some(Outcome::Stalemate)
+    );
+    assert_eq!(
+        game("8/8/4k3/8/8/3NK3/8/8 w - - 0 1").outcome(),
+        Some(Outcome::InsufficientMaterial)
+    );
+    assert_eq!(game("8/8/4k3/8/8/3NK3/8/N7 w - - 0 1").outcome(), None);
+
+    let mut shuffle = game("r3k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+    for _ in 0..2 {
+        assert_eq!(shuffle.outcome(), None);
+        for text in ["a1a2", "a8a7", "a2a1", "a7a8"] {
+            shuffle.play_uci(text).unwrap();
+        }
+    }
+    assert_eq!(shuffle.outcome(), Some(Outcome::Repetition));
+
+    let mut fifty = game("r3k3/8/8/8/8/8/8/R3K3 w - - 99 80");
+    assert_eq!(fifty.outcome(), None);
+    fifty.play_uci("a1a2").unwrap();
+    assert_eq!(fifty.outcome(), Some(Outcome::FiftyMoves));
+}
+
+#[test]
+fn counts_moves_in_the_fen() {
+    let mut opening = game("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    for text in ["e2e4", "e7e5", "g1f3"] {
+        opening.play_uci(text).unwrap();
+    }
+    assert_eq!(
+        opening.state().to_fen(),
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    );
+    opening.play_uci("b8c6").unwrap();
+    assert!(opening.state().to_fen().ends_with(" w KQkq - 2 3"));
+    opening.undo();
+    opening.undo();
+    assert!(opening.state().to_fen().ends_with(" w KQkq e6 0 2"));
+
+    let fen = "4k3/8/8/8/8/8/8/4K2R b K - 12 40";
+    assert_eq!(game(fen).state().to_fen(), fen);
+}
+
+#[test]
+fn rejects_malformed_fens() {
+    let zoborist_state = ZoboristState::new(0x5eed);
+    for fen in [
+        "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+        "4k3/8/8/8/8/8/8/3K5 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K2 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K3/8 w - - 0 1",
+        "4k3/8/8/8/8/8/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/0004K3 w - - 0 1",
+        "1/1/1/1/1/1/1/1 w - - 0 1",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 2",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 2",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e4 0 2",
+        // no black pawn that could just have moved to e5
+        "4k3/8/8/3P4/8/8/8/4K3 w - e6 0 1",
+        // e7 is still occupied
+        "4k3/4p3/8/3Pp3/8/8/8/4K3 w - e6 0 1",
+    ] {
+        assert!(Game::from_fen(fen, &zoborist_state).is_err(), "{}", fen);
+    }
+    game("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+    game("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+}
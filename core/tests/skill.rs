@@ -0,0 +1,19 @@
+use chess_engine_core::{Skill, MAX_SKILL_LEVEL};
+
+#[test]
+fn from_elo_covers_the_levels_in_order() {
+    assert_eq!(Skill::from_elo(Skill::min_elo()).level(), 0.0);
+    assert_eq!(Skill::from_elo(Skill::max_elo()).level(), MAX_SKILL_LEVEL as f64);
+    assert!(Skill::from_elo(Skill::max_elo()).is_full_strength());
+    // ratings outside the range are clamped to it
+    assert_eq!(Skill::from_elo(0).level(), 0.0);
+    assert_eq!(Skill::from_elo(4000).level(), MAX_SKILL_LEVEL as f64);
+
+    let mut previous = -1.0;
+    for elo in Skill::min_elo()..=Skill::max_elo() {
+        let level = Skill::from_elo(elo).level();
+        assert!(level > previous, "level {} at {} elo, {} just below", level, elo, previous);
+        assert!(level < MAX_SKILL_LEVEL as f64 || elo == Skill::max_elo(), "{} elo", elo);
+        previous = level;
+    }
+}
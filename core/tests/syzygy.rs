@@ -0,0 +1,176 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chess_engine_core::{ChessEngine, GameState, Tablebases, Wdl, ZoboristState};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+// tablebase piece codes of the pieces in KRvK, in table order
+const KRVK: [u8; 3] = [6, 4, 14];
+
+/// a KRvK table in which every position has the same value for each side to move, the
+/// simplest layout the format allows. WDL values are stored plus 2, so 4 is a win. DTZ
+/// tables only store white to move, here in moves rather than plies.
+fn single_value_table(magic: [u8; 4], values: &[u8]) -> Vec<u8> {
+    let split = (values.len() == 2) as u8;
+    let mut data = magic.to_vec();
+    data.push(split);
+    // the order of the piece groups, then the pieces for each side to move
+    data.push(0);
+    data.extend(KRVK.iter().map(|&code| code | code << 4));
+    if data.len() % 2 == 1 {
+        data.push(0);
+    }
+    for &value in values {
+        data.extend([0x80, value]);
+    }
+    // the (empty) block data starts at the next multiple of 64
+    data.resize(64, 0);
+    data
+}
+
+fn wdl_table() -> Vec<u8> {
+    single_value_table(WDL_MAGIC, &[4, 0])
+}
+
+fn dtz_table() -> Vec<u8> {
+    single_value_table(DTZ_MAGIC, &[10])
+}
+
+/// an empty directory for the table files of one test
+fn table_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chess-engine-syzygy-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn probe_wdl(dir: &Path, fen: &str) -> Option<Wdl> {
+    let zoborist_state = ZoboristState::new(0x5eed);
+    let mut state = GameState::new_from_fen(fen, &zoborist_state).unwrap();
+    Tablebases::new(dir.to_str().unwrap()).probe_wdl(&mut state, &zoborist_state)
+}
+
+#[test]
+fn probes_wdl_and_dtz() {
+    let dir = table_dir("probe");
+    fs::write(dir.join("KRvK.rtbw"), wdl_table()).unwrap();
+    fs::write(dir.join("KRvK.rtbz"), dtz_table()).unwrap();
+
+    assert_eq!(probe_wdl(&dir, "8/8/8/4k3/8/8/8/R3K3 w - - 0 1"), Some(Wdl::Win));
+    assert_eq!(probe_wdl(&dir, "8/8/8/4k3/8/8/8/R3K3 b - - 0 1"), Some(Wdl::Loss));
+    // the stronger side is found whichever colour it has
+    assert_eq!(probe_wdl(&dir, "r3k3/8/8/8/4K3/8/8/8 b - - 0 1"), Some(Wdl::Win));
+    // taking the rook leaves two kings, whatever the table says
+    assert_eq!(probe_wdl(&dir, "8/8/8/8/8/8/1k6/1R2K3 b - - 0 1"), Some(Wdl::Draw));
+    // no table for this material
+    assert_eq!(probe_wdl(&dir, "8/8/8/4k3/8/8/8/N3K3 w - - 0 1"), None);
+
+    // 10 moves to zeroing in the table: 21 plies after a rook move and the reply
+    let zoborist_state = ZoboristState::new(0x5eed);
+    let mut tablebases = Tablebases::new(dir.to_str().unwrap());
+    for (clock, expected) in [(0, Wdl::Win), (77, Wdl::Win), (78, Wdl::CursedWin)] {
+        let fen = format!("8/8/8/4k3/8/8/8/R3K3 w - - {} 60", clock);
+        let mut state = GameState::new_from_fen(&fen, &zoborist_state).unwrap();
+        let (_, wdl) = tablebases.probe_root(&mut state, &zoborist_state).unwrap();
+        assert_eq!(wdl, expected, "{}", fen);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ignores_truncated_and_corrupted_tables() {
+    let dir = table_dir("corrupt");
+    let fen = "8/8/8/4k3/8/8/8/R3K3 w - - 0 1";
+
+    // every prefix of a good table, down to a bare header
+    let table = wdl_table();
+    for len in 0..table.len() {
+        fs::write(dir.join("KRvK.rtbw"), &table[..len]).unwrap();
+        assert_eq!(probe_wdl(&dir, fen), None, "{} bytes", len);
+    }
+
+    // compressed tables with random sizes, symbol lengths and trees
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut random = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    };
+    for _ in 0..2000 {
+        let mut table = wdl_table();
+        table.truncate(10);
+        for _ in 0..2 {
+            let (min_sym_len, extra_lens) = (1 + random() % 8, random() % 4);
+            let blocks = 1 + random() % 8;
+            table.extend([0, 5 + random() % 4, 10 + random() % 8, random() % 4, blocks, 0, 0, 0]);
+            table.extend([min_sym_len + extra_lens, min_sym_len]);
+            table.extend((0..2 * (extra_lens + 1)).map(|_| random()));
+            // a tree of symbols pointing at each other, or leaves
+            let symbols = 1 + random() as u16 % 32;
+            table.extend(symbols.to_le_bytes());
+            for _ in 0..symbols {
+                let (left, right) = if random() % 2 == 0 {
+                    (random() as u16, 0xfff)
+                } else {
+                    (random() as u16 % symbols, random() as u16 % symbols)
+                };
+                table.extend([left as u8, (left >> 8) as u8 | (right << 4) as u8, (right >> 4) as u8]);
+            }
+            if symbols % 2 == 1 {
+                table.push(0);
+            }
+        }
+        // up to 8 blocks of 256 bytes for each side, with room to spare in most tables
+        let len = table.len() + 16 * random() as usize + 2000;
+        table.extend((table.len()..len).map(|_| random()));
+        fs::write(dir.join("KRvK.rtbw"), &table).unwrap();
+        probe_wdl(&dir, fen);
+    }
+
+    // the search carries on without the table
+    fs::write(dir.join("KRvK.rtbw"), [0x71, 0xe8, 0x23, 0x5d, 0x01, 0x00]).unwrap();
+    let zoborist_state = ZoboristState::new(0x5eed);
+    let state = GameState::new_from_fen(fen, &zoborist_state).unwrap();
+    let mut engine = ChessEngine::new(4, 40, 13);
+    engine.set_debug_output(false);
+    engine.set_syzygy_path(dir.to_str().unwrap());
+    engine.solve(&state, 4);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// known results from real tables. needs the 3 and 4 piece files in SYZYGY_PATH, and does
+/// nothing without them.
+#[test]
+fn probes_real_tables() {
+    let Ok(path) = std::env::var("SYZYGY_PATH") else {
+        return;
+    };
+    let zoborist_state = ZoboristState::new(0x5eed);
+    let mut tablebases = Tablebases::new(&path);
+    let state = |fen: &str| GameState::new_from_fen(fen, &zoborist_state).unwrap();
+
+    for (fen, expected) in [
+        ("8/8/8/4k3/8/8/8/R3K3 w - - 0 1", Wdl::Win),
+        ("8/8/8/8/8/8/1k6/1R2K3 b - - 0 1", Wdl::Draw),
+        ("8/8/8/4k3/8/8/8/N3K3 w - - 0 1", Wdl::Draw),
+        ("8/8/8/4k3/8/8/8/Q3K2n w - - 0 1", Wdl::Win),
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+    ] {
+        assert_eq!(tablebases.probe_wdl(&mut state(fen), &zoborist_state), Some(expected), "{}", fen);
+    }
+
+    // Rh8 mates
+    let mut mate_in_one = state("k7/8/1K6/8/8/8/8/7R w - - 0 1");
+    assert_eq!(tablebases.probe_dtz(&mut mate_in_one, &zoborist_state), Some(1));
+    let (mov, wdl) = tablebases.probe_root(&mut mate_in_one, &zoborist_state).unwrap();
+    assert_eq!((mate_in_one.move_to_uci(mov).as_str(), wdl), ("h1h8", Wdl::Win));
+
+    // a rook ending that takes well over one move, with the fifty-move count almost run out
+    let mut late = state("8/8/8/4k3/8/8/8/R3K3 w - - 99 80");
+    let (_, wdl) = tablebases.probe_root(&mut late, &zoborist_state).unwrap();
+    assert_eq!(wdl, Wdl::CursedWin);
+}
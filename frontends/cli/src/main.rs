@@ -1,7 +1,11 @@
-use std::{time::{SystemTime, Duration}, fs::File, io::Read, thread};
+mod play;
 
-use chess_engine_core::{ChessEngine, GameState, evaluate};
-use clap::Parser;
+use std::{time::{SystemTime, Duration}, fs::File, io::Read};
+
+use chess_engine_core::{ChessEngine, Game, GameState, Iteration, Move, Player, SearchLimits, SearchObserver, Skill, TimeControl, TimeManager, evaluator_from_name, slider_indexing, verify_attack_tables, Variant, EVALUATOR_NAMES, MAX_SKILL_LEVEL, PHASE_MAX, VARIANT_NAMES, ZoboristState};
+use clap::{Parser, Subcommand};
+
+use crate::play::PlayOptions;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -18,12 +22,221 @@ struct Cli {
 
     #[clap(long,short='d')]
     fixed_depth: Option<usize>,
+
+    /// Static evaluation used by the search
+    #[clap(long, short='e', default_value = "pesto", value_parser = EVALUATOR_NAMES)]
+    evaluator: String,
+
+    /// Rules to play or run perft under
+    #[clap(long, default_value = "chess", value_parser = VARIANT_NAMES)]
+    variant: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a per-term breakdown of the static evaluation
+    Eval {
+        /// Position to evaluate. Defaults to the state given by --path-to-state, or the starting position
+        #[clap(long)]
+        fen: Option<String>,
+    },
+    /// Draw the board with the side to move, castling rights, en passant square, FEN and hash
+    #[command(name = "d")]
+    Display {
+        /// Position to draw. Defaults to the state given by --path-to-state, or the starting position
+        #[clap(long)]
+        fen: Option<String>,
+        /// Draw the pieces as Unicode chess glyphs
+        #[clap(long)]
+        unicode: bool,
+        /// Show the board from Black's side
+        #[clap(long)]
+        flip: bool,
+    },
+    /// Play a skill level against a stronger one, by default the full-strength engine, to
+    /// calibrate UCI_Elo
+    SkillMatch {
+        /// Skill level of the weaker side, from 0 to 20
+        #[clap(long)]
+        level: u8,
+        /// Skill level of the stronger side. Levels too weak to score against full strength
+        /// are measured against the next levels up instead
+        #[clap(long, default_value_t = 20)]
+        against: u8,
+        /// Games to play, alternating colours
+        #[clap(long, default_value_t = 20)]
+        games: usize,
+        /// Search depth of the stronger side, which also caps the weaker side
+        #[clap(long, default_value_t = 6)]
+        depth: usize,
+    },
+    /// Play a game against the engine, with moves typed in SAN or long algebraic notation
+    Play {
+        /// Side you play
+        #[clap(long, default_value = "white", value_parser = ["white", "black", "random"])]
+        color: String,
+        /// Time control as minutes+increment in seconds
+        #[clap(long, default_value = "5+3")]
+        time: String,
+        /// Skill level of the engine, from 0 to 20
+        #[clap(long, default_value_t = MAX_SKILL_LEVEL)]
+        level: u8,
+        /// Position to start from. Defaults to the starting position of --variant
+        #[clap(long)]
+        fen: Option<String>,
+        /// Draw the pieces as Unicode chess glyphs
+        #[clap(long)]
+        unicode: bool,
+    },
+    /// Check the precomputed attack tables against the ray fills they replaced
+    CheckAttacks {
+        /// Random occupancies tried per square
+        #[clap(long, default_value_t = 10000)]
+        samples: usize,
+    },
+}
+
+// prints each depth of a timed search
+struct Report {
+    state: GameState,
+}
+
+impl SearchObserver for Report {
+    fn iteration_finished(&mut self, engine: &ChessEngine, iteration: &Iteration) {
+        let (score, pv) = &iteration.lines[0];
+        let mut state = self.state.clone();
+        let pv: Vec<String> = pv
+            .iter()
+            .map(|&mov| {
+                let text = state.move_to_uci(mov);
+                state.make_move(mov, &engine.zoborist_state);
+                text
+            })
+            .collect();
+        println!("depth: {}, score: {}, pv: {}", iteration.depth, score / PHASE_MAX, pv.join(" "));
+    }
+}
+
+// skill-match games are not reported on move by move
+struct Silent;
+
+impl SearchObserver for Silent {
+    fn iteration_finished(&mut self, _engine: &ChessEngine, _iteration: &Iteration) {}
+}
+
+/// the move `skill` plays after searching up to `depth`
+fn skill_move(engine: &mut ChessEngine, state: &GameState, skill: Skill, depth: usize) -> Move {
+    let limits = SearchLimits {
+        depth,
+        multi_pv: 1,
+        skill,
+    };
+    let time_manager = TimeManager::new(None, Duration::ZERO, false);
+    let (_, pv) = engine
+        .iterative_search(state, &limits, &time_manager, &mut Silent)
+        .expect("the game is not over");
+    pv[0]
+}
+
+/// plays one game and returns the score of `level`: 1 for a win, 0.5 for a draw. games
+/// still going after 200 moves are drawn.
+fn skill_game(level: Skill, against: Skill, level_is_white: bool, depth: usize) -> f64 {
+    let zoborist_state = ZoboristState::new(42);
+    let mut game = Game::new(&zoborist_state);
+    let mut engines = [true, false].map(|_| {
+        let mut engine = ChessEngine::new(depth, 40, 42);
+        engine.set_hash_size(16).expect("cannot allocate the hash table");
+        engine
+    });
+    let mut seen = vec![game.state().hash];
+    for ply in 0..400 {
+        let level_to_move = (ply % 2 == 0) == level_is_white;
+        let state = game.state().clone();
+        if state.legal_moves().is_empty() {
+            let lost = state.is_in_check() || state.is_variant_loss();
+            return match (lost, level_to_move) {
+                (false, _) => 0.5,
+                (true, true) => 0.0,
+                (true, false) => 1.0,
+            };
+        }
+        let (engine, skill) = if level_to_move {
+            (&mut engines[0], level)
+        } else {
+            (&mut engines[1], against)
+        };
+        let mov = skill_move(engine, &state, skill, depth);
+        game.play(mov).unwrap();
+        // threefold repetition
+        seen.push(game.state().hash);
+        if seen.iter().filter(|&&hash| hash == game.state().hash).count() >= 3 {
+            return 0.5;
+        }
+    }
+    0.5
 }
 
 fn main() {    
     let cli = Cli::parse();
+    if let Some(Command::SkillMatch { level, against, games, depth }) = cli.command {
+        let skill = Skill::from_level(level);
+        let mut score = 0.0;
+        for game in 0..games {
+            let result = skill_game(skill, Skill::from_level(against), game % 2 == 0, depth);
+            score += result;
+            println!("skill-match game {}: {}", game + 1, result);
+        }
+        // the elo difference that predicts this score, keeping clear of 0% and 100%
+        let ratio = (score / games as f64).clamp(0.5 / games as f64, 1.0 - 0.5 / games as f64);
+        println!(
+            "skill-match level {}: {}/{} against level {}, {:.0} elo",
+            level,
+            score,
+            games,
+            against,
+            -400.0 * (1.0 / ratio - 1.0).log10()
+        );
+        return;
+    }
+    if let Some(Command::Play { color, time, level, fen, unicode }) = cli.command {
+        let variant = Variant::from_name(&cli.variant).expect("checked by clap");
+        let (time, increment) = play::parse_time_control(&time).unwrap_or_else(|err| panic!("{}", err));
+        let evaluator = evaluator_from_name(&cli.evaluator).expect("checked by clap");
+        let mut engine = ChessEngine::with_evaluator(16, 40, 13, evaluator);
+        engine.set_hash_size(64).expect("cannot allocate the hash table");
+        // the board and the clocks are all the user wants to see
+        engine.set_debug_output(false);
+        let game = match fen {
+            Some(fen) => Game::from_fen_with_variant(&fen, variant, &engine.zoborist_state)
+                .unwrap_or_else(|err| panic!("invalid fen: {}", err)),
+            None => Game::new_variant(variant, &engine.zoborist_state),
+        };
+        let color = match color.as_str() {
+            "white" => Some(Player::White),
+            "black" => Some(Player::Black),
+            _ => None,
+        };
+        play::run(engine, game, PlayOptions { color, time, increment, level: level.min(MAX_SKILL_LEVEL), unicode });
+        return;
+    }
+    if let Some(Command::CheckAttacks { samples }) = cli.command {
+        println!("slider indexing: {:?}", slider_indexing());
+        match verify_attack_tables(samples) {
+            Ok(()) => println!("attack tables match the ray fills"),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let variant = Variant::from_name(&cli.variant).expect("checked by clap");
     if let Some(perft_depth) = cli.perft{
-        println!("perft of depth {} from starting position", perft_depth);
+        println!("perft of depth {} from {} ({})", perft_depth, variant.perft_fen(), variant.name());
         
         // println!("Initial score: {}", evaluate(&game_state));
 
@@ -32,13 +245,29 @@ fn main() {
         let mut engine = ChessEngine::new(perft_depth, 40, 42);
 
         for i in 1..=perft_depth{
-            let mut game_state = GameState::default();
+            let mut game_state = GameState::new_from_fen_with_variant(
+                variant.perft_fen(),
+                variant,
+                &engine.zoborist_state,
+            )
+            .unwrap();
 
             // let mut engine = ChessEngine::new(i, 40, 42);
             
-            println!("{}: {} nodes", i, engine.perft(&mut game_state, i));
+            let nodes = engine.perft(&mut game_state, i).expect("nothing stops the perft");
+            match variant.perft_reference().get(i - 1) {
+                Some(&expected) if expected != nodes => {
+                    println!("{}: {} nodes, expected {}", i, nodes, expected)
+                }
+                _ => println!("{}: {} nodes", i, nodes),
+            }
             let cur_time = SystemTime::now();
-            println!("{} ms", cur_time.duration_since(now).unwrap().as_millis());
+            let elapsed = cur_time.duration_since(now).unwrap();
+            println!(
+                "{} ms, {} nps",
+                elapsed.as_millis(),
+                (nodes as f64 / elapsed.as_secs_f64().max(1e-6)) as u64
+            );
             now = cur_time;            
         }
 
@@ -53,16 +282,36 @@ fn main() {
 
             serde_json::from_str(&buf).unwrap()
         },
-        None => GameState::default(),
+        None => GameState::new_variant(variant, &ZoboristState::new(42)),
     };
     
     // let mut game_state = 
     // GameState::new_from_state(white_state, black_state, Player::White);
-    println!("Initial score: {}", evaluate(&game_state));
+    let evaluator = evaluator_from_name(&cli.evaluator).expect("checked by clap");
+
+    if let Some(Command::Eval { fen }) = cli.command {
+        let game_state = match fen {
+            Some(fen) => GameState::new_from_fen(&fen, &ZoboristState::new(42)).unwrap(),
+            None => game_state,
+        };
+        println!("{}", evaluator.evaluate_with_trace(&game_state));
+        return;
+    }
+
+    if let Some(Command::Display { fen, unicode, flip }) = cli.command {
+        let game_state = match fen {
+            Some(fen) => GameState::new_from_fen_with_variant(&fen, variant, &ZoboristState::new(42)).unwrap(),
+            None => game_state,
+        };
+        println!("{}", game_state.diagram(unicode, flip));
+        return;
+    }
+
+    println!("Initial score ({}): {}", evaluator.name(), evaluator.evaluate(&game_state));
     // let start_time = SystemTime::now();
     
     // let (rx,tx) = mpsc::channel();
-    let mut engine = ChessEngine::new(10, 40, 42);
+    let mut engine = ChessEngine::with_evaluator(10, 40, 42, evaluator);
 
     if let Some(depth) = cli.fixed_depth{
         engine.solve(&game_state, depth);
@@ -71,19 +320,11 @@ fn main() {
     }
 
     let max_duration = Duration::from_secs(cli.allowed_time.unwrap_or(3));
-    thread::spawn(move ||{
-        for i in 1..10{
-            // rx.send((i,engine.solve(&game_state)));
-            println!("depth: {}", i);
-            engine.solve(&game_state, i);
-            engine.get_result();
-            println!();
-        }
-    });        
-
-    thread::sleep(max_duration);
-
-    // println!("best value: {}, time: {}ms", engine.solve(&game_state), SystemTime::now().duration_since(start_time).unwrap().as_millis());
-
-    // engine.get_result();
+    let time_manager = TimeManager::new(Some(TimeControl::MoveTime(max_duration)), Duration::ZERO, false);
+    engine.stop_signal().set_deadline(time_manager.deadline());
+    let mut report = Report { state: game_state.clone() };
+    match engine.iterative_search(&game_state, &SearchLimits::default(), &time_manager, &mut report) {
+        Some((_, pv)) => println!("best move: {}, {} ms", game_state.move_to_uci(pv[0]), time_manager.elapsed().as_millis()),
+        None => println!("no legal moves"),
+    }
 }
@@ -0,0 +1,432 @@
+//! a game against the engine on the terminal. moves are read in SAN or long algebraic
+//! notation, and the board is drawn after each one. besides moves:
+//!
+//! - `undo` takes back your last move and the engine's reply
+//! - `hint` suggests a move
+//! - `resign` ends the game
+//! - `save [file]` writes the game as PGN, to `game.pgn` by default
+//! - `board` draws the board again, `help` lists the commands and `quit` leaves
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    time::{Duration, Instant, SystemTime},
+};
+
+use chess_engine_core::{
+    ChessEngine, Game, Iteration, Move, MoveError, Outcome, Player, SearchLimits, SearchObserver,
+    Skill, TimeControl, TimeManager, Variant, PHASE_MAX,
+};
+
+const ENGINE_NAME: &str = "loglogn-bot";
+const DEFAULT_PGN_PATH: &str = "game.pgn";
+
+// kept back from the engine's clock for drawing the board
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+const HINT_TIME: Duration = Duration::from_secs(1);
+
+pub struct PlayOptions {
+    /// the side the user plays, none for a random one
+    pub color: Option<Player>,
+    pub time: Duration,
+    pub increment: Duration,
+    pub level: u8,
+    pub unicode: bool,
+}
+
+/// `5+3` for 5 minutes with 3 seconds more after each move, or `5` without increment
+pub fn parse_time_control(text: &str) -> Result<(Duration, Duration), String> {
+    let (minutes, increment) = text.split_once('+').unwrap_or((text, "0"));
+    let seconds = |text: &str, scale: f64| {
+        text.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .map(|value| Duration::from_secs_f64(value * scale))
+    };
+    match (seconds(minutes, 60.0), seconds(increment, 1.0)) {
+        (Some(time), Some(increment)) if !time.is_zero() => Ok((time, increment)),
+        _ => Err(format!(
+            "expected minutes+increment, e.g. 5+3, got {}",
+            text
+        )),
+    }
+}
+
+/// how the game ended
+enum End {
+    Outcome(Outcome),
+    Resigned(Player),
+    /// this side's flag fell
+    Time(Player),
+}
+
+impl End {
+    fn pgn_result(&self) -> &'static str {
+        match self {
+            End::Outcome(outcome) => outcome.pgn_result(),
+            End::Resigned(Player::White) | End::Time(Player::White) => "0-1",
+            End::Resigned(Player::Black) | End::Time(Player::Black) => "1-0",
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            End::Outcome(outcome) => outcome.to_string(),
+            End::Resigned(player) => format!("{:?} resigns", player),
+            End::Time(player) => format!("{:?} lost on time", player),
+        }
+    }
+}
+
+// only the depth reached is shown, not every iteration
+struct LastDepth(usize);
+
+impl SearchObserver for LastDepth {
+    fn iteration_finished(&mut self, _engine: &ChessEngine, iteration: &Iteration) {
+        self.0 = iteration.depth;
+    }
+}
+
+fn format_clock(time: Duration) -> String {
+    let tenths = time.as_millis() / 100;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+struct Match {
+    engine: ChessEngine,
+    game: Game,
+    user: Player,
+    skill: Skill,
+    options: PlayOptions,
+    clocks: [Duration; 2],
+    // the clocks before each move, for undo
+    clock_history: Vec<[Duration; 2]>,
+    end: Option<End>,
+}
+
+impl Match {
+    fn print_board(&self) {
+        println!(
+            "{}",
+            self.game
+                .state()
+                .diagram(self.options.unicode, self.user == Player::Black)
+        );
+        println!(
+            "white {}  black {}",
+            format_clock(self.clocks[Player::White as usize]),
+            format_clock(self.clocks[Player::Black as usize])
+        );
+    }
+
+    /// `1. e4` or `1... e5`
+    fn numbered(&self, san: &str) -> String {
+        let ply = self.game.moves().count() + self.game.start_state().player as usize;
+        match ply % 2 {
+            0 => format!("{}. {}", ply / 2 + 1, san),
+            _ => format!("{}... {}", ply / 2 + 1, san),
+        }
+    }
+
+    /// charges `elapsed` to the side to move and plays `mov` for it, unless its flag fell
+    fn play(&mut self, mov: Move, elapsed: Duration) {
+        let player = self.game.state().player;
+        let clock = &mut self.clocks[player as usize];
+        if elapsed > *clock {
+            *clock = Duration::ZERO;
+            self.end = Some(End::Time(player));
+            return;
+        }
+        self.clock_history.push(self.clocks);
+        self.clocks[player as usize] += self.options.increment;
+        self.clocks[player as usize] -= elapsed;
+        self.game.play(mov).expect("only legal moves are played");
+        self.end = self.game.outcome().map(End::Outcome);
+    }
+
+    /// searches the current position with `time_control`, returning the move, its score in
+    /// pawns from white's side and the depth reached
+    fn search(&mut self, time_control: TimeControl, skill: Skill) -> (Move, f64, usize) {
+        let state = self.game.state().clone();
+        let time_manager = TimeManager::new(Some(time_control), MOVE_OVERHEAD, false);
+        self.engine.set_game(&self.game);
+        let stop_signal = self.engine.stop_signal();
+        stop_signal.reset();
+        stop_signal.set_deadline(time_manager.deadline());
+        let limits = SearchLimits {
+            skill,
+            ..SearchLimits::default()
+        };
+        let mut depth = LastDepth(0);
+        let (score, pv) = self
+            .engine
+            .iterative_search(&state, &limits, &time_manager, &mut depth)
+            .expect("the game is not over");
+        (pv[0], (score / PHASE_MAX) as f64 / 100.0, depth.0)
+    }
+
+    fn engine_move(&mut self) {
+        let player = self.game.state().player;
+        let time_control = TimeControl::Clock {
+            time: self.clocks[player as usize],
+            increment: self.options.increment,
+            moves_to_go: None,
+        };
+        let start = Instant::now();
+        let (mov, score, depth) = self.search(time_control, self.skill);
+        let elapsed = start.elapsed();
+        let san = self.numbered(&self.game.move_to_san(mov));
+        self.play(mov, elapsed);
+        if !matches!(self.end, Some(End::Time(_))) {
+            println!(
+                "{} plays {} (depth {}, {:+.2}, {:.1} s)",
+                ENGINE_NAME,
+                san,
+                depth,
+                score,
+                elapsed.as_secs_f64()
+            );
+            self.print_board();
+        }
+    }
+
+    fn user_move(&mut self, text: &str, elapsed: Duration) {
+        if self.end.is_some() {
+            println!("the game is over, undo a move to go on playing");
+            return;
+        }
+        // `g1f3` reads as a pawn move in SAN, so long algebraic notation gets a try as well
+        let mut trial = self.game.clone();
+        let mov = trial
+            .play_san(text)
+            .or_else(|san_err| match trial.play_uci(text) {
+                Err(MoveError::InvalidNotation) => Err(san_err),
+                result => result,
+            });
+        match mov {
+            Ok(mov) => {
+                let san = self.numbered(&self.game.move_to_san(mov));
+                self.play(mov, elapsed);
+                if !matches!(self.end, Some(End::Time(_))) {
+                    println!("you play {}", san);
+                }
+            }
+            Err(err) => println!("{}: {}, type help for the commands", text, err),
+        }
+    }
+
+    /// takes back moves until it is the user's turn after one of their moves
+    fn undo(&mut self) {
+        // the engine has replied to the user's last move, unless that ended the game
+        let plies = if self.game.state().player == self.user {
+            2
+        } else {
+            1
+        };
+        if self.game.moves().count() < plies {
+            println!("there is no move of yours to take back");
+            return;
+        }
+        for _ in 0..plies {
+            self.game.undo();
+            self.clocks = self.clock_history.pop().expect("a clock per move");
+        }
+        self.end = None;
+        self.print_board();
+    }
+
+    fn hint(&mut self) {
+        let (mov, score, depth) = self.search(TimeControl::MoveTime(HINT_TIME), Skill::default());
+        println!(
+            "hint: {} (depth {}, {:+.2})",
+            self.game.move_to_san(mov),
+            depth,
+            score
+        );
+    }
+
+    fn pgn(&self) -> String {
+        let start = self.game.start_state();
+        let names = |player| {
+            if player == self.user {
+                "You".to_owned()
+            } else {
+                format!("{} (level {})", ENGINE_NAME, self.options.level)
+            }
+        };
+        let result = self.end.as_ref().map_or("*", End::pgn_result);
+        let mut pgn = format!(
+            "[Event \"Casual game\"]\n[Site \"?\"]\n[Date \"{}\"]\n[Round \"-\"]\n\
+             [White \"{}\"]\n[Black \"{}\"]\n[Result \"{}\"]\n[TimeControl \"{}+{}\"]\n",
+            pgn_date(),
+            names(Player::White),
+            names(Player::Black),
+            result,
+            self.options.time.as_secs(),
+            self.options.increment.as_secs()
+        );
+        if start.variant != Variant::Standard {
+            pgn += &format!("[Variant \"{}\"]\n", start.variant.name());
+        }
+        let initial = Game::new_variant(start.variant, &self.engine.zoborist_state);
+        if start.hash != initial.state().hash {
+            pgn += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", start.to_fen());
+        }
+        if let Some(end) = &self.end {
+            pgn += &format!("[Termination \"{}\"]\n", end.description());
+        }
+        pgn.push('\n');
+
+        let first_player = start.player;
+        let mut game = Game::from_state(start, &self.engine.zoborist_state);
+        let mut tokens = vec![];
+        for (i, mov) in self.game.moves().enumerate() {
+            let number = (i + first_player as usize) / 2 + 1;
+            if game.state().player == Player::White {
+                tokens.push(format!("{}.", number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", number));
+            }
+            tokens.push(game.move_to_san(mov));
+            game.play(mov).expect("the game's own moves are legal");
+        }
+        tokens.push(result.to_owned());
+        // movetext lines are at most 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        pgn += &line;
+        pgn.push('\n');
+        pgn
+    }
+
+    fn save(&self, path: &str) {
+        match fs::write(path, self.pgn()) {
+            Ok(()) => println!("saved the game to {}", path),
+            Err(err) => println!("cannot save the game to {}: {}", path, err),
+        }
+    }
+}
+
+/// today's date as PGN writes it, in UTC
+fn pgn_date() -> String {
+    let Ok(since_epoch) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) else {
+        return "????.??.??".to_owned();
+    };
+    // days to civil date, after Howard Hinnant's algorithm
+    let days = (since_epoch.as_secs() / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{}.{:02}.{:02}", year, month, day)
+}
+
+fn print_help() {
+    println!("enter moves in SAN (Nf3, exd5, O-O, e8=Q) or long algebraic notation (g1f3)");
+    println!("undo     take back your last move and the engine's reply");
+    println!("hint     suggest a move");
+    println!("resign   give up the game");
+    println!(
+        "save [f] write the game as PGN, to {} by default",
+        DEFAULT_PGN_PATH
+    );
+    println!("board    draw the board again");
+    println!("quit     leave");
+}
+
+/// plays from `game` until the user quits
+pub fn run(engine: ChessEngine, game: Game, options: PlayOptions) {
+    let user = options.color.unwrap_or_else(|| {
+        // any bit of the clock will do for a coin toss
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        if nanos.is_multiple_of(2) {
+            Player::White
+        } else {
+            Player::Black
+        }
+    });
+    let mut game_match = Match {
+        engine,
+        end: game.outcome().map(End::Outcome),
+        game,
+        user,
+        skill: Skill::from_level(options.level),
+        clocks: [options.time; 2],
+        clock_history: vec![],
+        options,
+    };
+    println!(
+        "you play {:?} against {} at level {}, type help for the commands",
+        user, ENGINE_NAME, game_match.options.level
+    );
+    game_match.print_board();
+
+    let mut lines = io::stdin().lock().lines();
+    let mut announced = false;
+    // the user's clock runs from the engine's move, through hints and other commands
+    let mut turn_start = None;
+    loop {
+        if game_match.end.is_none() && game_match.game.state().player != user {
+            game_match.engine_move();
+        }
+        match &game_match.end {
+            Some(end) if !announced => {
+                println!("{} {}", end.pgn_result(), end.description());
+                println!("save the game, undo a move or quit");
+                announced = true;
+            }
+            Some(_) => {}
+            None => announced = false,
+        }
+
+        print!("> ");
+        let _ = io::stdout().flush();
+        let start = *turn_start.get_or_insert_with(Instant::now);
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let moves_before = game_match.game.moves().count();
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "" => {}
+            "help" => print_help(),
+            "quit" | "exit" => break,
+            "board" | "d" => game_match.print_board(),
+            "undo" => game_match.undo(),
+            "save" => game_match.save(match args.trim() {
+                "" => DEFAULT_PGN_PATH,
+                path => path,
+            }),
+            "hint" if game_match.end.is_none() => game_match.hint(),
+            "resign" if game_match.end.is_none() => game_match.end = Some(End::Resigned(user)),
+            "hint" | "resign" => println!("the game is over"),
+            _ => game_match.user_move(line, start.elapsed()),
+        }
+        if game_match.game.moves().count() != moves_before {
+            turn_start = None;
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! the json endpoints. positions are given as an optional fen (the start position without
+//! one), an optional variant name and moves in long algebraic notation played from there:
+//!
+//! - `GET /status`: the number of engines, and how many are idle
+//! - `POST /position`: plays the moves, rejecting the first one that is not legal, and
+//!   returns the fen, the legal moves and whether the game is over
+//! - `POST /perft`: leaf nodes below each legal move, to `depth` (at most 6). a perft that
+//!   takes more than 30 seconds is stopped and answered with status 422.
+//! - `POST /eval`: the static evaluation, term by term
+//! - `POST /analyse`: searches to `depth` or for `movetime_ms`, with `multi_pv` lines. a
+//!   search stops after 10 seconds without `movetime_ms`, and after a minute with it. with
+//!   `"stream": true` or `Accept: text/event-stream`, each finished depth is sent as an
+//!   `iteration` event before the final `done` event.
+//!
+//! scores are in centipawns from the side to move's point of view, except in the
+//! evaluation trace, which is from white's.
+
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use chess_engine_core::{
+    ChessEngine, EvalTrace, Game, GameState, Iteration, Move, Player, SearchLimits, SearchObserver,
+    TimeControl, TimeManager, Variant, ZoboristState, MAX_SEARCH_DEPTH, PHASE_MAX, VARIANT_NAMES,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    http::{self, HttpError, Request},
+    pool::EnginePool,
+};
+
+/// depth of an analysis without limits
+const DEFAULT_DEPTH: usize = 10;
+/// time limit of an analysis without `movetime_ms`, so a deep search can't hold an engine
+const DEFAULT_MOVETIME_MS: u64 = 10_000;
+const MAX_MOVETIME_MS: u64 = 60_000;
+/// perft grows about thirtyfold per depth, and depth 6 takes minutes from busy middlegame
+/// positions, so it is stopped after `PERFT_TIME_LIMIT_MS` as well
+const MAX_PERFT_DEPTH: usize = 6;
+const PERFT_TIME_LIMIT_MS: u64 = 30_000;
+
+pub struct Server {
+    pub pool: EnginePool,
+    // the engines' hashing, for positions handled without an engine
+    pub zoborist_state: ZoboristState,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Position {
+    fen: Option<String>,
+    variant: Option<String>,
+    moves: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PerftRequest {
+    #[serde(flatten)]
+    position: Position,
+    depth: usize,
+}
+
+#[derive(Deserialize)]
+struct AnalyseRequest {
+    #[serde(flatten)]
+    position: Position,
+    depth: Option<usize>,
+    movetime_ms: Option<u64>,
+    multi_pv: Option<usize>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct Status {
+    engines: usize,
+    idle: usize,
+}
+
+#[derive(Serialize)]
+struct PositionInfo {
+    fen: String,
+    variant: &'static str,
+    side_to_move: &'static str,
+    in_check: bool,
+    /// `ongoing`, `checkmate`, `stalemate` or `variant_loss` when the side to move has lost
+    /// by a rule of the variant
+    status: &'static str,
+    legal_moves: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PerftMove {
+    #[serde(rename = "move")]
+    mov: String,
+    nodes: i64,
+}
+
+#[derive(Serialize)]
+struct Perft {
+    depth: usize,
+    nodes: i64,
+    moves: Vec<PerftMove>,
+}
+
+#[derive(Serialize, Clone)]
+struct Line {
+    score_cp: i32,
+    pv: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct Analysis {
+    depth: usize,
+    nodes: u64,
+    time_ms: u128,
+    /// none without legal moves
+    best_move: Option<String>,
+    lines: Vec<Line>,
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, HttpError> {
+    let body = if body.is_empty() { &b"{}"[..] } else { body };
+    serde_json::from_slice(body)
+        .map_err(|err| HttpError::bad_request(format!("invalid json: {}", err)))
+}
+
+/// the game the position describes, with its moves played
+fn game(position: &Position, zoborist_state: &ZoboristState) -> Result<Game, HttpError> {
+    let variant = match &position.variant {
+        None => Variant::Standard,
+        Some(name) => Variant::from_name(name).ok_or_else(|| {
+            HttpError::bad_request(format!(
+                "unknown variant {}, expected one of {}",
+                name,
+                VARIANT_NAMES.join(", ")
+            ))
+        })?,
+    };
+    let mut game = match &position.fen {
+        None => Game::new_variant(variant, zoborist_state),
+        Some(fen) => Game::from_fen_with_variant(fen, variant, zoborist_state)
+            .map_err(|err| HttpError::bad_request(format!("invalid fen: {}", err)))?,
+    };
+    for (i, text) in position.moves.iter().enumerate() {
+        game.play_uci(text)
+            .map_err(|err| HttpError::bad_request(format!("move {} ({}): {}", i + 1, text, err)))?;
+    }
+    Ok(game)
+}
+
+fn position_info(state: &GameState) -> PositionInfo {
+    let legal_moves = state.legal_moves();
+    let status = if state.is_variant_loss() {
+        "variant_loss"
+    } else if !legal_moves.is_empty() {
+        "ongoing"
+    } else if state.is_in_check() {
+        "checkmate"
+    } else {
+        "stalemate"
+    };
+    PositionInfo {
+        fen: state.to_fen(),
+        variant: state.variant.name(),
+        side_to_move: match state.player {
+            Player::White => "white",
+            Player::Black => "black",
+        },
+        in_check: state.is_in_check(),
+        status,
+        legal_moves: legal_moves
+            .into_iter()
+            .map(|mov| state.move_to_uci(mov))
+            .collect(),
+    }
+}
+
+fn perft(server: &Server, request: PerftRequest) -> Result<Perft, HttpError> {
+    let mut engine = server.pool.get();
+    let game = game(&request.position, &engine.zoborist_state)?;
+    engine.set_variant(game.state().variant);
+    let state = game.state();
+    let depth = request.depth.min(MAX_PERFT_DEPTH);
+    let stop_signal = engine.stop_signal();
+    stop_signal.reset();
+    stop_signal.set_deadline(Some(Instant::now() + Duration::from_millis(PERFT_TIME_LIMIT_MS)));
+    let divide = engine.perft_divide(state, depth).ok_or_else(|| {
+        HttpError::new(
+            422,
+            format!(
+                "perft to depth {} did not finish in {} seconds",
+                depth,
+                PERFT_TIME_LIMIT_MS / 1000
+            ),
+        )
+    })?;
+    Ok(Perft {
+        depth,
+        nodes: divide.iter().map(|&(_, nodes)| nodes).sum(),
+        moves: divide
+            .into_iter()
+            .map(|(mov, nodes)| PerftMove {
+                mov: state.move_to_uci(mov),
+                nodes,
+            })
+            .collect(),
+    })
+}
+
+fn eval(server: &Server, position: Position) -> Result<EvalTrace, HttpError> {
+    let engine = server.pool.get();
+    let game = game(&position, &engine.zoborist_state)?;
+    Ok(engine.evaluator().evaluate_with_trace(game.state()))
+}
+
+/// keeps the last finished depth, and streams it when the client asked for events
+struct Progress<'a> {
+    state: GameState,
+    search_start: Instant,
+    nodes_at_start: u64,
+    events: Option<&'a mut dyn Write>,
+    last: Option<Analysis>,
+}
+
+impl Progress<'_> {
+    fn line(&self, engine: &ChessEngine, score: i32, pv: &[Move]) -> Line {
+        let mut state = self.state.clone();
+        let pv = pv
+            .iter()
+            .map(|&mov| {
+                let text = state.move_to_uci(mov);
+                state.make_move(mov, &engine.zoborist_state);
+                text
+            })
+            .collect();
+        let score_cp = match self.state.player {
+            Player::White => score / PHASE_MAX,
+            Player::Black => -score / PHASE_MAX,
+        };
+        Line { score_cp, pv }
+    }
+
+    fn analysis(&self, engine: &ChessEngine, depth: usize, lines: Vec<Line>) -> Analysis {
+        Analysis {
+            depth,
+            nodes: engine.nodes() - self.nodes_at_start,
+            time_ms: self.search_start.elapsed().as_millis(),
+            best_move: lines.first().and_then(|line| line.pv.first().cloned()),
+            lines,
+        }
+    }
+}
+
+impl SearchObserver for Progress<'_> {
+    fn iteration_finished(&mut self, engine: &ChessEngine, iteration: &Iteration) {
+        let lines = iteration
+            .lines
+            .iter()
+            .map(|(score, pv)| self.line(engine, *score, pv))
+            .collect();
+        let analysis = self.analysis(engine, iteration.depth, lines);
+        if let Some(events) = &mut self.events {
+            // nobody is listening any more
+            if http::write_event(events, "iteration", &analysis).is_err() {
+                self.events = None;
+                engine.stop_signal().stop();
+            }
+        }
+        self.last = Some(analysis);
+    }
+}
+
+/// runs the analysis, sending progress to `events` if given
+fn analyse(
+    server: &Server,
+    request: &AnalyseRequest,
+    events: Option<&mut dyn Write>,
+) -> Result<Analysis, HttpError> {
+    let mut engine = server.pool.get();
+    let game = game(&request.position, &engine.zoborist_state)?;
+    engine.set_variant(game.state().variant);
+    let state = game.state().clone();
+    let limits = SearchLimits {
+        depth: match (request.depth, request.movetime_ms) {
+            (Some(depth), _) => depth.clamp(1, MAX_SEARCH_DEPTH),
+            (None, Some(_)) => MAX_SEARCH_DEPTH,
+            (None, None) => DEFAULT_DEPTH,
+        },
+        multi_pv: request.multi_pv.unwrap_or(1),
+        ..SearchLimits::default()
+    };
+    let movetime_ms = request
+        .movetime_ms
+        .unwrap_or(DEFAULT_MOVETIME_MS)
+        .min(MAX_MOVETIME_MS);
+    let time_control = TimeControl::MoveTime(Duration::from_millis(movetime_ms));
+    let time_manager = TimeManager::new(Some(time_control), Duration::ZERO, false);
+    engine.set_game(&game);
+    let stop_signal = engine.stop_signal();
+    stop_signal.reset();
+    stop_signal.set_deadline(time_manager.deadline());
+
+    let mut progress = Progress {
+        state: state.clone(),
+        search_start: Instant::now(),
+        nodes_at_start: engine.nodes(),
+        events,
+        last: None,
+    };
+    let result = engine.iterative_search(&state, &limits, &time_manager, &mut progress);
+    let Some((score, pv)) = result else {
+        return Ok(progress.analysis(&engine, 0, vec![]));
+    };
+    // stopped before the first depth was done, so only the move is known
+    let mut analysis = progress.last.clone().unwrap_or_else(|| {
+        let line = progress.line(&engine, score, &pv);
+        progress.analysis(&engine, 0, vec![line])
+    });
+    analysis.nodes = engine.nodes() - progress.nodes_at_start;
+    analysis.time_ms = progress.search_start.elapsed().as_millis();
+    Ok(analysis)
+}
+
+fn respond<T: Serialize>(stream: &mut impl Write, result: Result<T, HttpError>) -> io::Result<()> {
+    match result {
+        Ok(body) => http::write_json(stream, 200, &body),
+        Err(err) => http::write_error(stream, &err),
+    }
+}
+
+fn analyse_with_events(
+    server: &Server,
+    request: &AnalyseRequest,
+    stream: &mut impl Write,
+) -> io::Result<()> {
+    // a bad position is answered as for any other request
+    game(&request.position, &server.zoborist_state).map_err(|err| {
+        let _ = http::write_error(stream, &err);
+        io::Error::new(io::ErrorKind::InvalidInput, err.message)
+    })?;
+    http::start_events(stream)?;
+    match analyse(server, request, Some(&mut *stream)) {
+        Ok(analysis) => http::write_event(stream, "done", &analysis),
+        Err(err) => http::write_event(stream, "error", &err.message),
+    }
+}
+
+/// answers `request` on `stream`
+pub fn handle(server: &Server, request: Request, stream: &mut impl Write) -> io::Result<()> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => respond(
+            stream,
+            Ok(Status {
+                engines: server.pool.size(),
+                idle: server.pool.idle(),
+            }),
+        ),
+        ("POST", "/position") => respond(
+            stream,
+            parse(&request.body)
+                .and_then(|position| game(&position, &server.zoborist_state))
+                .map(|game| position_info(game.state())),
+        ),
+        ("POST", "/perft") => respond(
+            stream,
+            parse(&request.body).and_then(|request| perft(server, request)),
+        ),
+        ("POST", "/eval") => respond(
+            stream,
+            parse(&request.body).and_then(|position| eval(server, position)),
+        ),
+        ("POST", "/analyse") => match parse::<AnalyseRequest>(&request.body) {
+            Ok(analyse_request) if analyse_request.stream || request.accepts_events => {
+                analyse_with_events(server, &analyse_request, stream)
+            }
+            Ok(analyse_request) => respond(stream, analyse(server, &analyse_request, None)),
+            Err(err) => http::write_error(stream, &err),
+        },
+        (_, "/status" | "/position" | "/perft" | "/eval" | "/analyse") => {
+            http::write_error(stream, &HttpError::new(405, "method not allowed"))
+        }
+        _ => http::write_error(stream, &HttpError::new(404, "not found")),
+    }
+}
@@ -0,0 +1,151 @@
+//! just enough HTTP/1.1 for a local client: one request per connection, bodies sized by
+//! `Content-Length`, and responses that either carry a json body or stream server-sent
+//! events until the connection is closed.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use serde::Serialize;
+
+// requests are a fen, a few moves and some limits
+const MAX_BODY: usize = 1 << 20;
+const MAX_HEADER_LINES: usize = 100;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// the client asked for `text/event-stream`
+    pub accepts_events: bool,
+    pub body: Vec<u8>,
+}
+
+/// why a request could not be read, as a status and a message for the client
+pub struct HttpError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl HttpError {
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(400, message)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, HttpError> {
+    let mut line = String::new();
+    // a header line can't be longer than the largest body
+    match reader.take(MAX_BODY as u64).read_line(&mut line) {
+        Ok(0) => Err(HttpError::bad_request("unexpected end of request")),
+        Ok(_) => Ok(line.trim_end_matches(['\r', '\n']).to_owned()),
+        Err(_) => Err(HttpError::bad_request("the request is not valid utf-8")),
+    }
+}
+
+pub fn read_request(stream: impl Read) -> Result<Request, HttpError> {
+    let mut reader = BufReader::new(stream);
+    let request_line = read_line(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(HttpError::bad_request("invalid request line"));
+    };
+    // the query string is not used
+    let path = target.split('?').next().unwrap_or(target);
+
+    let mut content_length = 0;
+    let mut accepts_events = false;
+    for _ in 0..MAX_HEADER_LINES {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            if content_length > MAX_BODY {
+                return Err(HttpError::new(413, "the request body is too large"));
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).map_err(|_| {
+                HttpError::bad_request("the request body is shorter than its length")
+            })?;
+            return Ok(Request {
+                method: method.to_owned(),
+                path: path.to_owned(),
+                accepts_events,
+                body,
+            });
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(HttpError::bad_request("invalid header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| HttpError::bad_request("invalid content length"))?;
+        } else if name.eq_ignore_ascii_case("accept") {
+            accepts_events = value.contains("text/event-stream");
+        }
+    }
+    Err(HttpError::new(431, "too many headers"))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+pub fn write_json(stream: &mut impl Write, status: u16, body: &impl Serialize) -> io::Result<()> {
+    let body = serde_json::to_vec(body)?;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+pub fn write_error(stream: &mut impl Write, error: &HttpError) -> io::Result<()> {
+    write_json(
+        stream,
+        error.status,
+        &ErrorBody {
+            error: &error.message,
+        },
+    )
+}
+
+/// starts a response of server-sent events, which ends when the connection is closed
+pub fn start_events(stream: &mut impl Write) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n"
+    )?;
+    stream.flush()
+}
+
+/// one event with a json payload. fails once the client has gone away.
+pub fn write_event(stream: &mut impl Write, event: &str, data: &impl Serialize) -> io::Result<()> {
+    let data = serde_json::to_string(data)?;
+    write!(stream, "event: {}\ndata: {}\n\n", event, data)?;
+    stream.flush()
+}
@@ -0,0 +1,96 @@
+//! analyses over http with json, for a dashboard that should not start an engine process
+//! per request, e.g.
+//!
+//! `chess-engine-server --port 8080 --engines 4 --hash 64 --evaluator pesto`
+//!
+//! listens on localhost only, unless `--bind` says otherwise. see `api` for the endpoints.
+
+mod api;
+mod http;
+mod pool;
+
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    process,
+    sync::Arc,
+    thread,
+};
+
+use chess_engine_core::{evaluator_from_name, ChessEngine, EVALUATOR_NAMES};
+
+use crate::{api::Server, pool::EnginePool};
+
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_ENGINES: usize = 2;
+const DEFAULT_HASH_MB: usize = 64;
+
+/// the value after `--name` on the command line
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+fn parsed_arg<T: std::str::FromStr>(args: &[String], name: &str, default: T) -> T {
+    match arg_value(args, name) {
+        None => default,
+        Some(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid value for {}: {}", name, value)),
+    }
+}
+
+fn serve(server: &Server, mut stream: TcpStream) {
+    let result = match http::read_request(&stream) {
+        Ok(request) => api::handle(server, request, &mut stream),
+        Err(err) => http::write_error(&mut stream, &err),
+    };
+    // the client is gone, there is nobody to tell
+    let _ = result.and_then(|()| stream.flush());
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let bind = arg_value(&args, "--bind").unwrap_or_else(|| "127.0.0.1".to_owned());
+    let port = parsed_arg(&args, "--port", DEFAULT_PORT);
+    let engines = parsed_arg(&args, "--engines", DEFAULT_ENGINES).max(1);
+    let hash_mb = parsed_arg(&args, "--hash", DEFAULT_HASH_MB).max(1);
+    let evaluator = arg_value(&args, "--evaluator").unwrap_or_else(|| "pesto".to_owned());
+    if evaluator_from_name(&evaluator).is_none() {
+        eprintln!(
+            "unknown evaluator {}, expected one of {}",
+            evaluator,
+            EVALUATOR_NAMES.join(", ")
+        );
+        process::exit(1);
+    }
+
+    let engines: Vec<ChessEngine> = (0..engines)
+        .map(|_| {
+            let evaluator = evaluator_from_name(&evaluator).expect("checked above");
+            let mut engine = ChessEngine::with_evaluator(16, 40, 13, evaluator);
+            // the search's progress goes out as events, not on stdout
+            engine.set_debug_output(false);
+            if let Err(err) = engine.set_hash_size(hash_mb) {
+                eprintln!("cannot allocate {} MB of hash per engine: {}", hash_mb, err);
+                process::exit(1);
+            }
+            engine
+        })
+        .collect();
+    let server = Arc::new(Server {
+        zoborist_state: engines[0].zoborist_state.clone(),
+        pool: EnginePool::new(engines),
+    });
+
+    let listener = TcpListener::bind((bind.as_str(), port))
+        .unwrap_or_else(|err| panic!("cannot listen on {}:{}: {}", bind, port, err));
+    // with `--port 0` this is where to find the server
+    println!("listening on {}", listener.local_addr().unwrap());
+    for stream in listener.incoming().flatten() {
+        let server = server.clone();
+        thread::spawn(move || serve(&server, stream));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use chess_engine_core::{ChessEngine, Variant};
+
+/// engines shared by the requests, each used by one request at a time. a request waits
+/// for an engine when all of them are busy.
+pub struct EnginePool {
+    // with the variant each engine last searched
+    idle: Mutex<Vec<(ChessEngine, Variant)>>,
+    returned: Condvar,
+    size: usize,
+}
+
+/// an engine taken from the pool, which gets it back when this is dropped
+pub struct PooledEngine<'a> {
+    pool: &'a EnginePool,
+    engine: Option<ChessEngine>,
+    variant: Variant,
+}
+
+impl EnginePool {
+    pub fn new(engines: Vec<ChessEngine>) -> Self {
+        Self {
+            size: engines.len(),
+            idle: Mutex::new(
+                engines
+                    .into_iter()
+                    .map(|engine| (engine, Variant::Standard))
+                    .collect(),
+            ),
+            returned: Condvar::new(),
+        }
+    }
+
+    // a request that panicked leaves the engines usable
+    fn lock(&self) -> MutexGuard<'_, Vec<(ChessEngine, Variant)>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self) -> PooledEngine<'_> {
+        let mut idle = self.lock();
+        loop {
+            if let Some((engine, variant)) = idle.pop() {
+                return PooledEngine {
+                    pool: self,
+                    engine: Some(engine),
+                    variant,
+                };
+            }
+            idle = self
+                .returned
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+impl PooledEngine<'_> {
+    /// positions hash the same in every variant, so the tables left by a request in
+    /// another variant are cleared before they can be read
+    pub fn set_variant(&mut self, variant: Variant) {
+        if variant != self.variant {
+            self.variant = variant;
+            self.engine.as_mut().unwrap().new_game();
+        }
+    }
+}
+
+impl Deref for PooledEngine<'_> {
+    type Target = ChessEngine;
+
+    fn deref(&self) -> &ChessEngine {
+        self.engine.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledEngine<'_> {
+    fn deref_mut(&mut self) -> &mut ChessEngine {
+        self.engine.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledEngine<'_> {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            self.pool.lock().push((engine, self.variant));
+            self.pool.returned.notify_one();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    thread,
+};
+
+struct Server {
+    process: Child,
+    address: String,
+}
+
+impl Server {
+    fn start() -> Self {
+        Self::with_engines(2)
+    }
+
+    fn with_engines(engines: usize) -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_chess-engine-server"))
+            .args(["--port", "0", "--engines", &engines.to_string(), "--hash", "1"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let address = line
+            .trim()
+            .strip_prefix("listening on ")
+            .unwrap()
+            .to_owned();
+        // nothing else should be written to stdout, but it must not block the server
+        thread::spawn(move || io::copy(&mut stdout, &mut io::sink()));
+        Self { process, address }
+    }
+
+    /// the status and body of the response
+    fn request(&self, method: &str, path: &str, headers: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n{}\r\n{}",
+            method,
+            path,
+            body.len(),
+            headers,
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, body.to_owned())
+    }
+
+    fn post(&self, path: &str, body: &str) -> (u16, serde_json::Value) {
+        let (status, body) = self.request("POST", path, "", body);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[test]
+fn plays_and_validates_moves() {
+    let server = Server::start();
+    let (status, position) = server.post(
+        "/position",
+        r#"{"moves": ["f2f3", "e7e5", "g2g4", "d8h4"]}"#,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(position["status"], "checkmate");
+    assert_eq!(position["side_to_move"], "white");
+
+    let (status, position) = server.post("/position", "");
+    assert_eq!(status, 200);
+    assert_eq!(position["legal_moves"].as_array().unwrap().len(), 20);
+
+    let (status, error) = server.post("/position", r#"{"moves": ["e2e4", "e4e5"]}"#);
+    assert_eq!(status, 400);
+    assert!(
+        error["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("move 2 (e4e5)"),
+        "{}",
+        error
+    );
+
+    let (status, _) = server.post("/position", r#"{"fen": "8/8/8/8/8/8/8/8 w - - 0 1"}"#);
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn counts_perft_and_traces_the_evaluation() {
+    let server = Server::start();
+    let (status, perft) = server.post(
+        "/perft",
+        r#"{"fen": "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", "depth": 3}"#,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(perft["nodes"], 97862);
+    assert_eq!(perft["moves"].as_array().unwrap().len(), 48);
+
+    // deeper requests are cut down to a depth that finishes
+    let (status, perft) = server.post("/perft", r#"{"fen": "7k/8/8/8/8/8/8/K7 w - - 0 1", "depth": 50}"#);
+    assert_eq!(status, 200);
+    assert_eq!(perft["depth"], 6);
+}
+
+#[test]
+fn switching_variants_forgets_the_cached_counts() {
+    // one engine, so both requests share its tables
+    let server = Server::with_engines(1);
+    let fen = "4k3/8/8/8/8/4K3/8/8 w - - 0 1";
+    let (_, standard) = server.post("/perft", &format!(r#"{{"fen": "{}", "depth": 4}}"#, fen));
+    let (status, koth) = server.post(
+        "/perft",
+        &format!(r#"{{"fen": "{}", "variant": "kingofthehill", "depth": 4}}"#, fen),
+    );
+    assert_eq!(status, 200);
+    assert_ne!(standard["nodes"], koth["nodes"]);
+    assert_eq!(koth["nodes"], 1453);
+
+    let (status, trace) = server.post("/eval", "{}");
+    assert_eq!(status, 200);
+    assert_eq!(trace["score"], 0);
+}
+
+#[test]
+fn analyses_with_and_without_events() {
+    let server = Server::start();
+    let (status, analysis) = server.post("/analyse", r#"{"depth": 4, "multi_pv": 3}"#);
+    assert_eq!(status, 200);
+    assert_eq!(analysis["depth"], 4);
+    assert_eq!(analysis["lines"].as_array().unwrap().len(), 3);
+
+    let (status, events) = server.request(
+        "POST",
+        "/analyse",
+        "Accept: text/event-stream\r\n",
+        r#"{"moves": ["e2e4"], "depth": 4}"#,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(events.matches("event: iteration").count(), 2, "{}", events);
+    assert!(events.contains("event: done"), "{}", events);
+
+    let (status, analysis) = server.post(
+        "/analyse",
+        r#"{"fen": "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", "movetime_ms": 100}"#,
+    );
+    assert_eq!(status, 200);
+    assert!(analysis["best_move"].is_null());
+}
+
+#[test]
+fn rejects_unknown_requests() {
+    let server = Server::start();
+    assert_eq!(server.request("GET", "/nothing", "", "").0, 404);
+    assert_eq!(server.request("GET", "/analyse", "", "").0, 405);
+    assert_eq!(server.post("/analyse", "{\"depth\": ").0, 400);
+    assert_eq!(server.request("GET", "/status", "", "").0, 200);
+}
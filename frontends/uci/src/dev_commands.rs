@@ -0,0 +1,133 @@
+//! commands for working on the engine, outside the uci protocol:
+//!
+//! - `d [unicode] [flip]` draws the board, with the fen and hash
+//! - `eval` shows how the evaluation adds up
+//! - `flip` swaps the colours of the position
+//! - `moves` lists the legal moves
+//! - `go perft <depth>` counts the leaf nodes below each legal move
+//! - `bench [depth]` searches a fixed set of positions and prints the nodes searched, which
+//!   changes only when the search does
+
+use std::time::Instant;
+
+use chess_engine_core::{ChessEngine, GameState, MAX_SEARCH_DEPTH};
+
+use crate::{info_string, move_engine_to_uci};
+
+const BENCH_DEPTH: usize = 8;
+
+const BENCH_FENS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+];
+
+/// runs `line` if it is one of the commands above. false if it is not.
+pub fn run(
+    line: &str,
+    engine: &mut ChessEngine,
+    game_state: &mut GameState,
+    chess960: bool,
+) -> bool {
+    let tokens: Vec<String> = line.split_whitespace().map(str::to_lowercase).collect();
+    let args: Vec<&str> = tokens.iter().skip(1).map(String::as_str).collect();
+    match tokens.first().map(String::as_str) {
+        Some("d") => println!(
+            "{}",
+            game_state.diagram(args.contains(&"unicode"), args.contains(&"flip"))
+        ),
+        Some("eval") if args.is_empty() => {
+            println!("{}", engine.evaluator().evaluate_with_trace(game_state))
+        }
+        Some("flip") if args.is_empty() => match game_state.flipped(&engine.zoborist_state) {
+            Ok(flipped) => {
+                *game_state = flipped;
+                // the positions played before belong to the unflipped game
+                engine.clear_move_history_threefold_repetition();
+            }
+            Err(err) => info_string(format!("cannot flip the position: {}", err)),
+        },
+        Some("moves") if args.is_empty() => {
+            let moves: Vec<String> = game_state
+                .legal_moves()
+                .into_iter()
+                .map(|mov| move_engine_to_uci(game_state, mov, chess960).to_string())
+                .collect();
+            println!("{} legal moves: {}", moves.len(), moves.join(" "));
+        }
+        Some("go") if args.first() == Some(&"perft") => {
+            match args.get(1).map(|depth| depth.parse()) {
+                Some(Ok(depth)) if args.len() == 2 => perft(engine, game_state, depth, chess960),
+                _ => info_string("go perft expects a depth".to_owned()),
+            }
+        }
+        Some("bench") => match args.first().map(|depth| depth.parse()) {
+            None => bench(engine, BENCH_DEPTH),
+            Some(Ok(depth)) if args.len() == 1 => bench(engine, depth),
+            _ => info_string("bench expects a depth or nothing".to_owned()),
+        },
+        _ => return false,
+    }
+    true
+}
+
+fn perft(engine: &mut ChessEngine, game_state: &GameState, depth: usize, chess960: bool) {
+    let depth = depth.min(MAX_SEARCH_DEPTH);
+    let start = Instant::now();
+    // a stop sent while no search was running would end it right away
+    engine.stop_signal().reset();
+    let Some(divide) = engine.perft_divide(game_state, depth) else {
+        info_string("perft stopped".to_owned());
+        return;
+    };
+    let elapsed = start.elapsed();
+    for &(mov, count) in &divide {
+        println!(
+            "{}: {}",
+            move_engine_to_uci(game_state, mov, chess960),
+            count
+        );
+    }
+    let nodes: i64 = divide.iter().map(|&(_, count)| count).sum();
+    println!();
+    println!("nodes searched: {}", nodes);
+    info_string(format!(
+        "perft depth {}, {} ms, {} nps",
+        depth,
+        elapsed.as_millis(),
+        (nodes as f64 / elapsed.as_secs_f64().max(1e-6)) as u64
+    ));
+}
+
+/// the node count depends on the hash size, seed and quiescence depth, so signatures are
+/// only comparable with the same options. the engine is reset as for a new game before and
+/// after, and the current position is kept.
+fn bench(engine: &mut ChessEngine, depth: usize) {
+    let depth = depth.clamp(1, MAX_SEARCH_DEPTH);
+    engine.new_game();
+    engine.stop_signal().reset();
+    let start = Instant::now();
+    let nodes_before = engine.nodes();
+    for fen in BENCH_FENS {
+        let state =
+            GameState::new_from_fen(fen, &engine.zoborist_state).expect("bench fens are valid");
+        for i in ((2 - depth % 2)..=depth).step_by(2) {
+            engine.solve(&state, i);
+        }
+    }
+    let nodes = engine.nodes() - nodes_before;
+    let elapsed = start.elapsed();
+    engine.new_game();
+    println!("positions: {}, depth: {}", BENCH_FENS.len(), depth);
+    println!("time: {} ms", elapsed.as_millis());
+    println!("nodes searched: {}", nodes);
+    println!(
+        "nps: {}",
+        (nodes as f64 / elapsed.as_secs_f64().max(1e-6)) as u64
+    );
+}
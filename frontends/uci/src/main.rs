@@ -1,13 +1,22 @@
+mod dev_commands;
+mod options;
+
 use chess_engine_core::{
-    canonical_to_pos, pos_to_coord, ChessEngine, GameState, Move, Piece, Player,
+    evaluator_from_name, Book, BookSelection, ChessEngine, Evaluator, GameState, Iteration, Move,
+    Piece, Player, SearchLimits, SearchObserver, Skill, Square, StopSignal, TimeControl,
+    TimeManager, Variant, EVALUATOR_NAMES, MAX_SEARCH_DEPTH, PHASE_MAX,
 };
+use options::Options;
 use std::{
     io::{self, BufRead, Write},
-    time::SystemTime,
+    panic,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 use vampirc_uci::{
-    parse_one, UciInfoAttribute, UciMessage, UciMove, UciOptionConfig, UciPiece, UciSearchControl,
-    UciSquare,
+    parse_one, UciInfoAttribute, UciMessage, UciMove, UciPiece, UciSearchControl, UciSquare,
+    UciTimeControl,
 };
 
 fn log_unnormalized_message(message: &UciMessage) {
@@ -53,26 +62,31 @@ fn piece_engine_to_uci(piece: Piece) -> UciPiece {
 }
 
 fn pos_engine_to_uci(pos: u8) -> UciSquare {
-    let tup = pos_to_coord(pos);
+    let square = Square::from_pos(pos).expect("engine positions are on the board");
     UciSquare {
-        file: (7 - tup.1 + 'a' as i8) as u8 as char,
-        rank: tup.0 as u8 + 1,
+        file: (b'a' + square.file()) as char,
+        rank: square.rank() + 1,
     }
 }
 
-fn move_engine_to_uci(player: Player, mov: Move) -> UciMove {
+fn square_uci_to_engine(square: UciSquare) -> Option<Square> {
+    Square::new((square.file as u8).wrapping_sub(b'a'), square.rank.wrapping_sub(1))
+}
+
+/// with `chess960` set, castling is written as the king taking its own rook
+fn move_engine_to_uci(state: &GameState, mov: Move, chess960: bool) -> UciMove {
+    let player = state.player;
     let (prev_pos, new_pos, promotion) = match mov {
         Move::Move {
             prev_pos, new_pos, ..
         } => (prev_pos, new_pos, None),
         Move::Castle { is_short } => {
-            let (prev_pos, new_pos) = match (player, is_short) {
-                (Player::White, true) => (3, 1),
-                (Player::White, false) => (3, 5),
-                (Player::Black, true) => (59, 57),
-                (Player::Black, false) => (59, 61),
-            };
-            (prev_pos, new_pos, None)
+            let (king_from, king_to, rook_from, _) = state.castling.squares(player, is_short);
+            if chess960 {
+                (king_from, rook_from, None)
+            } else {
+                (king_from, king_to, None)
+            }
         }
         Move::PawnPromote {
             prev_pos,
@@ -104,14 +118,407 @@ fn move_engine_to_uci(player: Player, mov: Move) -> UciMove {
     }
 }
 
+/// an invalid fen is an error, leaving the current position as it is. moves that cannot be
+/// played are reported and dropped.
+fn set_position(
+    engine: &mut ChessEngine,
+    variant: Variant,
+    fen: Option<&str>,
+    moves: Vec<UciMove>,
+) -> Result<GameState, &'static str> {
+    let mut game_state = match fen {
+        None => GameState::new_variant(variant, &engine.zoborist_state),
+        Some(fen) => GameState::new_from_fen_with_variant(fen, variant, &engine.zoborist_state)?,
+    };
+    engine.clear_move_history_threefold_repetition();
+    for UciMove {
+        from,
+        to,
+        promotion,
+    } in moves
+    {
+        let promoted_to_piece = promotion.map(piece_uci_to_engine);
+        let result = match (square_uci_to_engine(from), square_uci_to_engine(to)) {
+            (Some(from), Some(to)) => engine
+                .make_move_raw_parts(&mut game_state, from, to, promoted_to_piece)
+                .map_err(|err| err.to_string()),
+            _ => Err("square off the board".to_owned()),
+        };
+        // the moves after a rejected one were meant for another position, so stop here
+        if let Err(err) = result {
+            info_string(format!("ignoring move {}{} and the moves after it: {}", from, to, err));
+            break;
+        }
+    }
+    Ok(game_state)
+}
+
+/// vampirc-uci only accepts plain six-field fens with KQkq castling rights. chess960
+/// positions in Shredder-FEN (e.g. `HFhf`) and three-check fens with a check count field
+/// end up as unknown messages and are parsed here instead.
+fn parse_fen_position(msg: &str) -> Option<(String, Vec<UciMove>)> {
+    let mut tokens = msg.split_whitespace().peekable();
+    if tokens.next()? != "position" || tokens.next()? != "fen" {
+        return None;
+    }
+    let mut fen = Vec::new();
+    while let Some(token) = tokens.next_if(|&token| token != "moves") {
+        fen.push(token);
+    }
+    if fen.len() != 6 && fen.len() != 7 {
+        return None;
+    }
+
+    let parse_square = |chars: &[char]| -> Option<UciSquare> {
+        let file = *chars.first().filter(|c| ('a'..='h').contains(c))?;
+        let rank = chars.get(1)?.to_digit(10).filter(|r| (1..=8).contains(r))?;
+        Some(UciSquare {
+            file,
+            rank: rank as u8,
+        })
+    };
+    let mut moves = Vec::new();
+    if let Some(token) = tokens.next() {
+        if token != "moves" {
+            return None;
+        }
+        for token in tokens {
+            let chars: Vec<char> = token.chars().collect();
+            let promotion = match chars.get(4) {
+                Some(&c) => Some(piece_engine_to_uci(Piece::try_from(c).ok()?)),
+                None => None,
+            };
+            moves.push(UciMove {
+                from: parse_square(chars.get(..2)?)?,
+                to: parse_square(chars.get(2..4)?)?,
+                promotion,
+            });
+        }
+    }
+    Some((fen.join(" "), moves))
+}
+
+/// the clock of `player`. pondering is not a time control of its own, `go ponder` searches
+/// on the clock it is given.
+fn time_control_uci_to_engine(
+    time_control: Option<&UciTimeControl>,
+    player: Player,
+) -> Option<TimeControl> {
+    // the uci parser gives chrono durations
+    let to_std = |time: &vampirc_uci::Duration| time.to_std().unwrap_or_default();
+    match time_control? {
+        UciTimeControl::MoveTime(time) => Some(TimeControl::MoveTime(to_std(time))),
+        UciTimeControl::TimeLeft {
+            white_time,
+            black_time,
+            white_increment,
+            black_increment,
+            moves_to_go,
+        } => {
+            let (time, increment) = match player {
+                Player::White => (white_time.as_ref(), white_increment.as_ref()),
+                Player::Black => (black_time.as_ref(), black_increment.as_ref()),
+            };
+            Some(TimeControl::Clock {
+                time: to_std(time?),
+                increment: increment.map_or(Duration::ZERO, to_std),
+                moves_to_go: moves_to_go.map(u32::from),
+            })
+        }
+        UciTimeControl::Ponder => None,
+        UciTimeControl::Infinite => Some(TimeControl::Infinite),
+    }
+}
+
+fn info_string(text: String) {
+    println!(
+        "{}",
+        UciMessage::Info(vec![UciInfoAttribute::Any("string".to_owned(), text)])
+    );
+}
+
+/// what a search thread needs to know besides the engine
+struct SearchParams {
+    state: GameState,
+    limits: SearchLimits,
+    chess960: bool,
+}
+
+struct RunningSearch {
+    handle: JoinHandle<()>,
+    stop_signal: StopSignal,
+    time_manager: Arc<TimeManager>,
+}
+
+/// stops a running search and waits for its bestmove
+fn finish_search(search: &mut Option<RunningSearch>) {
+    if let Some(running) = search.take() {
+        running.stop_signal.stop();
+        // the panic message is already on stderr. the engine is reset by the next search.
+        if running.handle.join().is_err() {
+            info_string("the search failed".to_owned());
+        }
+    }
+}
+
+/// the engine, even if a failed search left its lock poisoned
+fn lock_engine(engine: &Mutex<ChessEngine>) -> MutexGuard<'_, ChessEngine> {
+    engine.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// waits for stop while pondering and in infinite mode
+fn wait_for_stop(time_manager: &TimeManager, stop_signal: &StopSignal) {
+    while time_manager.waits_for_stop() && !stop_signal.is_stopped() {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// prints info after each depth, and whenever the best root move changes in the middle of
+/// a depth
+struct InfoReporter {
+    state: GameState,
+    chess960: bool,
+    search_start: Instant,
+    iteration_start: Instant,
+}
+
+impl InfoReporter {
+    fn uci_pv(&self, engine: &ChessEngine, pv: &[Move]) -> Vec<UciMove> {
+        let mut state = self.state.clone();
+        pv.iter()
+            .map(|&mov| {
+                let uci_move = move_engine_to_uci(&state, mov, self.chess960);
+                state.make_move(mov, &engine.zoborist_state);
+                uci_move
+            })
+            .collect()
+    }
+
+    // scores are from white's side, uci wants them from the engine's
+    fn to_uci_score(&self, score: i32) -> i32 {
+        match self.state.player {
+            Player::White => score / PHASE_MAX,
+            Player::Black => -score / PHASE_MAX,
+        }
+    }
+}
+
+impl SearchObserver for InfoReporter {
+    fn iteration_started(&mut self, engine: &mut ChessEngine, depth: usize, best: Option<Move>) {
+        println!(
+            "{}",
+            UciMessage::Info(vec![UciInfoAttribute::Any(
+                "current depth".to_owned(),
+                format!("{}", depth)
+            )])
+        );
+        self.iteration_start = Instant::now();
+        // a root move replacing the best move of the last depth is reported before this
+        // depth is finished
+        let listener_state = self.state.clone();
+        let chess960 = self.chess960;
+        let search_start = self.search_start;
+        let mut last_reported = best;
+        engine.set_root_listener(Some(Box::new(move |mov, score| {
+            if last_reported.is_none_or(|last| last == mov) {
+                return;
+            }
+            last_reported = Some(mov);
+            let elapsed = search_start.elapsed();
+            println!(
+                "{}",
+                UciMessage::Info(vec![
+                    UciInfoAttribute::Depth(depth as u8),
+                    UciInfoAttribute::from_centipawns(score / PHASE_MAX),
+                    UciInfoAttribute::Time(vampirc_uci::Duration::milliseconds(
+                        elapsed.as_millis() as i64
+                    )),
+                    UciInfoAttribute::Pv(vec![move_engine_to_uci(&listener_state, mov, chess960)]),
+                ])
+            );
+        })));
+    }
+
+    fn iteration_finished(&mut self, engine: &ChessEngine, iteration: &Iteration) {
+        let (score, pv) = &iteration.lines[0];
+        println!(
+            "{}",
+            UciMessage::Info(vec![UciInfoAttribute::Any(
+                "iterative search finished".to_owned(),
+                format!(
+                    "depth {}, score {}, {}, {}ms",
+                    iteration.depth,
+                    score,
+                    pv[0],
+                    self.iteration_start.elapsed().as_millis()
+                )
+            )])
+        );
+        println!(
+            "{}",
+            UciMessage::Info(vec![
+                UciInfoAttribute::Depth(iteration.depth as u8),
+                UciInfoAttribute::from_centipawns(self.to_uci_score(*score)),
+                UciInfoAttribute::Nodes(engine.nodes()),
+                UciInfoAttribute::Time(vampirc_uci::Duration::milliseconds(
+                    self.search_start.elapsed().as_millis() as i64
+                )),
+                UciInfoAttribute::TbHits(engine.tb_hits()),
+                UciInfoAttribute::Pv(self.uci_pv(engine, pv)),
+            ])
+        );
+        if iteration.lines.len() > 1 {
+            for (k, (line_score, line_pv)) in iteration.lines.iter().enumerate() {
+                println!(
+                    "{}",
+                    UciMessage::Info(vec![
+                        UciInfoAttribute::MultiPv(k as u16 + 1),
+                        UciInfoAttribute::Depth(iteration.depth as u8),
+                        UciInfoAttribute::from_centipawns(self.to_uci_score(*line_score)),
+                        UciInfoAttribute::Pv(self.uci_pv(engine, line_pv)),
+                    ])
+                );
+            }
+        }
+    }
+}
+
+/// searches for as long as the limits and the time manager allow, reporting on the way.
+/// bestmove is sent at the end, but not before stop while pondering or analysing.
+fn search_position(engine: &mut ChessEngine, params: SearchParams, time_manager: &TimeManager) {
+    let SearchParams {
+        state,
+        limits,
+        chess960,
+    } = params;
+    let stop_signal = engine.stop_signal();
+    let mut reporter = InfoReporter {
+        state: state.clone(),
+        chess960,
+        search_start: Instant::now() - time_manager.elapsed(),
+        iteration_start: Instant::now(),
+    };
+    let Some((score, pv)) = engine.iterative_search(&state, &limits, time_manager, &mut reporter)
+    else {
+        // mate or stalemate
+        info_string("no legal moves".to_owned());
+        wait_for_stop(time_manager, &stop_signal);
+        println!("bestmove 0000");
+        io::stdout().flush().unwrap();
+        return;
+    };
+    println!(
+        "{}",
+        UciMessage::Info(vec![UciInfoAttribute::Any(
+            "best score".to_owned(),
+            format!("{}", score)
+        )])
+    );
+    println!(
+        "{}",
+        UciMessage::Info(vec![UciInfoAttribute::Any(
+            "best move".to_owned(),
+            format!("{}", pv[0])
+        )])
+    );
+    println!(
+        "{}",
+        UciMessage::Info(vec![UciInfoAttribute::Any(
+            "time".to_owned(),
+            format!("{} ms", time_manager.elapsed().as_millis())
+        )])
+    );
+
+    wait_for_stop(time_manager, &stop_signal);
+    let mut after_best = state.clone();
+    after_best.make_move(pv[0], &engine.zoborist_state);
+    println!(
+        "{}",
+        UciMessage::BestMove {
+            best_move: move_engine_to_uci(&state, pv[0], chess960),
+            ponder: pv
+                .get(1)
+                .map(|&reply| move_engine_to_uci(&after_best, reply, chess960)),
+        }
+    );
+    engine.print_debug();
+    io::stdout().flush().unwrap();
+}
+
 const NAME: &'static str = "loglogn-bot";
 const AUTHOR: &'static str = "loglogn";
 
+// the evaluator can be picked on the command line, e.g. `chess-engine-uci --evaluator material`
+fn evaluator_from_args() -> Box<dyn Evaluator> {
+    let args: Vec<String> = std::env::args().collect();
+    let name = args
+        .iter()
+        .position(|arg| arg == "--evaluator")
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+        .unwrap_or("pesto");
+    evaluator_from_name(name).unwrap_or_else(|| {
+        eprintln!(
+            "unknown evaluator {}, expected one of {}",
+            name,
+            EVALUATOR_NAMES.join(", ")
+        );
+        std::process::exit(1);
+    })
+}
+
 fn main() {
     let mut game_state = GameState::default();
-    let mut engine = ChessEngine::new(16, 40, 13);
+    let shared_engine = Arc::new(Mutex::new(ChessEngine::with_evaluator(
+        16,
+        40,
+        13,
+        evaluator_from_args(),
+    )));
+    let mut search: Option<RunningSearch> = None;
+    let mut book: Option<Book> = None;
+    let mut options = Options::default();
+    let mut variant = Variant::Standard;
     for line in io::stdin().lock().lines() {
-        let msg: UciMessage = parse_one(&line.unwrap());
+        let line = match line {
+            Ok(line) => line,
+            // the line has been read, so the next one can still be
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                info_string("ignoring a line that is not utf-8".to_owned());
+                continue;
+            }
+            Err(_) => break,
+        };
+        // the parser panics on some malformed lines, e.g. `go ` with a trailing space
+        let msg: UciMessage = panic::catch_unwind(|| parse_one(line.trim()))
+            .unwrap_or_else(|_| UciMessage::Unknown(line.trim().to_owned(), None));
+
+        // these are answered while a search is running
+        match msg {
+            UciMessage::IsReady => {
+                println!("{}", UciMessage::ReadyOk);
+                continue;
+            }
+            UciMessage::Stop => {
+                finish_search(&mut search);
+                continue;
+            }
+            UciMessage::PonderHit => {
+                match &search {
+                    Some(running) if running.time_manager.is_pondering() => {
+                        running.time_manager.ponderhit();
+                        running.stop_signal.set_deadline(running.time_manager.deadline());
+                    }
+                    _ => log_unnormalized_message(&msg),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        // anything else waits for the search to end. the gui is supposed to send stop
+        // first, so a search still running is stopped.
+        finish_search(&mut search);
+        let mut engine = lock_engine(&shared_engine);
 
         match msg {
             UciMessage::Uci => {
@@ -122,155 +529,186 @@ fn main() {
                         author: Some(AUTHOR.to_owned())
                     }
                 );
+                for declaration in options.declarations() {
+                    println!("{}", UciMessage::Option(declaration.clone()));
+                }
                 println!("{}", UciMessage::UciOk);
             }
             UciMessage::Debug(_) => log_unnormalized_message(&msg),
-            UciMessage::IsReady => println!("{}", UciMessage::ReadyOk),
             UciMessage::Register { .. } => log_unnormalized_message(&msg),
             UciMessage::Position {
                 startpos,
                 fen,
                 moves,
             } => {
-                engine.clear_move_history_threefold_repetition();
-                game_state = if startpos {
-                    GameState::new_with_hash(&engine.zoborist_state)
-                } else {
-                    GameState::new_from_fen(fen.unwrap().as_str(), &engine.zoborist_state).unwrap()
+                let fen = if startpos { None } else { fen.map(|fen| fen.0) };
+                match set_position(&mut engine, variant, fen.as_deref(), moves) {
+                    Ok(new_state) => game_state = new_state,
+                    Err(err) => info_string(format!("keeping the last position, invalid fen: {}", err)),
+                }
+            }
+            UciMessage::SetOption { ref name, ref value } => {
+                let name = match options.set(name, value.as_deref()) {
+                    Ok(name) => name,
+                    Err(err) => {
+                        info_string(err);
+                        io::stdout().flush().unwrap();
+                        continue;
+                    }
                 };
-                for UciMove {
-                    from,
-                    to,
-                    promotion,
-                } in moves
-                {
-                    let to_u8 = |s: UciSquare| canonical_to_pos(&format!("{}{}", s.file, s.rank));
-                    let promoted_to_piece = promotion.map(piece_uci_to_engine);
-                    engine
-                        .make_move_raw_parts(
-                            &mut game_state,
-                            to_u8(from),
-                            to_u8(to),
-                            promoted_to_piece,
-                        )
-                        .unwrap();
+                match name {
+                    "Hash" => {
+                        if let Err(err) = engine.set_hash_size(options.spin("Hash") as usize) {
+                            info_string(format!("cannot set Hash, keeping {} MB: {}", engine.hash_size(), err));
+                        }
+                    }
+                    "Clear Hash" => engine.clear_hash(),
+                    "QuiescenceDepth" => {
+                        let depth = options.spin("QuiescenceDepth") as usize;
+                        if let Err(err) = engine.set_quiescence_depth(depth) {
+                            info_string(format!("cannot set QuiescenceDepth: {}", err));
+                        }
+                    }
+                    "Seed" => {
+                        engine.set_zoborist_seed(options.spin("Seed") as u64);
+                        game_state.rehash(&engine.zoborist_state);
+                    }
+                    "SyzygyPath" => {
+                        engine.set_syzygy_path(options.text("SyzygyPath"));
+                        info_string(format!(
+                            "found {} tablebase files, up to {} pieces",
+                            engine.tablebases().num_tables(),
+                            engine.tablebases().max_pieces()
+                        ));
+                    }
+                    "UCI_Variant" => {
+                        variant = Variant::from_name(options.text("UCI_Variant"))
+                            .expect("the option only offers known variants");
+                        engine.new_game();
+                        game_state = GameState::new_variant(variant, &engine.zoborist_state);
+                    }
+                    "BookFile" => {
+                        let path = options.text("BookFile");
+                        book = match Book::open(path) {
+                            Ok(new_book) => {
+                                info_string(format!("loaded book with {} entries", new_book.len()));
+                                Some(new_book)
+                            }
+                            Err(err) => {
+                                if !path.is_empty() {
+                                    info_string(format!("cannot load book {}: {}", path, err));
+                                }
+                                None
+                            }
+                        };
+                    }
+                    // read when they are needed
+                    _ => {}
                 }
             }
-            UciMessage::SetOption { .. } => log_unnormalized_message(&msg),
-            UciMessage::UciNewGame => log_unnormalized_message(&msg),
-            UciMessage::Stop => log_unnormalized_message(&msg),
-            UciMessage::PonderHit => log_unnormalized_message(&msg),
+            UciMessage::UciNewGame => {
+                engine.new_game();
+                game_state = GameState::new_variant(variant, &engine.zoborist_state);
+            }
+            UciMessage::IsReady | UciMessage::Stop | UciMessage::PonderHit => unreachable!(),
             UciMessage::Quit => break,
             UciMessage::Go {
-                time_control: _,
+                time_control,
                 search_control,
             } => {
-                // let max_duration = Duration::from_secs(3);
-                // thread::scope(|s|{
-                //     let handle = s.spawn(|| {
-                //         for i in 1..10 {
-                //             // rx.send((i,engine.solve(&game_state)));
-                //             println!("depth: {}", i);
-                //             engine.solve(&game_state, i);
-                //             engine.get_result();
-                //             println!();
-                //         }
-                //     });
-                //     thread::sleep(max_duration);
-                //     let mov = handle.join();
-                // });
-                let start = SystemTime::now();
-
-                let depth = (|| {
-                    let search_control = search_control?;
-                    let depth = search_control.depth?;
-                    Some(depth)
-                })()
-                .unwrap_or(10);
-                let mut mov = Move::Castle { is_short: false };
-                let mut score = 0;
-                for i in ((2 - (depth as usize % 2))..=(depth as usize)).step_by(2) {
+                let chess960 = options.check("UCI_Chess960");
+                // the parser drops `ponder` when a clock is given as well
+                let pondering = time_control == Some(UciTimeControl::Ponder)
+                    || line
+                        .split_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case("ponder"));
+                // book moves are played without searching, except when analysing. a book
+                // move cannot be sent before the ponderhit, so search then as well.
+                let book_selection = if options.check("BestBookMove") {
+                    BookSelection::Best
+                } else {
+                    BookSelection::WeightedRandom
+                };
+                let book_move = book
+                    .as_ref()
+                    .filter(|_| {
+                        options.check("OwnBook") && !options.check("UCI_AnalyseMode") && !pondering
+                    })
+                    .and_then(|book| book.probe(&game_state, book_selection));
+                if let Some(mov) = book_move {
                     println!(
                         "{}",
                         UciMessage::Info(vec![UciInfoAttribute::Any(
-                            "current depth".to_owned(),
-                            format!("{}", i)
+                            "string".to_owned(),
+                            format!("book move {}", mov)
                         )])
                     );
-                    let start = SystemTime::now();
-                    score = engine.solve(&game_state, i);
-                    mov = engine.get_best_calculated_move(game_state.player).unwrap();
                     println!(
                         "{}",
-                        UciMessage::Info(vec![UciInfoAttribute::Any(
-                            "iterative search finished".to_owned(),
-                            format!(
-                                "depth {}, score {}, {}, {}ms",
-                                i,
-                                score,
-                                mov,
-                                SystemTime::now().duration_since(start).unwrap().as_millis()
-                            )
-                        )])
+                        UciMessage::BestMove {
+                            best_move: move_engine_to_uci(&game_state, mov, chess960),
+                            ponder: None
+                        }
                     );
-
-                    engine.lift_killer_moves(2);
+                    io::stdout().flush().unwrap();
+                    continue;
                 }
-                println!(
-                    "{}",
-                    UciMessage::Info(vec![UciInfoAttribute::Any(
-                        "best score".to_owned(),
-                        format!("{}", score)
-                    )])
-                );
-                // let mov = engine.get_best_calculated_move(game_state.player).unwrap();
-                println!(
-                    "{}",
-                    UciMessage::Info(vec![UciInfoAttribute::Any(
-                        "best move".to_owned(),
-                        format!("{}", mov,)
-                    )])
-                );
-                println!(
-                    "{}",
-                    UciMessage::Info(vec![UciInfoAttribute::Any(
-                        "time".to_owned(),
-                        format!(
-                            "{} ms",
-                            SystemTime::now().duration_since(start).unwrap().as_millis()
+
+                // without a depth, the search goes on until the time manager or stop ends it
+                let depth = search_control
+                    .and_then(|search_control| search_control.depth)
+                    .map_or(MAX_SEARCH_DEPTH, |depth| (depth as usize).clamp(1, MAX_SEARCH_DEPTH));
+                let overhead = Duration::from_millis(options.spin("Move Overhead") as u64);
+                let time_manager = Arc::new(TimeManager::new(
+                    time_control_uci_to_engine(time_control.as_ref(), game_state.player),
+                    overhead,
+                    pondering,
+                ));
+                let stop_signal = engine.stop_signal();
+                stop_signal.reset();
+                stop_signal.set_deadline(time_manager.deadline());
+                let params = SearchParams {
+                    state: game_state.clone(),
+                    limits: SearchLimits {
+                        depth,
+                        multi_pv: options.spin("MultiPV") as usize,
+                        skill: if options.check("UCI_LimitStrength") {
+                            Skill::from_elo(options.spin("UCI_Elo") as u32)
+                        } else {
+                            Skill::from_level(options.spin("Skill Level") as u8)
+                        },
+                    },
+                    chess960,
+                };
+                // the search thread takes the engine until it has sent bestmove
+                drop(engine);
+                let shared_engine = shared_engine.clone();
+                let search_time_manager = time_manager.clone();
+                search = Some(RunningSearch {
+                    handle: thread::spawn(move || {
+                        search_position(
+                            &mut lock_engine(&shared_engine),
+                            params,
+                            &search_time_manager,
                         )
-                    )])
-                );
-                println!(
-                    "{}",
-                    UciMessage::BestMove {
-                        best_move: move_engine_to_uci(game_state.player, mov),
-                        ponder: None
-                    }
-                );
-                engine.print_debug()
+                    }),
+                    stop_signal,
+                    time_manager,
+                });
             }
 
             UciMessage::Unknown(msg, _) => {
-                if msg.to_lowercase().starts_with("go perft") {
-                    let depth = str::parse::<usize>(&msg[9..]).unwrap_or(8);
-                    let mut engine = ChessEngine::new(depth, 40, 42);
-                    for depth in 1..=depth {
-                        let start = SystemTime::now();
-                        println!(
-                            "{}",
-                            UciMessage::Info(vec![UciInfoAttribute::Any(
-                                "Perft".to_owned(),
-                                format!(
-                                    "depth {}, value {}, {} ms",
-                                    depth,
-                                    engine.perft(&mut game_state, depth),
-                                    SystemTime::now().duration_since(start).unwrap().as_millis()
-                                )
-                            )])
-                        );
+                if let Some((fen, moves)) = parse_fen_position(&msg) {
+                    match set_position(&mut engine, variant, Some(&fen), moves) {
+                        Ok(new_state) => game_state = new_state,
+                        Err(err) => info_string(format!("keeping the last position, invalid fen: {}", err)),
                     }
-                } else {
+                } else if !dev_commands::run(
+                    &msg,
+                    &mut engine,
+                    &mut game_state,
+                    options.check("UCI_Chess960"),
+                ) {
                     println!(
                         "{}",
                         UciMessage::Info(vec![UciInfoAttribute::Any(
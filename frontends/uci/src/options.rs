@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use chess_engine_core::{Skill, Variant, MAX_SKILL_LEVEL, VARIANT_NAMES};
+use vampirc_uci::UciOptionConfig;
+
+/// a value set with `setoption`, after checking it against the option's declaration
+#[derive(Clone, PartialEq, Debug)]
+enum OptionValue {
+    Check(bool),
+    Spin(i64),
+    // combo and string options
+    Text(String),
+    Button,
+}
+
+/// the options advertised on `uci` and their current values. names are matched without
+/// regard to case, as the protocol asks.
+pub struct Options {
+    declarations: Vec<UciOptionConfig>,
+    values: HashMap<String, OptionValue>,
+}
+
+fn check(name: &str, default: bool) -> UciOptionConfig {
+    UciOptionConfig::Check {
+        name: name.to_owned(),
+        default: Some(default),
+    }
+}
+
+fn spin(name: &str, default: i64, min: i64, max: i64) -> UciOptionConfig {
+    UciOptionConfig::Spin {
+        name: name.to_owned(),
+        default: Some(default),
+        min: Some(min),
+        max: Some(max),
+    }
+}
+
+fn string(name: &str) -> UciOptionConfig {
+    UciOptionConfig::String {
+        name: name.to_owned(),
+        default: Some("<empty>".to_owned()),
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new(vec![
+            spin("Hash", 256, 1, 65536),
+            UciOptionConfig::Button {
+                name: "Clear Hash".to_owned(),
+            },
+            // the search is single threaded
+            spin("Threads", 1, 1, 1),
+            spin("MultiPV", 1, 1, 64),
+            spin("Move Overhead", 30, 0, 5000),
+            check("Ponder", false),
+            check("UCI_AnalyseMode", false),
+            // UCI_Elo takes precedence over Skill Level while UCI_LimitStrength is on
+            spin("Skill Level", MAX_SKILL_LEVEL as i64, 0, MAX_SKILL_LEVEL as i64),
+            check("UCI_LimitStrength", false),
+            spin(
+                "UCI_Elo",
+                Skill::max_elo() as i64,
+                Skill::min_elo() as i64,
+                Skill::max_elo() as i64,
+            ),
+            check("UCI_Chess960", false),
+            UciOptionConfig::Combo {
+                name: "UCI_Variant".to_owned(),
+                default: Some(Variant::Standard.name().to_owned()),
+                var: VARIANT_NAMES.iter().map(|&name| name.to_owned()).collect(),
+            },
+            string("SyzygyPath"),
+            check("OwnBook", false),
+            string("BookFile"),
+            check("BestBookMove", false),
+            spin("QuiescenceDepth", 40, 16, 128),
+            spin("Seed", 13, 0, i64::MAX),
+        ])
+    }
+}
+
+impl Options {
+    pub fn new(declarations: Vec<UciOptionConfig>) -> Self {
+        let values = declarations
+            .iter()
+            .map(|declaration| {
+                let value = match declaration {
+                    UciOptionConfig::Check { default, .. } => OptionValue::Check(default.unwrap_or_default()),
+                    UciOptionConfig::Spin { default, .. } => OptionValue::Spin(default.unwrap_or_default()),
+                    UciOptionConfig::Combo { default, .. } | UciOptionConfig::String { default, .. } => {
+                        OptionValue::Text(default.clone().unwrap_or_default())
+                    }
+                    UciOptionConfig::Button { .. } => OptionValue::Button,
+                };
+                (declaration.get_name().to_lowercase(), value)
+            })
+            .collect();
+        Self {
+            declarations,
+            values,
+        }
+    }
+
+    pub fn declarations(&self) -> &[UciOptionConfig] {
+        &self.declarations
+    }
+
+    /// checks `value` against the declaration of `name` and stores it. returns the name as
+    /// declared, to decide what to do with the new value.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<&str, String> {
+        let declaration = self
+            .declarations
+            .iter()
+            .find(|declaration| declaration.get_name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| format!("unknown option {}", name))?;
+        let value = value.unwrap_or_default().trim();
+        let new_value = match declaration {
+            UciOptionConfig::Check { .. } => match value.to_lowercase().as_str() {
+                "true" => OptionValue::Check(true),
+                "false" => OptionValue::Check(false),
+                _ => return Err(format!("{} expects true or false, not {}", declaration.get_name(), value)),
+            },
+            UciOptionConfig::Spin { min, max, .. } => {
+                let min = min.unwrap_or(i64::MIN);
+                let max = max.unwrap_or(i64::MAX);
+                match value.parse::<i64>() {
+                    Ok(number) if (min..=max).contains(&number) => OptionValue::Spin(number),
+                    _ => {
+                        return Err(format!(
+                            "{} expects a number from {} to {}, not {}",
+                            declaration.get_name(),
+                            min,
+                            max,
+                            value
+                        ))
+                    }
+                }
+            }
+            UciOptionConfig::Combo { var, .. } => match var.iter().find(|var| var.eq_ignore_ascii_case(value)) {
+                Some(var) => OptionValue::Text(var.clone()),
+                None => {
+                    return Err(format!(
+                        "{} expects one of {}, not {}",
+                        declaration.get_name(),
+                        var.join(", "),
+                        value
+                    ))
+                }
+            },
+            UciOptionConfig::String { .. } => OptionValue::Text(value.to_owned()),
+            UciOptionConfig::Button { .. } => OptionValue::Button,
+        };
+        self.values.insert(declaration.get_name().to_lowercase(), new_value);
+        Ok(declaration.get_name())
+    }
+
+    fn value(&self, name: &str) -> &OptionValue {
+        self.values
+            .get(&name.to_lowercase())
+            .unwrap_or_else(|| panic!("option {} is not declared", name))
+    }
+
+    pub fn check(&self, name: &str) -> bool {
+        match self.value(name) {
+            OptionValue::Check(value) => *value,
+            _ => panic!("option {} is not a check", name),
+        }
+    }
+
+    pub fn spin(&self, name: &str) -> i64 {
+        match self.value(name) {
+            OptionValue::Spin(value) => *value,
+            _ => panic!("option {} is not a spin", name),
+        }
+    }
+
+    /// the value of a combo or string option. `<empty>` stands for the empty string.
+    pub fn text(&self, name: &str) -> &str {
+        match self.value(name) {
+            OptionValue::Text(value) if value == "<empty>" => "",
+            OptionValue::Text(value) => value,
+            _ => panic!("option {} is neither a combo nor a string", name),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use proptest::prelude::*;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// commands the gui might send, some of them half right
+const LINES: [&str; 47] = [
+    "uci",
+    "isready",
+    "ucinewgame",
+    "debug on",
+    "position startpos",
+    "position startpos moves e2e4 e7e5 g1f3",
+    "position startpos moves e2e4 e2e4",
+    "position startpos moves e7e5",
+    "position startpos moves a7a8q",
+    "position startpos moves",
+    "position fen",
+    "position fen 8/8/8/8/8/8/8/8 w - - 0 1",
+    "position fen 4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+    "position fen 4k3/8/8/8/8/8/8/4K2R w KQkq - 0 1",
+    "position fen 4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1",
+    "position fen P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+    "position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+    "position fen 7k/6Q1/6K1/8/8/8/8/8 b - - 0 1",
+    "position fen 999999999/8/8/8/8/8/8/8 w - - 0 1",
+    "position fen 4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+    "position fen 1/1/1/1/1/1/1/1 w - - 0 1",
+    "position fen 4k3/8/8/8/8/8/4K3 w - - 0 1",
+    "position fen rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 2",
+    "position fen rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 2",
+    "position fen 4k3/8/8/3P4/8/8/8/4K3 w - e6 0 1",
+    "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq z9 0 1",
+    "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 3+9 0 1 moves e2e4",
+    "go depth 3",
+    "go depth 0",
+    "go depth 255",
+    "go movetime 10",
+    "go movetime 0",
+    "go wtime 0 btime 0",
+    "go wtime 100 btime 100 winc 10 binc 10 movestogo 0",
+    "go nodes 100",
+    "go infinite",
+    "go ponder",
+    "ponderhit",
+    "stop",
+    "setoption name MultiPV value 3",
+    "setoption name Skill Level value 0",
+    "setoption name UCI_Variant value horde",
+    "setoption name Hash value",
+    "d",
+    "eval",
+    "flip",
+    "moves",
+];
+
+// kept whole, as a deeper perft or bench runs for minutes
+const WHOLE_LINES: [&str; 5] = ["go perft 1", "go perft 2", "go perft x", "bench 1", "bench x"];
+
+fn line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => prop::sample::select(&LINES[..]).prop_map(str::to_owned),
+        1 => prop::sample::select(&WHOLE_LINES[..]).prop_map(str::to_owned),
+        // a known command cut short or followed by rubbish
+        2 => (prop::sample::select(&LINES[..]), 0..40usize, "\\PC{0,12}").prop_map(
+            |(line, len, rest)| format!("{}{}", &line[..len.min(line.len())], rest)
+        ),
+        1 => "\\PC{0,40}",
+        1 => prop::collection::vec(
+            prop::sample::select(vec!["go", "position", "fen", "moves", "setoption", "name", "value", "depth", "0", "-1", "e2e4", "startpos", "wtime"]),
+            0..8
+        )
+        .prop_map(|tokens| tokens.join(" ")),
+    ]
+}
+
+/// runs the engine on `lines` and returns what it printed. the engine has to answer the
+/// closing isready and quit cleanly.
+fn run(lines: &[String]) -> String {
+    let mut engine = Command::new(env!("CARGO_BIN_EXE_chess-engine-uci"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = engine.stdin.take().unwrap();
+    for line in lines {
+        writeln!(stdin, "{}", line).unwrap();
+    }
+    writeln!(stdin, "isready\nquit").unwrap();
+    drop(stdin);
+    let output = engine.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "engine failed on {:?}: {}",
+        lines,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("readyok"), "no readyok after {:?}", lines);
+    assert!(!stdout.contains("the search failed"), "search failed on {:?}", lines);
+    stdout
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn survives_any_input(lines in prop::collection::vec(line(), 0..12)) {
+        run(&lines);
+    }
+}
+
+#[test]
+fn survives_invalid_utf8() {
+    let mut engine = Command::new(env!("CARGO_BIN_EXE_chess-engine-uci"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = engine.stdin.take().unwrap();
+    stdin.write_all(b"position \xff\xfe startpos\nisready\nquit\n").unwrap();
+    drop(stdin);
+    let output = engine.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("readyok"));
+}
+
+#[test]
+fn mate_and_stalemate_give_null_move() {
+    for fen in ["7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"] {
+        let stdout = run(&[format!("position fen {}", fen), "go depth 4".to_owned()]);
+        assert!(stdout.contains("bestmove 0000"), "{}", stdout);
+    }
+}
+
+#[test]
+fn invalid_fen_keeps_position() {
+    let stdout = run(&[
+        "position startpos moves e2e4".to_owned(),
+        "position fen 8/8/8/8/8/8/8/8 w - - 0 1".to_owned(),
+        "go depth 2".to_owned(),
+    ]);
+    assert!(stdout.contains("invalid fen"), "{}", stdout);
+    assert!(stdout.lines().any(|line| line.starts_with("bestmove") && !line.contains("0000")));
+}
+
+#[test]
+fn unknown_evaluator_lists_the_names() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chess-engine-uci"))
+        .args(["--evaluator", "nonsense"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("pesto"), "{}", stderr);
+}
@@ -0,0 +1,516 @@
+use chess_engine_core::{
+    evaluator_from_name, ChessEngine, Evaluator, Game, GameState, Iteration, Move, MoveError,
+    Player, SearchLimits, SearchObserver, StopSignal, TimeControl, TimeManager, Variant,
+    EVALUATOR_NAMES, MAX_SEARCH_DEPTH, PHASE_MAX,
+};
+use std::{
+    io::{self, BufRead},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const NAME: &str = "loglogn-bot";
+
+// kept back from the clock for communication delays
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+
+/// variants as xboard names them, with the engine's variant
+const VARIANTS: [(&str, Variant); 4] = [
+    ("normal", Variant::Standard),
+    ("kingofthehill", Variant::KingOfTheHill),
+    ("3check", Variant::ThreeCheck),
+    ("horde", Variant::Horde),
+];
+
+/// how the engine takes part in the game
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Mode {
+    /// the moves are only recorded, e.g. while the gui sets up a game
+    Force,
+    /// the engine plays this side, and thinks whenever it is to move
+    Play(Player),
+    /// the engine searches every position it is given and never moves
+    Analyze,
+}
+
+/// the time control set with `level` or `st`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Level {
+    /// `moves` in `base`, or all moves if 0, with `increment` after each move
+    Conventional {
+        moves: u32,
+        base: Duration,
+        increment: Duration,
+    },
+    /// the same time for each move
+    PerMove(Duration),
+}
+
+impl Default for Level {
+    // what xboard starts with: 40 moves in 5 minutes
+    fn default() -> Self {
+        Level::Conventional {
+            moves: 40,
+            base: Duration::from_secs(300),
+            increment: Duration::ZERO,
+        }
+    }
+}
+
+struct RunningSearch {
+    handle: JoinHandle<()>,
+    stop_signal: StopSignal,
+    // the move is not played after all, e.g. after `force` or `new`
+    cancelled: Arc<AtomicBool>,
+}
+
+/// what a search thread needs to know besides the engine
+struct SearchParams {
+    // the game so far, for the result after the engine's move
+    game: Game,
+    limits: SearchLimits,
+    post: bool,
+    analyze: bool,
+    cancelled: Arc<AtomicBool>,
+    // the move the engine plays, for the main thread to add to the game
+    moves: Sender<Move>,
+}
+
+/// prints the thinking output after each depth: ply, score, time in centiseconds, nodes
+/// and the principal variation
+struct Thinking {
+    state: GameState,
+    search_start: Instant,
+    nodes_at_start: u64,
+}
+
+impl SearchObserver for Thinking {
+    fn iteration_finished(&mut self, engine: &ChessEngine, iteration: &Iteration) {
+        let (score, pv) = &iteration.lines[0];
+        // scores are from white's side, xboard wants them from the engine's
+        let score = match self.state.player {
+            Player::White => score / PHASE_MAX,
+            Player::Black => -score / PHASE_MAX,
+        };
+        let mut state = self.state.clone();
+        let pv: Vec<String> = pv
+            .iter()
+            .map(|&mov| {
+                let text = state.move_to_uci(mov);
+                state.make_move(mov, &engine.zoborist_state);
+                text
+            })
+            .collect();
+        println!(
+            "{} {} {} {} {}",
+            iteration.depth,
+            score,
+            self.search_start.elapsed().as_millis() / 10,
+            engine.nodes() - self.nodes_at_start,
+            pv.join(" ")
+        );
+    }
+}
+
+/// the result to claim once the game is over: mate, stalemate, a loss by a rule of the
+/// variant, or a draw by repetition, the fifty-move rule or insufficient material
+fn game_result(game: &Game) -> Option<String> {
+    game.outcome()
+        .map(|outcome| format!("{} {{{}}}", outcome.pgn_result(), outcome))
+}
+
+/// searches until the limits, the time manager or a stop end it, then plays the move unless
+/// the search was cancelled. analysis only reports.
+fn think(engine: &mut ChessEngine, params: SearchParams, time_manager: &TimeManager) {
+    let SearchParams {
+        game,
+        limits,
+        post,
+        analyze,
+        cancelled,
+        moves,
+    } = params;
+    let state = game.state().clone();
+    let mut thinking = Thinking {
+        state: state.clone(),
+        search_start: Instant::now(),
+        nodes_at_start: engine.nodes(),
+    };
+    let result = if post || analyze {
+        engine.iterative_search(&state, &limits, time_manager, &mut thinking)
+    } else {
+        engine.iterative_search(&state, &limits, time_manager, &mut Quiet)
+    };
+    if analyze || cancelled.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some((_, pv)) = result else {
+        return;
+    };
+    // the main thread adds the move to the game before it reads the reply to it
+    let _ = moves.send(pv[0]);
+    println!("move {}", state.move_to_uci(pv[0]));
+    let mut after = game;
+    if after.play(pv[0]).is_err() {
+        return;
+    }
+    if let Some(result) = game_result(&after) {
+        println!("{}", result);
+    }
+}
+
+struct Quiet;
+
+impl SearchObserver for Quiet {
+    fn iteration_finished(&mut self, _engine: &ChessEngine, _iteration: &Iteration) {}
+}
+
+/// the engine, even if a failed search left its lock poisoned
+fn lock_engine(engine: &Mutex<ChessEngine>) -> MutexGuard<'_, ChessEngine> {
+    engine.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// `5` or `5:30` minutes, as in `level 40 5:30 0`
+fn parse_base_time(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = text.split_once(':').unwrap_or((text, "0"));
+    Some(Duration::from_secs(
+        minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?,
+    ))
+}
+
+fn parse_seconds(text: &str) -> Option<Duration> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+fn parse_centiseconds(text: &str) -> Option<Duration> {
+    // the clock may have run out
+    let centiseconds = text.parse::<i64>().ok()?.max(0);
+    Some(Duration::from_millis(centiseconds as u64 * 10))
+}
+
+struct Xboard {
+    engine: Arc<Mutex<ChessEngine>>,
+    game: Game,
+    variant: Variant,
+    mode: Mode,
+    level: Level,
+    // the engine's clock, as given by `time`
+    clock: Option<Duration>,
+    // set with `sd`
+    depth: usize,
+    post: bool,
+    search: Option<RunningSearch>,
+    engine_moves: Receiver<Move>,
+    engine_moves_sender: Sender<Move>,
+}
+
+impl Xboard {
+    fn new(engine: ChessEngine) -> Self {
+        let (engine_moves_sender, engine_moves) = mpsc::channel();
+        Self {
+            game: Game::new(&engine.zoborist_state),
+            engine: Arc::new(Mutex::new(engine)),
+            variant: Variant::Standard,
+            mode: Mode::Play(Player::Black),
+            level: Level::default(),
+            clock: None,
+            depth: MAX_SEARCH_DEPTH,
+            post: false,
+            search: None,
+            engine_moves,
+            engine_moves_sender,
+        }
+    }
+
+    /// adds the moves the engine has played to the game
+    fn take_engine_moves(&mut self) {
+        while let Ok(mov) = self.engine_moves.try_recv() {
+            if self.game.play(mov).is_err() {
+                println!("Error (internal): the engine played an illegal move");
+            }
+        }
+    }
+
+    /// stops the search. with `cancel`, its move is not played, unless it already was.
+    fn finish_search(&mut self, cancel: bool) {
+        if let Some(running) = self.search.take() {
+            if cancel {
+                running.cancelled.store(true, Ordering::Relaxed);
+            }
+            running.stop_signal.stop();
+            if running.handle.join().is_err() {
+                println!("Error (internal): the search failed");
+            }
+        }
+        self.take_engine_moves();
+    }
+
+    fn time_control(&self) -> TimeControl {
+        match self.level {
+            Level::PerMove(time) => TimeControl::MoveTime(time),
+            Level::Conventional {
+                moves,
+                base,
+                increment,
+            } => {
+                // moves the engine has made since the last time control
+                let played = (self.game.moves().count() / 2) as u32;
+                TimeControl::Clock {
+                    time: self.clock.unwrap_or(base),
+                    increment,
+                    moves_to_go: (moves > 0).then(|| moves - played % moves),
+                }
+            }
+        }
+    }
+
+    /// starts a search if the engine is to move or analysing, and claims the result when
+    /// it is to move in a finished game
+    fn think_if_needed(&mut self) {
+        if self.search.as_ref().is_some_and(|running| running.handle.is_finished()) {
+            self.finish_search(false);
+        }
+        let state = self.game.state().clone();
+        let analyze = self.mode == Mode::Analyze;
+        if self.search.is_some() || !(analyze || self.mode == Mode::Play(state.player)) {
+            return;
+        }
+        if let Some(result) = game_result(&self.game) {
+            if !analyze {
+                println!("{}", result);
+                self.mode = Mode::Force;
+            }
+            return;
+        }
+
+        let time_control = if analyze {
+            TimeControl::Infinite
+        } else {
+            self.time_control()
+        };
+        let time_manager = Arc::new(TimeManager::new(Some(time_control), MOVE_OVERHEAD, false));
+        let mut engine = lock_engine(&self.engine);
+        engine.set_game(&self.game);
+        let stop_signal = engine.stop_signal();
+        stop_signal.reset();
+        stop_signal.set_deadline(time_manager.deadline());
+        drop(engine);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let params = SearchParams {
+            game: self.game.clone(),
+            limits: SearchLimits {
+                depth: self.depth,
+                ..SearchLimits::default()
+            },
+            post: self.post,
+            analyze,
+            cancelled: cancelled.clone(),
+            moves: self.engine_moves_sender.clone(),
+        };
+        let engine = self.engine.clone();
+        self.search = Some(RunningSearch {
+            handle: thread::spawn(move || think(&mut lock_engine(&engine), params, &time_manager)),
+            stop_signal,
+            cancelled,
+        });
+    }
+
+    fn user_move(&mut self, text: &str) {
+        match self.game.play_uci(text) {
+            Ok(_) => {}
+            Err(MoveError::InvalidNotation) => println!("Error (invalid move): {}", text),
+            Err(_) => println!("Illegal move: {}", text),
+        }
+    }
+
+    fn new_game(&mut self, variant: Variant) {
+        let mut engine = lock_engine(&self.engine);
+        engine.new_game();
+        self.game = Game::new_variant(variant, &engine.zoborist_state);
+        self.variant = variant;
+    }
+
+    /// handles one command. false once the engine should exit.
+    fn handle(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+
+        // these do not disturb the search
+        match command {
+            "" | "xboard" | "accepted" | "rejected" | "computer" | "name" | "rating" | "ics"
+            | "random" | "hard" | "easy" | "otim" | "." => return true,
+            "ping" => {
+                println!("pong {}", args);
+                return true;
+            }
+            // move now
+            "?" => {
+                if let Some(running) = &self.search {
+                    running.stop_signal.stop();
+                }
+                return true;
+            }
+            "post" => {
+                self.post = true;
+                return true;
+            }
+            "nopost" => {
+                self.post = false;
+                return true;
+            }
+            "time" => {
+                match parse_centiseconds(args) {
+                    Some(clock) => self.clock = Some(clock),
+                    None => println!("Error (invalid time): {}", line),
+                }
+                return true;
+            }
+            "level" => {
+                let fields: Vec<&str> = args.split_whitespace().collect();
+                let level = match fields[..] {
+                    [moves, base, increment] => Some(Level::Conventional {
+                        moves: moves.parse().unwrap_or(0),
+                        base: parse_base_time(base).unwrap_or_default(),
+                        increment: parse_seconds(increment).unwrap_or_default(),
+                    }),
+                    _ => None,
+                };
+                match level {
+                    Some(level) => {
+                        self.level = level;
+                        self.clock = None;
+                    }
+                    None => println!("Error (invalid level): {}", line),
+                }
+                return true;
+            }
+            "st" => {
+                match parse_seconds(args) {
+                    Some(time) => self.level = Level::PerMove(time),
+                    None => println!("Error (invalid time): {}", line),
+                }
+                return true;
+            }
+            "sd" => {
+                match args.parse::<usize>() {
+                    Ok(depth) => self.depth = depth.clamp(1, MAX_SEARCH_DEPTH),
+                    Err(_) => println!("Error (invalid depth): {}", line),
+                }
+                return true;
+            }
+            _ => {}
+        }
+
+        // anything else cancels the search first, so the move it was thinking about is not
+        // played. analysis is started again for the new position where needed.
+        self.finish_search(true);
+        match command {
+            "quit" => return false,
+            "protover" => {
+                let variants: Vec<&str> = VARIANTS.iter().map(|&(name, _)| name).collect();
+                println!(
+                    "feature ping=1 setboard=1 usermove=1 playother=0 san=0 time=1 draw=0 \
+                     sigint=0 sigterm=0 reuse=1 analyze=1 colors=0 memory=1 myname=\"{}\" \
+                     variants=\"{}\" done=1",
+                    NAME,
+                    variants.join(",")
+                );
+            }
+            "new" => {
+                self.new_game(Variant::Standard);
+                self.mode = Mode::Play(Player::Black);
+                self.level = Level::default();
+                self.clock = None;
+                self.depth = MAX_SEARCH_DEPTH;
+            }
+            "variant" => match VARIANTS.iter().find(|&&(name, _)| name == args) {
+                Some(&(_, variant)) => self.new_game(variant),
+                None => println!("Error (unsupported variant): {}", args),
+            },
+            "setboard" => {
+                let engine = lock_engine(&self.engine);
+                match Game::from_fen_with_variant(args, self.variant, &engine.zoborist_state) {
+                    Ok(game) => self.game = game,
+                    Err(err) => println!("tellusererror Illegal position: {}", err),
+                }
+            }
+            "memory" => match args.parse::<usize>() {
+                Ok(megabytes) => {
+                    if let Err(err) = lock_engine(&self.engine).set_hash_size(megabytes.max(1)) {
+                        println!("Error ({}): {}", err, line);
+                    }
+                }
+                Err(_) => println!("Error (invalid memory): {}", line),
+            },
+            "force" => self.mode = Mode::Force,
+            "go" => self.mode = Mode::Play(self.game.state().player),
+            "usermove" => self.user_move(args),
+            "undo" => {
+                self.game.undo();
+            }
+            "remove" => {
+                self.game.undo();
+                self.game.undo();
+            }
+            "analyze" => self.mode = Mode::Analyze,
+            "exit" => self.mode = Mode::Force,
+            "result" => self.mode = Mode::Force,
+            // a move without `usermove`, for guis that did not accept the feature
+            _ => match self.game.play_uci(line) {
+                Ok(_) => {}
+                Err(MoveError::InvalidNotation) => println!("Error (unknown command): {}", command),
+                Err(_) => println!("Illegal move: {}", line),
+            },
+        }
+        true
+    }
+}
+
+// the evaluator can be picked on the command line, e.g. `chess-engine-xboard --evaluator material`
+fn evaluator_from_args() -> Box<dyn Evaluator> {
+    let args: Vec<String> = std::env::args().collect();
+    let name = args
+        .iter()
+        .position(|arg| arg == "--evaluator")
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+        .unwrap_or("pesto");
+    evaluator_from_name(name).unwrap_or_else(|| {
+        eprintln!(
+            "unknown evaluator {}, expected one of {}",
+            name,
+            EVALUATOR_NAMES.join(", ")
+        );
+        std::process::exit(1);
+    })
+}
+
+fn main() {
+    let mut engine = ChessEngine::with_evaluator(16, 40, 13, evaluator_from_args());
+    engine.set_hash_size(256).expect("cannot allocate the hash table");
+    // anything but protocol lines on stdout confuses the gui
+    engine.set_debug_output(false);
+    let mut xboard = Xboard::new(engine);
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => continue,
+            Err(_) => break,
+        };
+        xboard.take_engine_moves();
+        if !xboard.handle(&line) {
+            break;
+        }
+        xboard.think_if_needed();
+    }
+    xboard.finish_search(true);
+}
@@ -0,0 +1,125 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+/// sends `lines` and reads the engine's output up to the first line starting with `until`,
+/// then quits
+fn run_until(lines: &[&str], until: &str) -> Vec<String> {
+    let mut engine = Command::new(env!("CARGO_BIN_EXE_chess-engine-xboard"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = engine.stdin.take().unwrap();
+    for line in ["xboard", "protover 2"].iter().chain(lines) {
+        writeln!(stdin, "{}", line).unwrap();
+    }
+    let mut output = Vec::new();
+    for line in BufReader::new(engine.stdout.take().unwrap()).lines() {
+        let line = line.unwrap();
+        let done = line.starts_with(until);
+        output.push(line);
+        if done {
+            break;
+        }
+    }
+    writeln!(stdin, "quit").unwrap();
+    assert!(engine.wait().unwrap().success());
+    assert!(
+        output.last().is_some_and(|line| line.starts_with(until)),
+        "no {:?} in {:?}",
+        until,
+        output
+    );
+    output
+}
+
+#[test]
+fn negotiates_features() {
+    let output = run_until(&["ping 1"], "pong 1");
+    let features = output.iter().find(|line| line.starts_with("feature")).unwrap();
+    for feature in ["setboard=1", "usermove=1", "analyze=1", "done=1"] {
+        assert!(features.contains(feature), "{}", features);
+    }
+}
+
+#[test]
+fn answers_a_move() {
+    let output = run_until(&["new", "post", "sd 4", "usermove e2e4"], "move");
+    // thinking output is ply, score, time, nodes and the pv
+    assert!(output.iter().any(|line| line.starts_with("4 ")), "{:?}", output);
+}
+
+#[test]
+fn claims_mate_and_rejects_illegal_moves() {
+    let output = run_until(
+        &[
+            "new",
+            "force",
+            "setboard 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+            "usermove d1d9",
+            "usermove e1e2",
+            "sd 4",
+            "go",
+        ],
+        "1-0",
+    );
+    assert!(output.contains(&"Illegal move: e1e2".to_owned()), "{:?}", output);
+    assert!(output.contains(&"move d1d8".to_owned()), "{:?}", output);
+}
+
+#[test]
+fn undo_restores_the_position() {
+    let output = run_until(
+        &[
+            "new",
+            "force",
+            "usermove e2e4",
+            "usermove e7e5",
+            "remove",
+            "usermove e2e4",
+            "usermove e7e5",
+            "undo",
+            "usermove e7e5",
+            "ping 2",
+        ],
+        "pong 2",
+    );
+    assert!(!output.iter().any(|line| line.starts_with("Illegal")), "{:?}", output);
+}
+
+#[test]
+fn writes_nothing_but_protocol_lines() {
+    let output = run_until(&["new", "post", "sd 5", "usermove e2e4"], "move");
+    let thinking = |line: &str| line.split(' ').next().unwrap().parse::<u32>().is_ok();
+    for line in &output {
+        assert!(
+            ["feature ", "move "]
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+                || thinking(line),
+            "{:?} in {:?}",
+            line,
+            output
+        );
+    }
+}
+
+#[test]
+fn claims_draws() {
+    let output = run_until(
+        &["new", "force", "setboard 8/8/8/4k3/8/8/8/4K2N w - - 0 1", "go"],
+        "1/2-1/2",
+    );
+    assert_eq!(output.last().unwrap(), "1/2-1/2 {draw by insufficient material}");
+    assert!(!output.iter().any(|line| line.starts_with("move")), "{:?}", output);
+
+    // any move but mate runs out the fifty moves
+    let output = run_until(
+        &["new", "force", "setboard 4k3/8/8/8/8/8/8/R3K3 w - - 99 80", "sd 3", "go"],
+        "1/2-1/2",
+    );
+    assert_eq!(output.last().unwrap(), "1/2-1/2 {draw by the fifty-move rule}");
+    assert!(output.iter().any(|line| line.starts_with("move")), "{:?}", output);
+}